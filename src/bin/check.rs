@@ -2,93 +2,103 @@ extern crate tor_stream;
 
 use tor_stream::*;
 
-use std::env::{args, var};
-use std::io::{ErrorKind, Read, Write};
-use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, ToSocketAddrs};
+use std::env::args;
+use std::net::ToSocketAddrs;
+use std::path::Path;
 use std::process::exit;
 
 fn main() {
-    let address = if let Some(address) = args().nth(1).or_else(|| var("TOR_PROXY").ok()) {
-        address
-            .to_socket_addrs()
-            .unwrap_or_else(|e| {
-                eprintln!("Failed to parse socket address: {}", e);
-                exit(1);
-            })
-            .next()
-            .unwrap()
-    } else {
-        SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 9050))
-    };
-
-    println!("Tor address {}", address);
+    let mut discovery = discovery::ProxyDiscovery::new();
+    if let Some(address) = args().nth(1) {
+        let address = match address.strip_prefix("unix:") {
+            Some(path) => ProxyAddr::from(Path::new(path)),
+            None => address
+                .to_socket_addrs()
+                .unwrap_or_else(|e| {
+                    eprintln!("Failed to parse socket address: {}", e);
+                    exit(1);
+                })
+                .next()
+                .unwrap()
+                .into(),
+        };
+        discovery = discovery.proxy(address);
+    }
+    let proxy = discovery.discover().unwrap_or_else(|e| {
+        eprintln!("Failed to find the proxy: {}", e);
+        exit(1);
+    });
+    println!("Tor address {}", proxy);
 
-    check_clear_web(address.clone());
-    check_hidden_service(address);
+    check_clear_web(&proxy.address);
+    check_hidden_service(&proxy.address);
 
     exit(0);
 }
 
-fn check_clear_web(address: SocketAddr) {
-    let mut stream =
-        TorStream::connect_with_address(address, "www.example.com:80").unwrap_or_else(|e| {
-            if e.kind() == ErrorKind::ConnectionRefused {
-                eprintln!("Connection refused, is Tor running?");
-            } else {
-                eprintln!("Failed to connect: {}", e);
-            };
-            exit(1);
-        });
-
-    stream
-        .write_all(b"GET / HTTP/1.1\r\nConnection: Close\r\nHost: www.example.com\r\n\r\n")
-        .expect("Failed to send request");
-
-    let mut buf = String::with_capacity(1633);
-    stream
-        .read_to_string(&mut buf)
-        .expect("Failed to read response");
+fn check_clear_web(address: &ProxyAddr) {
+    let response = client(address)
+        .get("http://www.example.com/")
+        .unwrap_or_else(|e| request_failed(e));
 
-    if buf.starts_with("HTTP/1.1 200 OK") {
+    if response.status() == 200 {
         println!("Clear web check successful");
     } else {
-        eprintln!(
-            "Clear web check failed\nInvalid response; dump ({} bytes):\n--------\n{}\n--------",
-            buf.len(),
-            buf
-        );
+        response_failed("Clear web", &response);
     }
 }
 
-fn check_hidden_service(address: SocketAddr) {
-    let mut stream = TorStream::connect_with_address(
-        address,
-        (
-            "darkfailenbsdla5mal2mxn2uz66od5vtzd5qozslagrfzachha3f3id.onion",
-            80,
-        ),
-    )
-    .unwrap_or_else(|e| {
-        if e.kind() == ErrorKind::ConnectionRefused {
-            eprintln!("Connection refused, is Tor running?");
-        } else {
-            eprintln!("Failed to connect: {}", e);
-        };
-        exit(1);
-    });
+fn check_hidden_service(address: &ProxyAddr) {
+    let onion: onion::OnionAddress =
+        "darkfailenbsdla5mal2mxn2uz66od5vtzd5qozslagrfzachha3f3id.onion"
+            .parse()
+            .expect("Invalid onion address");
 
-    stream
-        .write_all(b"GET / HTTP/1.1\r\nConnection: Close\r\nHost: darkfailenbsdla5mal2mxn2uz66od5vtzd5qozslagrfzachha3f3id.onion\r\n\r\n")
-        .expect("Failed to send request");
+    let response = client(address)
+        .get(&format!("http://{}/", onion))
+        .unwrap_or_else(|e| request_failed(e));
 
-    let mut buf = String::with_capacity(390);
-    stream
-        .read_to_string(&mut buf)
-        .expect("Failed to read response");
-
-    if buf.starts_with("HTTP/1.1 200 OK") {
+    if response.status() == 200 {
         println!("Hidden service check successful");
     } else {
-        eprintln!("Hidden service check failed\nInvalid response; dump ({} bytes):\n--------\n{}\n--------", buf.len(), buf);
+        response_failed("Hidden service", &response);
     }
 }
+
+fn client(address: &ProxyAddr) -> http::Client {
+    http::Client::new()
+        .stream_builder(TorStreamBuilder::new().proxy(address.clone()))
+        .max_redirects(0)
+}
+
+fn response_failed(check: &str, response: &http::Response) {
+    let body = String::from_utf8_lossy(response.body());
+    eprintln!(
+        "{} check failed\nInvalid response {} {}; body ({} bytes):\n--------\n{}\n--------",
+        check,
+        response.status(),
+        response.reason(),
+        response.body().len(),
+        body
+    );
+}
+
+fn request_failed(e: http::HttpError) -> ! {
+    match e {
+        http::HttpError::Tor(e) => connect_failed(e),
+        e => eprintln!("Request failed: {}", e),
+    }
+    exit(1);
+}
+
+fn connect_failed(e: TorError) -> ! {
+    match e {
+        TorError::ProxyUnreachable(e) => {
+            eprintln!("Failed to reach the proxy, is Tor running? {}", e)
+        }
+        TorError::TimedOut | TorError::TtlExpired => eprintln!("Timed out, is Tor bootstrapped?"),
+        e if e.is_onion_service_error() => eprintln!("Failed to reach the onion service: {}", e),
+        e => eprintln!("Failed to connect: {}", e),
+    };
+    exit(1);
+}
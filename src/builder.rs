@@ -0,0 +1,181 @@
+use crate::discovery;
+use crate::socks5::{self, Handshake};
+use crate::{
+    DnsPolicy, Isolation, ProxyAddr, ProxyStream, TargetAddr, ToTargetAddr, TorError, TorStream,
+};
+
+use std::io;
+use std::time::{Duration, Instant};
+
+/// A builder for configuring how a [`TorStream`] is connected.
+///
+/// ```no_run
+/// use tor_stream::{ProxyAddr, TorStreamBuilder};
+/// use std::time::Duration;
+///
+/// let stream = TorStreamBuilder::new()
+///     .proxy("127.0.0.1:9150".parse::<ProxyAddr>().unwrap())
+///     .connect_timeout(Duration::from_secs(30))
+///     .read_timeout(Duration::from_secs(60))
+///     .connect("www.example.com:80")
+///     .expect("Failed to connect");
+/// ```
+///
+/// [`TorStream`]: struct.TorStream.html
+#[derive(Debug, Clone)]
+pub struct TorStreamBuilder {
+    pub(crate) proxy: Option<ProxyAddr>,
+    pub(crate) connect_timeout: Option<Duration>,
+    pub(crate) read_timeout: Option<Duration>,
+    pub(crate) write_timeout: Option<Duration>,
+    pub(crate) isolation: Isolation,
+    pub(crate) dns_policy: DnsPolicy,
+}
+
+impl TorStreamBuilder {
+    /// Creates a builder using the [discovered] proxy, no timeouts and no isolation.
+    ///
+    /// [discovered]: discovery/index.html
+    pub fn new() -> TorStreamBuilder {
+        TorStreamBuilder {
+            proxy: None,
+            connect_timeout: None,
+            read_timeout: None,
+            write_timeout: None,
+            isolation: Isolation::None,
+            dns_policy: DnsPolicy::Permissive,
+        }
+    }
+
+    /// Sets the address of the Tor SOCKS5 proxy, instead of discovering it.
+    ///
+    /// Besides a `SocketAddr`, this accepts the path of a Unix domain socket.
+    pub fn proxy(mut self, proxy: impl Into<ProxyAddr>) -> TorStreamBuilder {
+        self.proxy = Some(proxy.into());
+        self
+    }
+
+    /// Sets a deadline for establishing the stream.
+    ///
+    /// The timeout covers both the connection to the proxy and the
+    /// complete SOCKS5 handshake, which includes building the circuit to the destination.
+    /// If it expires, connecting fails with [`TorError::TimedOut`].
+    ///
+    /// [`TorError::TimedOut`]: enum.TorError.html#variant.TimedOut
+    pub fn connect_timeout(mut self, timeout: Duration) -> TorStreamBuilder {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the read timeout of the connected stream.
+    ///
+    /// See [`TcpStream::set_read_timeout`].
+    ///
+    /// [`TcpStream::set_read_timeout`]: https://doc.rust-lang.org/std/net/struct.TcpStream.html#method.set_read_timeout
+    pub fn read_timeout(mut self, timeout: Duration) -> TorStreamBuilder {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the write timeout of the connected stream.
+    ///
+    /// See [`TcpStream::set_write_timeout`].
+    ///
+    /// [`TcpStream::set_write_timeout`]: https://doc.rust-lang.org/std/net/struct.TcpStream.html#method.set_write_timeout
+    pub fn write_timeout(mut self, timeout: Duration) -> TorStreamBuilder {
+        self.write_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the stream isolation, which determines the circuits the stream may share.
+    ///
+    /// See [`Isolation`] for more information.
+    ///
+    /// [`Isolation`]: enum.Isolation.html
+    pub fn isolation(mut self, isolation: impl Into<Isolation>) -> TorStreamBuilder {
+        self.isolation = isolation.into();
+        self
+    }
+
+    /// Sets how destinations which may leak DNS requests are treated.
+    ///
+    /// See [`DnsPolicy`] for more information.
+    ///
+    /// [`DnsPolicy`]: enum.DnsPolicy.html
+    pub fn dns_policy(mut self, policy: DnsPolicy) -> TorStreamBuilder {
+        self.dns_policy = policy;
+        self
+    }
+
+    /// Connects to a destination address over the Tor network.
+    ///
+    /// Unlike [`TorStream::connect()`], this reports failures as a [`TorError`].
+    ///
+    /// [`TorStream::connect()`]: struct.TorStream.html#method.connect
+    /// [`TorError`]: enum.TorError.html
+    pub fn connect(&self, destination: impl ToTargetAddr) -> Result<TorStream, TorError> {
+        let target = self.target_addr(&destination)?;
+        let (stream, bind_addr) = self.request(socks5::CMD_CONNECT, target.clone())?;
+
+        // The handshake may have left timeouts on the socket
+        stream.set_read_timeout(self.read_timeout)?;
+        stream.set_write_timeout(self.write_timeout)?;
+
+        Ok(TorStream {
+            stream,
+            target,
+            bind_addr,
+        })
+    }
+
+    /// Connects to the proxy and sends a SOCKS5 request with `command`,
+    /// returning the connection and the address of the reply.
+    pub(crate) fn request(
+        &self,
+        command: u8,
+        target: TargetAddr,
+    ) -> Result<(ProxyStream, TargetAddr), TorError> {
+        let deadline = self.connect_timeout.map(|timeout| Instant::now() + timeout);
+
+        let proxy = self.proxy_addr(deadline)?;
+        let timeout = deadline.map(socks5::remaining).transpose()?;
+        let mut stream =
+            ProxyStream::connect(&proxy, timeout).map_err(|e| self.proxy_unreachable(e))?;
+
+        let handshake = Handshake::new(command, target, self.isolation.to_credentials());
+        let reply = socks5::handshake(&mut stream, handshake, deadline)?;
+        Ok((stream, reply))
+    }
+
+    /// Converts the destination, checking it against the DNS policy.
+    pub(crate) fn target_addr(
+        &self,
+        destination: &impl ToTargetAddr,
+    ) -> Result<TargetAddr, TorError> {
+        let target = destination.to_target_addr()?;
+        self.dns_policy.check(destination, &target)?;
+        Ok(target)
+    }
+
+    /// Returns the configured proxy, or discovers it without probing past the deadline.
+    pub(crate) fn proxy_addr(&self, deadline: Option<Instant>) -> Result<ProxyAddr, TorError> {
+        match &self.proxy {
+            Some(proxy) => Ok(proxy.clone()),
+            None => Ok(discovery::default_proxy_until(deadline)?.address),
+        }
+    }
+
+    /// Wraps an error connecting to the proxy, forgetting a discovered proxy so it is looked up again.
+    pub(crate) fn proxy_unreachable(&self, error: io::Error) -> TorError {
+        if self.proxy.is_none() {
+            discovery::reset_default_proxy();
+        }
+        TorError::ProxyUnreachable(error)
+    }
+}
+
+impl Default for TorStreamBuilder {
+    fn default() -> TorStreamBuilder {
+        TorStreamBuilder::new()
+    }
+}
@@ -0,0 +1,157 @@
+use super::{quote, ControlError, TorControl};
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+
+const SERVER_TO_CONTROLLER: &[u8] = b"Tor safe cookie authentication server-to-controller hash";
+const CONTROLLER_TO_SERVER: &[u8] = b"Tor safe cookie authentication controller-to-server hash";
+const COOKIE_LEN: usize = 32;
+const NONCE_LEN: usize = 32;
+
+impl<S: Read + Write> TorControl<S> {
+    /// Authenticates with the first method accepted by Tor, as reported by `PROTOCOLINFO`.
+    ///
+    /// The methods are tried in the order `NULL`, `SAFECOOKIE`, `COOKIE` and `HASHEDPASSWORD`.
+    /// The cookie is read from the file reported by Tor, so the process needs read access to it.
+    /// Password authentication is only used if a password was set with [`set_password()`].
+    ///
+    /// [`set_password()`]: #method.set_password
+    pub fn authenticate_auto(&mut self) -> Result<(), ControlError> {
+        let info = self.protocol_info()?;
+        let accepts = |method: &str| info.auth_methods.iter().any(|m| m == method);
+
+        if accepts("NULL") {
+            return self.authenticate_null();
+        }
+        if let Some(cookie_file) = &info.cookie_file {
+            if accepts("SAFECOOKIE") {
+                return self.authenticate_safecookie(cookie_file);
+            }
+            if accepts("COOKIE") {
+                return self.authenticate_cookie(cookie_file);
+            }
+        }
+        if accepts("HASHEDPASSWORD") {
+            if let Some(password) = self.password.clone() {
+                return self.authenticate_password(&password);
+            }
+            return Err(ControlError::Authentication(
+                "Tor requires a password, but none was set",
+            ));
+        }
+
+        Err(ControlError::Authentication(
+            "no supported authentication method",
+        ))
+    }
+
+    /// Sets the password used by [`authenticate_auto()`] for `HASHEDPASSWORD` authentication.
+    ///
+    /// [`authenticate_auto()`]: #method.authenticate_auto
+    pub fn set_password(&mut self, password: impl Into<String>) {
+        self.password = Some(password.into());
+    }
+
+    /// Authenticates without credentials, which works if Tor has no authentication configured.
+    pub fn authenticate_null(&mut self) -> Result<(), ControlError> {
+        self.send("AUTHENTICATE").map(drop)
+    }
+
+    /// Authenticates with the password configured by `HashedControlPassword`.
+    pub fn authenticate_password(&mut self, password: &str) -> Result<(), ControlError> {
+        self.send(&format!("AUTHENTICATE {}", quote(password)))
+            .map(drop)
+    }
+
+    /// Authenticates by sending the contents of the cookie file.
+    ///
+    /// Prefer [`authenticate_safecookie()`] if Tor supports it,
+    /// because it does not disclose the cookie to a process impersonating Tor.
+    ///
+    /// [`authenticate_safecookie()`]: #method.authenticate_safecookie
+    pub fn authenticate_cookie(
+        &mut self,
+        cookie_file: impl AsRef<Path>,
+    ) -> Result<(), ControlError> {
+        let cookie = read_cookie(cookie_file.as_ref())?;
+        self.send(&format!("AUTHENTICATE {}", hex(&cookie)))
+            .map(drop)
+    }
+
+    /// Authenticates with the `SAFECOOKIE` challenge-response protocol.
+    ///
+    /// Both sides prove knowledge of the cookie with an HMAC-SHA256 over the cookie
+    /// and a nonce from each side, so the cookie itself is never sent.
+    pub fn authenticate_safecookie(
+        &mut self,
+        cookie_file: impl AsRef<Path>,
+    ) -> Result<(), ControlError> {
+        let cookie = read_cookie(cookie_file.as_ref())?;
+
+        let mut client_nonce = [0; NONCE_LEN];
+        getrandom::getrandom(&mut client_nonce).map_err(std::io::Error::from)?;
+
+        let reply = self.send(&format!("AUTHCHALLENGE SAFECOOKIE {}", hex(&client_nonce)))?;
+        let text = reply.message();
+        let pairs = super::reply::parse_key_values(
+            text.strip_prefix("AUTHCHALLENGE ")
+                .ok_or(ControlError::Protocol("invalid AUTHCHALLENGE reply"))?,
+        )?;
+        let value = |key: &str| {
+            pairs
+                .iter()
+                .find(|(k, _)| k == key)
+                .and_then(|(_, value)| unhex(value))
+                .ok_or(ControlError::Protocol("invalid AUTHCHALLENGE reply"))
+        };
+        let server_hash = value("SERVERHASH")?;
+        let server_nonce = value("SERVERNONCE")?;
+
+        let mut message = Vec::with_capacity(COOKIE_LEN + 2 * NONCE_LEN);
+        message.extend_from_slice(&cookie);
+        message.extend_from_slice(&client_nonce);
+        message.extend_from_slice(&server_nonce);
+
+        // Verifying the server hash ensures that the other side knows the cookie as well
+        hmac(SERVER_TO_CONTROLLER, &message)
+            .verify_slice(&server_hash)
+            .map_err(|_| ControlError::Authentication("Tor sent an invalid server hash"))?;
+
+        let client_hash = hmac(CONTROLLER_TO_SERVER, &message).finalize().into_bytes();
+        self.send(&format!("AUTHENTICATE {}", hex(&client_hash)))
+            .map(drop)
+    }
+}
+
+fn hmac(key: &[u8], message: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(message);
+    mac
+}
+
+fn read_cookie(path: &Path) -> Result<Vec<u8>, ControlError> {
+    let cookie = fs::read(path)?;
+    if cookie.len() != COOKIE_LEN {
+        return Err(ControlError::Authentication(
+            "the cookie file must be 32 bytes long",
+        ));
+    }
+    Ok(cookie)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02X}", byte)).collect()
+}
+
+fn unhex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) || !s.is_ascii() {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
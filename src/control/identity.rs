@@ -0,0 +1,111 @@
+use super::{ControlError, Reply, Signal, TorControl};
+
+use std::io::{Read, Write};
+use std::thread;
+use std::time::Duration;
+
+/// The outcome of [`TorControl::new_identity()`].
+///
+/// [`TorControl::new_identity()`]: struct.TorControl.html#method.new_identity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NewIdentity {
+    /// Tor switched to clean circuits immediately.
+    Applied,
+    /// Tor received the request too soon after the previous one
+    /// and will switch to clean circuits after the given delay.
+    RateLimited(Duration),
+}
+
+impl NewIdentity {
+    /// Returns the time until new connections use clean circuits.
+    pub fn delay(&self) -> Duration {
+        match self {
+            NewIdentity::Applied => Duration::from_secs(0),
+            NewIdentity::RateLimited(delay) => *delay,
+        }
+    }
+
+    /// Blocks until new connections use clean circuits.
+    pub fn wait(&self) {
+        let delay = self.delay();
+        if delay > Duration::from_secs(0) {
+            thread::sleep(delay);
+        }
+    }
+}
+
+impl<S: Read + Write> TorControl<S> {
+    /// Requests a new identity with `SIGNAL NEWNYM`.
+    ///
+    /// Afterwards, new connections such as [`TorStream::connect()`] use circuits
+    /// which are not shared with earlier connections, and therefore usually a different exit.
+    /// Tor rate limits this signal, in which case the returned [`NewIdentity`]
+    /// holds the delay until it takes effect. Call [`wait()`] on it before connecting
+    /// to make sure the next connection uses a clean circuit.
+    ///
+    /// To detect rate limiting, the `NOTICE` event is subscribed temporarily
+    /// in addition to the events passed to [`set_events()`].
+    ///
+    /// ```no_run
+    /// use tor_stream::control::{TorControl, TOR_CONTROL};
+    ///
+    /// let mut control = TorControl::connect(*TOR_CONTROL).expect("Failed to connect");
+    /// control.authenticate_auto().expect("Failed to authenticate");
+    /// control.new_identity().expect("Failed to request a new identity").wait();
+    /// ```
+    ///
+    /// [`TorStream::connect()`]: ../struct.TorStream.html#method.connect
+    /// [`NewIdentity`]: enum.NewIdentity.html
+    /// [`wait()`]: enum.NewIdentity.html#method.wait
+    /// [`set_events()`]: #method.set_events
+    pub fn new_identity(&mut self) -> Result<NewIdentity, ControlError> {
+        let subscribed = self.subscribed.clone();
+        let listening = subscribed.iter().any(|event| event == "NOTICE");
+        let mut events: Vec<&str> = subscribed.iter().map(String::as_str).collect();
+        if !listening {
+            events.push("NOTICE");
+            self.set_events(&events)?;
+            events.pop();
+        }
+
+        let result = self.signal(Signal::NewNym).and_then(|_| {
+            // Tor logs the notice while handling the signal,
+            // so it is received before the reply to any later command
+            self.send("GETINFO version")
+        });
+
+        // Restore the subscriptions even if the signal failed,
+        // so notices don't pile up on the connection
+        let restored = if listening {
+            Ok(())
+        } else {
+            self.set_events(&events)
+        };
+
+        let mut delay = None;
+        self.events.retain(|event| {
+            if event.keyword() != "NOTICE" {
+                return true;
+            }
+            if let Some(seconds) = rate_limit_delay(event) {
+                delay = Some(Duration::from_secs(seconds));
+            }
+            listening
+        });
+        result?;
+        restored?;
+
+        Ok(delay.map_or(NewIdentity::Applied, NewIdentity::RateLimited))
+    }
+}
+
+/// Parses the delay from "Rate limiting NEWNYM request: delaying by 8 second(s)".
+fn rate_limit_delay(event: &Reply) -> Option<u64> {
+    event
+        .message()
+        .strip_prefix("NOTICE Rate limiting NEWNYM request: delaying by ")?
+        .split(' ')
+        .next()?
+        .parse()
+        .ok()
+}
@@ -0,0 +1,399 @@
+//! A client for the Tor control protocol.
+//!
+//! The control port allows managing a running Tor instance,
+//! for example to query its status or to change its configuration.
+//! It has to be enabled with the `ControlPort` option in `torrc`.
+//! See the [control specification] for details on the protocol.
+//!
+//! ```no_run
+//! use tor_stream::control::{Signal, TorControl, TOR_CONTROL};
+//!
+//! let mut control = TorControl::connect(*TOR_CONTROL).expect("Failed to connect");
+//! // Authentication is required before any other command
+//! control.authenticate_auto().expect("Failed to authenticate");
+//!
+//! println!("Tor version {}", control.get_info("version").unwrap());
+//! control.signal(Signal::NewNym).unwrap();
+//! ```
+//!
+//! [control specification]: https://spec.torproject.org/control-spec/
+
+mod auth;
+mod identity;
+mod reply;
+
+pub use self::identity::NewIdentity;
+pub use self::reply::{quote, unquote, Reply, ReplyLine};
+
+pub(crate) use self::reply::parse_key_values;
+
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufReader, Write};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+
+lazy_static! {
+    /// The default Tor control port address, `127.0.0.1:9051`.
+    /// The control port is disabled by default and can be enabled with `ControlPort 9051` in `torrc`.
+    pub static ref TOR_CONTROL: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 9051));
+}
+
+/// An error that occurred while talking to the control port.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ControlError {
+    /// An I/O error occurred.
+    Io(io::Error),
+    /// Tor sent a malformed reply.
+    Protocol(&'static str),
+    /// Tor rejected the command with an error reply.
+    Reply(Reply),
+    /// Authentication could not be attempted, or Tor failed to prove its identity.
+    Authentication(&'static str),
+}
+
+impl fmt::Display for ControlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ControlError::Io(e) => e.fmt(f),
+            ControlError::Protocol(message) => write!(f, "control protocol error: {}", message),
+            ControlError::Reply(reply) => write!(f, "command failed: {}", reply),
+            ControlError::Authentication(message) => {
+                write!(f, "authentication failed: {}", message)
+            }
+        }
+    }
+}
+
+impl Error for ControlError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ControlError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ControlError {
+    fn from(error: io::Error) -> ControlError {
+        ControlError::Io(error)
+    }
+}
+
+impl From<ControlError> for io::Error {
+    fn from(error: ControlError) -> io::Error {
+        match error {
+            ControlError::Io(e) => e,
+            ControlError::Protocol(_) => io::Error::new(io::ErrorKind::InvalidData, error),
+            ControlError::Reply(_) => io::Error::other(error),
+            ControlError::Authentication(_) => {
+                io::Error::new(io::ErrorKind::PermissionDenied, error)
+            }
+        }
+    }
+}
+
+/// A signal that can be sent to Tor with [`TorControl::signal()`].
+///
+/// [`TorControl::signal()`]: struct.TorControl.html#method.signal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Signal {
+    /// Reloads the configuration.
+    Reload,
+    /// Shuts down cleanly, waiting for `ShutdownWaitLength` if Tor is a relay.
+    Shutdown,
+    /// Dumps statistics to the log.
+    Dump,
+    /// Switches all open logs to the debug level.
+    Debug,
+    /// Shuts down immediately.
+    Halt,
+    /// Forgets the client-side cached IPs for all hostnames.
+    ClearDnsCache,
+    /// Switches to clean circuits, so new requests don't share circuits with old ones.
+    ///
+    /// [`TorControl::new_identity()`] additionally reports whether Tor delayed the signal.
+    ///
+    /// [`TorControl::new_identity()`]: struct.TorControl.html#method.new_identity
+    NewNym,
+    /// Makes Tor log a heartbeat message.
+    Heartbeat,
+    /// Tells Tor to become dormant.
+    Dormant,
+    /// Tells Tor to stop being dormant.
+    Active,
+}
+
+impl Signal {
+    /// Returns the name of the signal in the control protocol.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Signal::Reload => "RELOAD",
+            Signal::Shutdown => "SHUTDOWN",
+            Signal::Dump => "DUMP",
+            Signal::Debug => "DEBUG",
+            Signal::Halt => "HALT",
+            Signal::ClearDnsCache => "CLEARDNSCACHE",
+            Signal::NewNym => "NEWNYM",
+            Signal::Heartbeat => "HEARTBEAT",
+            Signal::Dormant => "DORMANT",
+            Signal::Active => "ACTIVE",
+        }
+    }
+}
+
+/// The result of a `PROTOCOLINFO` command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtocolInfo {
+    /// The version of the control protocol, which is currently always `1`.
+    pub protocol_version: u32,
+    /// The version of Tor.
+    pub tor_version: String,
+    /// The accepted authentication methods, such as `NULL`, `HASHEDPASSWORD`, `COOKIE` or `SAFECOOKIE`.
+    pub auth_methods: Vec<String>,
+    /// The path of the authentication cookie, if cookie authentication is enabled.
+    pub cookie_file: Option<PathBuf>,
+}
+
+/// A connection to the Tor control port.
+///
+/// Any stream implementing `Read` and `Write` can be used as connection,
+/// such as a `UnixStream` if the control port is a Unix domain socket.
+///
+/// Asynchronous events, which can arrive at any time after subscribing
+/// with [`set_events()`], are queued and can be retrieved with [`next_event()`].
+///
+/// [`set_events()`]: #method.set_events
+/// [`next_event()`]: #method.next_event
+#[derive(Debug)]
+pub struct TorControl<S = TcpStream> {
+    stream: BufReader<S>,
+    events: VecDeque<Reply>,
+    subscribed: Vec<String>,
+    password: Option<String>,
+}
+
+impl TorControl<TcpStream> {
+    /// Connects to the control port at `address`.
+    pub fn connect(address: impl ToSocketAddrs) -> io::Result<TorControl<TcpStream>> {
+        TcpStream::connect(address).map(TorControl::new)
+    }
+}
+
+impl<S: io::Read + Write> TorControl<S> {
+    /// Creates a control connection from an established stream.
+    pub fn new(stream: S) -> TorControl<S> {
+        TorControl {
+            stream: BufReader::new(stream),
+            events: VecDeque::new(),
+            subscribed: Vec::new(),
+            password: None,
+        }
+    }
+
+    /// Sends a raw command line and returns the reply.
+    ///
+    /// Asynchronous events received in the meantime are queued.
+    /// Replies with a status code other than `2xx` are returned as [`ControlError::Reply`].
+    ///
+    /// [`ControlError::Reply`]: enum.ControlError.html#variant.Reply
+    pub fn send(&mut self, command: &str) -> Result<Reply, ControlError> {
+        if command.contains(['\r', '\n']) {
+            return Err(ControlError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "command must not contain line breaks",
+            )));
+        }
+
+        let stream = self.stream.get_mut();
+        stream.write_all(format!("{}\r\n", command).as_bytes())?;
+        stream.flush()?;
+
+        let reply = self.read_reply()?;
+        if reply.is_ok() {
+            Ok(reply)
+        } else {
+            Err(ControlError::Reply(reply))
+        }
+    }
+
+    /// Reads the next reply which is not an event.
+    fn read_reply(&mut self) -> Result<Reply, ControlError> {
+        loop {
+            let reply = Reply::read(&mut self.stream)?;
+            if reply.is_event() {
+                self.events.push_back(reply);
+            } else {
+                return Ok(reply);
+            }
+        }
+    }
+
+    /// Returns the value of a single `GETINFO` key.
+    pub fn get_info(&mut self, key: &str) -> Result<String, ControlError> {
+        let mut values = self.get_info_many(&[key])?;
+        values
+            .remove(key)
+            .ok_or(ControlError::Protocol("missing GETINFO value"))
+    }
+
+    /// Returns the values of multiple `GETINFO` keys.
+    pub fn get_info_many(
+        &mut self,
+        keys: &[&str],
+    ) -> Result<HashMap<String, String>, ControlError> {
+        let reply = self.send(&format!("GETINFO {}", keys.join(" ")))?;
+        Ok(reply
+            .lines()
+            .iter()
+            .filter_map(|line| line.key_value())
+            .map(|(key, value)| (key.to_owned(), value.to_owned()))
+            .collect())
+    }
+
+    /// Returns the values of configuration options.
+    ///
+    /// Options can occur multiple times, such as `SocksPort`.
+    /// Options without a value are returned as `None`.
+    pub fn get_conf(
+        &mut self,
+        keys: &[&str],
+    ) -> Result<Vec<(String, Option<String>)>, ControlError> {
+        let reply = self.send(&format!("GETCONF {}", keys.join(" ")))?;
+        reply
+            .lines()
+            .iter()
+            .map(|line| match line.text().split_once('=') {
+                Some((key, value)) if value.starts_with('"') => {
+                    let (value, _) =
+                        unquote(value).ok_or(ControlError::Protocol("invalid quoted string"))?;
+                    Ok((key.to_owned(), Some(value)))
+                }
+                Some((key, value)) => Ok((key.to_owned(), Some(value.to_owned()))),
+                None => Ok((line.text().to_owned(), None)),
+            })
+            .collect()
+    }
+
+    /// Changes configuration options.
+    ///
+    /// Options set to `None` are reset to their default value.
+    pub fn set_conf(&mut self, options: &[(&str, Option<&str>)]) -> Result<(), ControlError> {
+        let mut command = String::from("SETCONF");
+        for (key, value) in options {
+            command.push(' ');
+            command.push_str(key);
+            if let Some(value) = value {
+                command.push('=');
+                command.push_str(&quote(value));
+            }
+        }
+        self.send(&command).map(drop)
+    }
+
+    /// Sends a signal.
+    pub fn signal(&mut self, signal: Signal) -> Result<(), ControlError> {
+        self.send(&format!("SIGNAL {}", signal.as_str())).map(drop)
+    }
+
+    /// Removes an onion service created by this or, if it was detached, any other control connection.
+    pub fn del_onion(&mut self, service_id: &str) -> Result<(), ControlError> {
+        self.send(&format!("DEL_ONION {}", service_id)).map(drop)
+    }
+
+    /// Queries the protocol version and the accepted authentication methods.
+    ///
+    /// This is one of the few commands allowed before authenticating.
+    pub fn protocol_info(&mut self) -> Result<ProtocolInfo, ControlError> {
+        let reply = self.send("PROTOCOLINFO 1")?;
+        let mut info = ProtocolInfo {
+            protocol_version: 0,
+            tor_version: String::new(),
+            auth_methods: Vec::new(),
+            cookie_file: None,
+        };
+
+        for line in reply.lines() {
+            let (keyword, rest) = line.text().split_once(' ').unwrap_or((line.text(), ""));
+            match keyword {
+                "PROTOCOLINFO" => {
+                    info.protocol_version = rest
+                        .parse()
+                        .map_err(|_| ControlError::Protocol("invalid protocol version"))?
+                }
+                "AUTH" => {
+                    for (key, value) in parse_key_values(rest)? {
+                        match key.as_str() {
+                            "METHODS" => {
+                                info.auth_methods = value.split(',').map(str::to_owned).collect()
+                            }
+                            "COOKIEFILE" => info.cookie_file = Some(PathBuf::from(value)),
+                            _ => {}
+                        }
+                    }
+                }
+                "VERSION" => {
+                    for (key, value) in parse_key_values(rest)? {
+                        if key == "Tor" {
+                            info.tor_version = value;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(info)
+    }
+
+    /// Subscribes to asynchronous events, replacing the previous subscription.
+    ///
+    /// Passing no events unsubscribes from all events.
+    pub fn set_events(&mut self, events: &[&str]) -> Result<(), ControlError> {
+        let mut command = String::from("SETEVENTS");
+        for event in events {
+            command.push(' ');
+            command.push_str(event);
+        }
+        self.send(&command)?;
+        self.subscribed = events.iter().map(|&event| event.to_owned()).collect();
+        Ok(())
+    }
+
+    /// Returns the next asynchronous event, blocking until one arrives.
+    pub fn next_event(&mut self) -> Result<Reply, ControlError> {
+        match self.events.pop_front() {
+            Some(event) => Ok(event),
+            None => {
+                let reply = Reply::read(&mut self.stream)?;
+                if reply.is_event() {
+                    Ok(reply)
+                } else {
+                    Err(ControlError::Protocol("unexpected reply without a command"))
+                }
+            }
+        }
+    }
+
+    /// Removes and returns all events which were received while waiting for replies.
+    pub fn take_events(&mut self) -> Vec<Reply> {
+        self.events.drain(..).collect()
+    }
+
+    /// Gets a reference to the underlying stream.
+    #[inline]
+    pub fn get_ref(&self) -> &S {
+        self.stream.get_ref()
+    }
+
+    /// Gets a mutable reference to the underlying stream.
+    ///
+    /// Reading from or writing to the stream directly may corrupt the connection.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut S {
+        self.stream.get_mut()
+    }
+}
@@ -0,0 +1,272 @@
+use super::ControlError;
+
+use std::fmt;
+use std::io::BufRead;
+
+/// A reply from the Tor control port.
+///
+/// Replies consist of one or more lines sharing a status code.
+/// Asynchronous events are replies with a `6xx` status code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reply {
+    code: u16,
+    lines: Vec<ReplyLine>,
+}
+
+/// A single line of a [`Reply`].
+///
+/// [`Reply`]: struct.Reply.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplyLine {
+    text: String,
+    data: Option<String>,
+}
+
+impl Reply {
+    /// Reads a complete reply.
+    pub(crate) fn read(reader: &mut impl BufRead) -> Result<Reply, ControlError> {
+        let mut code = None;
+        let mut lines = Vec::new();
+
+        loop {
+            let line = read_line(reader)?;
+            if line.len() < 4 || !line.is_char_boundary(3) {
+                return Err(ControlError::Protocol("reply line is too short"));
+            }
+            let (status, rest) = line.split_at(3);
+            let status: u16 = status
+                .parse()
+                .map_err(|_| ControlError::Protocol("invalid status code"))?;
+            if *code.get_or_insert(status) != status {
+                return Err(ControlError::Protocol("status code changed within reply"));
+            }
+
+            // A multibyte character in place of the separator is not a character boundary
+            let text = rest
+                .get(1..)
+                .ok_or(ControlError::Protocol("invalid reply line separator"))?
+                .to_owned();
+            match rest.as_bytes()[0] {
+                b' ' => {
+                    lines.push(ReplyLine { text, data: None });
+                    return Ok(Reply {
+                        code: status,
+                        lines,
+                    });
+                }
+                b'-' => lines.push(ReplyLine { text, data: None }),
+                b'+' => {
+                    let data = read_data(reader)?;
+                    lines.push(ReplyLine {
+                        text,
+                        data: Some(data),
+                    });
+                }
+                _ => return Err(ControlError::Protocol("invalid reply line separator")),
+            }
+        }
+    }
+
+    /// Returns the status code.
+    #[inline]
+    pub fn code(&self) -> u16 {
+        self.code
+    }
+
+    /// Returns whether the status code indicates success (`2xx`).
+    #[inline]
+    pub fn is_ok(&self) -> bool {
+        self.code / 100 == 2
+    }
+
+    /// Returns whether the reply is an asynchronous event (`6xx`).
+    #[inline]
+    pub fn is_event(&self) -> bool {
+        self.code / 100 == 6
+    }
+
+    /// Returns all lines of the reply.
+    #[inline]
+    pub fn lines(&self) -> &[ReplyLine] {
+        &self.lines
+    }
+
+    /// Returns the text of the final line, which is usually a human-readable status message.
+    pub fn message(&self) -> &str {
+        self.lines.last().map_or("", |line| line.text())
+    }
+
+    /// Returns the first word of the reply, which is the event type for events.
+    pub fn keyword(&self) -> &str {
+        self.lines
+            .first()
+            .and_then(|line| line.text().split(' ').next())
+            .unwrap_or("")
+    }
+}
+
+impl fmt::Display for Reply {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.code, self.message())
+    }
+}
+
+impl ReplyLine {
+    /// Returns the text of the line, without the status code.
+    #[inline]
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Returns the data block following the line, if there is one.
+    ///
+    /// Lines of the data are separated by `\n`.
+    #[inline]
+    pub fn data(&self) -> Option<&str> {
+        self.data.as_deref()
+    }
+
+    /// Splits a `key=value` line, using the data block as value if present.
+    pub(crate) fn key_value(&self) -> Option<(&str, &str)> {
+        let (key, value) = self.text.split_once('=')?;
+        Some((key, self.data.as_deref().unwrap_or(value)))
+    }
+}
+
+fn read_line(reader: &mut impl BufRead) -> Result<String, ControlError> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(ControlError::Protocol("connection closed"));
+    }
+    if !line.ends_with("\r\n") {
+        return Err(ControlError::Protocol(
+            "reply line is not terminated by CRLF",
+        ));
+    }
+    line.truncate(line.len() - 2);
+    Ok(line)
+}
+
+/// Reads a data block terminated by a single `.`.
+fn read_data(reader: &mut impl BufRead) -> Result<String, ControlError> {
+    let mut data = String::new();
+    loop {
+        let line = read_line(reader)?;
+        if line == "." {
+            return Ok(data);
+        }
+        if !data.is_empty() {
+            data.push('\n');
+        }
+        data.push_str(line.strip_prefix('.').unwrap_or(&line));
+    }
+}
+
+/// Quotes a string for use as an argument of a control command.
+///
+/// ```
+/// use tor_stream::control::quote;
+///
+/// assert_eq!(quote(r#"say "hi""#), r#""say \"hi\"""#);
+/// ```
+pub fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\r' => quoted.push_str("\\r"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Parses a quoted string at the start of `s`.
+///
+/// Returns the unescaped string and the remainder of `s` after the closing quote.
+///
+/// ```
+/// use tor_stream::control::unquote;
+///
+/// assert_eq!(unquote(r#""C:\\tor" rest"#), Some((r"C:\tor".to_owned(), " rest")));
+/// ```
+pub fn unquote(s: &str) -> Option<(String, &str)> {
+    let mut chars = s.strip_prefix('"')?.char_indices();
+    let mut bytes = Vec::new();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => {
+                let value = String::from_utf8(bytes).ok()?;
+                return Some((value, &s[1 + i + 1..]));
+            }
+            '\\' => match chars.next()?.1 {
+                'n' => bytes.push(b'\n'),
+                'r' => bytes.push(b'\r'),
+                't' => bytes.push(b'\t'),
+                c @ '0'..='7' => {
+                    // Octal escapes have up to three digits
+                    let mut value = c.to_digit(8)?;
+                    for _ in 0..2 {
+                        match chars.clone().next() {
+                            Some((_, c @ '0'..='7')) => {
+                                chars.next();
+                                value = value * 8 + c.to_digit(8)?;
+                            }
+                            _ => break,
+                        }
+                    }
+                    if value > 0xFF {
+                        return None;
+                    }
+                    bytes.push(value as u8);
+                }
+                c => {
+                    let mut buf = [0; 4];
+                    bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+            },
+            c => {
+                let mut buf = [0; 4];
+                bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            }
+        }
+    }
+
+    None
+}
+
+/// Parses space-separated `KEY=VALUE` pairs, where values may be quoted.
+/// Words without `=` are skipped.
+pub(crate) fn parse_key_values(mut s: &str) -> Result<Vec<(String, String)>, ControlError> {
+    let mut pairs = Vec::new();
+
+    loop {
+        s = s.trim_start_matches(' ');
+        if s.is_empty() {
+            return Ok(pairs);
+        }
+
+        let word_end = s.find([' ', '=']).unwrap_or(s.len());
+        let (key, rest) = s.split_at(word_end);
+        match rest.strip_prefix('=') {
+            Some(rest) if rest.starts_with('"') => {
+                let (value, rest) =
+                    unquote(rest).ok_or(ControlError::Protocol("invalid quoted string"))?;
+                pairs.push((key.to_owned(), value));
+                s = rest;
+            }
+            Some(rest) => {
+                let end = rest.find(' ').unwrap_or(rest.len());
+                pairs.push((key.to_owned(), rest[..end].to_owned()));
+                s = &rest[end..];
+            }
+            None => s = rest,
+        }
+    }
+}
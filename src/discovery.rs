@@ -0,0 +1,336 @@
+//! Finding the address of the local Tor SOCKS5 proxy.
+//!
+//! [`TorStream::connect()`] and a [`TorStreamBuilder`] without an explicit proxy
+//! use the first address found by the following chain:
+//!
+//! 1. The `TOR_PROXY` environment variable, as `host:port`, `socks5h://host:port`
+//!    or `unix:/path/to/socket`.
+//! 2. The `ALL_PROXY` or `all_proxy` environment variable, if it is a `socks5h://` or `socks5://` URL.
+//! 3. The first TCP or Unix socket `SocksPort` of the system `torrc`,
+//!    if a SOCKS5 proxy answers there.
+//! 4. `127.0.0.1:9050` of the Tor daemon and `127.0.0.1:9150` of the Tor Browser,
+//!    whichever answers as a SOCKS5 proxy first.
+//! 5. [`TOR_PROXY`] otherwise, so connecting fails with [`TorError::ProxyUnreachable`].
+//!
+//! The result of the default chain is cached after it found a proxy,
+//! until connecting to that proxy fails or [`reset_default_proxy()`] is called.
+//!
+//! ```no_run
+//! use tor_stream::discovery::ProxyDiscovery;
+//!
+//! let proxy = ProxyDiscovery::new().discover().expect("Invalid proxy configuration");
+//! println!("Using the Tor proxy at {} from {}", proxy.address, proxy.source);
+//! ```
+//!
+//! [`TorStream::connect()`]: ../struct.TorStream.html#method.connect
+//! [`TorStreamBuilder`]: ../struct.TorStreamBuilder.html
+//! [`TOR_PROXY`]: ../struct.TOR_PROXY.html
+//! [`TorError::ProxyUnreachable`]: ../enum.TorError.html#variant.ProxyUnreachable
+//! [`reset_default_proxy()`]: fn.reset_default_proxy.html
+
+use crate::torrc::Torrc;
+use crate::{ProxyAddr, ProxyStream, TOR_PROXY};
+
+use std::env;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, SocketAddrV4, ToSocketAddrs};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The locations of the system `torrc` on common platforms.
+const SYSTEM_TORRC: &[&str] = &[
+    "/etc/tor/torrc",
+    "/usr/local/etc/tor/torrc",
+    "/opt/homebrew/etc/tor/torrc",
+];
+
+/// The SOCKS ports of the Tor daemon and the Tor Browser.
+const PROBE_PORTS: &[u16] = &[9050, 9150];
+
+lazy_static! {
+    static ref DEFAULT_PROXY: Mutex<Option<DiscoveredProxy>> = Mutex::new(None);
+}
+
+/// Where a proxy address was found.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ProxySource {
+    /// The address was set explicitly.
+    Override,
+    /// The address was read from the named environment variable.
+    Environment(&'static str),
+    /// The address was read from the `SocksPort` option of the `torrc` at the path.
+    Torrc(PathBuf),
+    /// A SOCKS5 proxy answered at a well-known port.
+    Probe,
+    /// No proxy was found, so the default address is used.
+    Default,
+}
+
+/// A proxy address together with where it was found.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DiscoveredProxy {
+    /// The address of the SOCKS5 proxy.
+    pub address: ProxyAddr,
+    /// Where the address was found.
+    pub source: ProxySource,
+}
+
+/// Configures how the proxy is discovered.
+///
+/// The default chain is described in the [module documentation](index.html).
+#[derive(Debug, Clone)]
+pub struct ProxyDiscovery {
+    proxy: Option<ProxyAddr>,
+    environment: bool,
+    torrc_paths: Vec<PathBuf>,
+    probe_ports: Vec<u16>,
+    probe_timeout: Duration,
+    deadline: Option<Instant>,
+}
+
+impl fmt::Display for ProxySource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProxySource::Override => f.write_str("explicit configuration"),
+            ProxySource::Environment(name) => write!(f, "environment variable {}", name),
+            ProxySource::Torrc(path) => write!(f, "SocksPort in {}", path.display()),
+            ProxySource::Probe => f.write_str("probing well-known ports"),
+            ProxySource::Default => f.write_str("default address"),
+        }
+    }
+}
+
+impl fmt::Display for DiscoveredProxy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (from {})", self.address, self.source)
+    }
+}
+
+impl ProxyDiscovery {
+    /// Creates the default discovery chain.
+    pub fn new() -> ProxyDiscovery {
+        ProxyDiscovery {
+            proxy: None,
+            environment: true,
+            torrc_paths: SYSTEM_TORRC.iter().map(PathBuf::from).collect(),
+            probe_ports: PROBE_PORTS.to_vec(),
+            probe_timeout: Duration::from_millis(500),
+            deadline: None,
+        }
+    }
+
+    /// Sets an address which takes precedence over all other sources.
+    pub fn proxy(mut self, proxy: impl Into<ProxyAddr>) -> ProxyDiscovery {
+        self.proxy = Some(proxy.into());
+        self
+    }
+
+    /// Sets whether environment variables are read.
+    pub fn environment(mut self, environment: bool) -> ProxyDiscovery {
+        self.environment = environment;
+        self
+    }
+
+    /// Sets the `torrc` files to read, replacing the system locations.
+    pub fn torrc_paths(mut self, paths: Vec<PathBuf>) -> ProxyDiscovery {
+        self.torrc_paths = paths;
+        self
+    }
+
+    /// Sets the local ports to probe, replacing 9050 and 9150.
+    pub fn probe_ports(mut self, ports: Vec<u16>) -> ProxyDiscovery {
+        self.probe_ports = ports;
+        self
+    }
+
+    /// Sets how long to wait for a proxy to answer while probing.
+    pub fn probe_timeout(mut self, timeout: Duration) -> ProxyDiscovery {
+        self.probe_timeout = timeout;
+        self
+    }
+
+    /// Sets a deadline for probing, such as the connect deadline of a stream.
+    ///
+    /// Probes wait at most until the deadline, and are skipped once it has passed,
+    /// so the discovery falls back to the default address.
+    pub fn deadline(mut self, deadline: Instant) -> ProxyDiscovery {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Runs the discovery chain.
+    ///
+    /// Fails if an environment variable contains an invalid address.
+    /// Invalid or unreadable `torrc` files are skipped.
+    pub fn discover(&self) -> io::Result<DiscoveredProxy> {
+        if let Some(address) = &self.proxy {
+            return Ok(DiscoveredProxy {
+                address: address.clone(),
+                source: ProxySource::Override,
+            });
+        }
+
+        if self.environment {
+            if let Some(proxy) = from_environment()? {
+                return Ok(proxy);
+            }
+        }
+
+        for path in &self.torrc_paths {
+            let address = match Torrc::load(path).map(|torrc| torrc.socks_proxy()) {
+                Ok(Ok(Some(address))) => address,
+                _ => continue,
+            };
+            if self.probe(&address) {
+                return Ok(DiscoveredProxy {
+                    address,
+                    source: ProxySource::Torrc(path.clone()),
+                });
+            }
+        }
+
+        for &port in &self.probe_ports {
+            let address = ProxyAddr::from(SocketAddrV4::new(Ipv4Addr::LOCALHOST, port));
+            if self.probe(&address) {
+                return Ok(DiscoveredProxy {
+                    address,
+                    source: ProxySource::Probe,
+                });
+            }
+        }
+
+        Ok(DiscoveredProxy {
+            address: ProxyAddr::Tcp(*TOR_PROXY),
+            source: ProxySource::Default,
+        })
+    }
+
+    /// Probes an address for the probe timeout, or the time remaining until the deadline.
+    fn probe(&self, address: &ProxyAddr) -> bool {
+        let timeout = match self.deadline {
+            Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                Some(remaining) if !remaining.is_zero() => remaining.min(self.probe_timeout),
+                _ => return false,
+            },
+            None => self.probe_timeout,
+        };
+        is_socks5_proxy(address, timeout)
+    }
+}
+
+impl Default for ProxyDiscovery {
+    fn default() -> ProxyDiscovery {
+        ProxyDiscovery::new()
+    }
+}
+
+/// Returns the proxy found by the default discovery chain.
+///
+/// A found proxy is cached, while the default address is not,
+/// so a Tor instance started later is still found.
+pub fn default_proxy() -> io::Result<DiscoveredProxy> {
+    default_proxy_until(None)
+}
+
+/// Returns the proxy found by the default discovery chain, probing only until the deadline.
+pub(crate) fn default_proxy_until(deadline: Option<Instant>) -> io::Result<DiscoveredProxy> {
+    if let Some(proxy) = &*DEFAULT_PROXY.lock().unwrap_or_else(|e| e.into_inner()) {
+        return Ok(proxy.clone());
+    }
+
+    // The lock is not held while probing, so other connections are not held up past their deadline
+    let mut discovery = ProxyDiscovery::new();
+    if let Some(deadline) = deadline {
+        discovery = discovery.deadline(deadline);
+    }
+    let proxy = discovery.discover()?;
+    if proxy.source != ProxySource::Default {
+        *DEFAULT_PROXY.lock().unwrap_or_else(|e| e.into_inner()) = Some(proxy.clone());
+    }
+    Ok(proxy)
+}
+
+/// Forgets the cached result of [`default_proxy()`], so the next call runs the discovery chain again.
+///
+/// Connecting through the builder does this when the cached proxy can't be reached,
+/// for example after Tor moved to another port.
+///
+/// [`default_proxy()`]: fn.default_proxy.html
+pub fn reset_default_proxy() {
+    *DEFAULT_PROXY.lock().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+fn from_environment() -> io::Result<Option<DiscoveredProxy>> {
+    for &name in &["TOR_PROXY", "ALL_PROXY", "all_proxy"] {
+        let value = match env::var(name) {
+            Ok(value) if !value.trim().is_empty() => value,
+            _ => continue,
+        };
+        // Only TOR_PROXY is sure to refer to Tor without a scheme
+        let address = match parse_proxy_url(&value, name == "TOR_PROXY") {
+            Some(address) => address,
+            None => continue,
+        };
+        let address = match address.strip_prefix("unix:") {
+            Some("") => return Err(invalid_variable(name, "missing socket path")),
+            Some(path) => ProxyAddr::Unix(PathBuf::from(path)),
+            None => address
+                .to_socket_addrs()
+                .map_err(|e| invalid_variable(name, e))?
+                .next()
+                .map(ProxyAddr::Tcp)
+                .ok_or_else(|| invalid_variable(name, "no address"))?,
+        };
+        return Ok(Some(DiscoveredProxy {
+            address,
+            source: ProxySource::Environment(name),
+        }));
+    }
+    Ok(None)
+}
+
+fn invalid_variable(name: &str, error: impl fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("invalid proxy in {}: {}", name, error),
+    )
+}
+
+/// Extracts `host:port` from a SOCKS5 proxy URL, ignoring credentials and a trailing slash.
+///
+/// A plain value may also be a Unix socket as `unix:/path`, which is returned unchanged.
+///
+/// Returns `None` for other schemes, or for a missing scheme unless `allow_plain` is set.
+fn parse_proxy_url(url: &str, allow_plain: bool) -> Option<&str> {
+    let url = url.trim();
+    let address = match url.split_once("://") {
+        Some((scheme, rest))
+            if scheme.eq_ignore_ascii_case("socks5h") || scheme.eq_ignore_ascii_case("socks5") =>
+        {
+            rest
+        }
+        Some(_) => return None,
+        None if allow_plain && url.starts_with("unix:") => return Some(url),
+        None if allow_plain => url,
+        None => return None,
+    };
+    let address = address.trim_end_matches('/');
+    Some(address.rsplit_once('@').map_or(address, |(_, host)| host))
+}
+
+/// Checks whether a SOCKS5 proxy accepting unauthenticated connections listens at `address`.
+fn is_socks5_proxy(address: &ProxyAddr, timeout: Duration) -> bool {
+    let probe = || -> io::Result<bool> {
+        let mut stream = ProxyStream::connect(address, Some(timeout))?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        stream.write_all(&[5, 1, 0])?;
+        let mut reply = [0; 2];
+        stream.read_exact(&mut reply)?;
+        Ok(reply == [5, 0])
+    };
+    probe().unwrap_or(false)
+}
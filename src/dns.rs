@@ -0,0 +1,72 @@
+use crate::{TargetAddr, ToTargetAddr, TorError};
+
+/// Controls how destinations which may leak DNS requests are treated.
+///
+/// A hostname passed to Tor is resolved by the exit relay, so nothing is visible locally.
+/// A `SocketAddr` on the other hand is usually the result of a local DNS lookup,
+/// for example by `ToSocketAddrs`, which already revealed the hostname to the local resolver.
+/// See [`ToTargetAddr`] for which inputs are considered leak-free.
+///
+/// Checking happens before connecting, so a refused destination never reaches the proxy.
+///
+/// ```no_run
+/// use tor_stream::{DnsPolicy, TorError, TorStreamBuilder};
+/// use std::net::ToSocketAddrs;
+///
+/// let builder = TorStreamBuilder::new().dns_policy(DnsPolicy::Strict);
+///
+/// // The name is resolved by Tor
+/// let stream = builder.connect("www.example.com:80").expect("Failed to connect");
+///
+/// // The name was resolved locally, so the address is refused
+/// let address = "www.example.com:80".to_socket_addrs().unwrap().next().unwrap();
+/// assert!(matches!(builder.connect(address), Err(TorError::LocallyResolved(_))));
+/// ```
+///
+/// [`ToTargetAddr`]: trait.ToTargetAddr.html
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum DnsPolicy {
+    /// All destinations are accepted.
+    #[default]
+    Permissive,
+    /// Destinations which may have been resolved locally are refused with
+    /// [`TorError::LocallyResolved`], while IP addresses given as strings or as
+    /// [`TargetAddr`] are accepted.
+    ///
+    /// [`TorError::LocallyResolved`]: enum.TorError.html#variant.LocallyResolved
+    /// [`TargetAddr`]: enum.TargetAddr.html
+    Strict,
+    /// Like `Strict`, but expects every destination to be a hostname,
+    /// so accepted IP addresses are logged as a warning with the [`log`] crate.
+    ///
+    /// [`log`]: https://docs.rs/log/
+    HostnameOnly,
+}
+
+impl DnsPolicy {
+    /// Checks the `target` which `destination` was converted to.
+    pub(crate) fn check(
+        self,
+        destination: &impl ToTargetAddr,
+        target: &TargetAddr,
+    ) -> Result<(), TorError> {
+        let address = match target {
+            TargetAddr::Ip(address) => *address,
+            TargetAddr::Domain(..) => return Ok(()),
+        };
+        if self == DnsPolicy::Permissive {
+            return Ok(());
+        }
+        if destination.may_be_resolved() {
+            return Err(TorError::LocallyResolved(address));
+        }
+        if self == DnsPolicy::HostnameOnly {
+            log::warn!(
+                "connecting to the IP address {} instead of a hostname over Tor",
+                address
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,239 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+
+/// An error that occurred while connecting through the Tor proxy.
+///
+/// Besides the reply codes defined in [RFC 1928], Tor can report why connecting
+/// to an onion service failed. These extended codes are only sent if the
+/// `SocksPort` is configured with the `ExtendedErrors` flag.
+/// See the [Tor manual] for more information.
+///
+/// A `TorError` can be converted into an `io::Error`. The original error can
+/// be recovered from it with [`TorError::from_io_error()`].
+///
+/// ```no_run
+/// use tor_stream::{TorError, TorStreamBuilder};
+///
+/// match TorStreamBuilder::new().connect("www.example.com:80") {
+///     Ok(_stream) => println!("Connected"),
+///     Err(TorError::ProxyUnreachable(e)) => eprintln!("Is Tor running? {}", e),
+///     Err(TorError::TtlExpired) => eprintln!("The circuit timed out"),
+///     Err(e) => eprintln!("Failed to connect: {}", e),
+/// }
+/// ```
+///
+/// [RFC 1928]: https://tools.ietf.org/html/rfc1928#section-6
+/// [Tor manual]: https://www.torproject.org/docs/tor-manual.html.en
+/// [`TorError::from_io_error()`]: enum.TorError.html#method.from_io_error
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum TorError {
+    /// The connection to the proxy could not be established.
+    ProxyUnreachable(io::Error),
+    /// An I/O error occurred while talking to the proxy.
+    Io(io::Error),
+    /// The connection deadline expired before the stream was established.
+    TimedOut,
+    /// The proxy violated the SOCKS5 protocol.
+    Protocol(&'static str),
+    /// The destination may have been resolved locally, which the [`DnsPolicy`] refuses.
+    ///
+    /// [`DnsPolicy`]: enum.DnsPolicy.html
+    LocallyResolved(SocketAddr),
+    /// The proxy rejected the username/password credentials.
+    AuthenticationFailed,
+    /// `0x01`: General SOCKS server failure.
+    GeneralFailure,
+    /// `0x02`: Connection not allowed by ruleset, e.g. by Tor's exit policy.
+    NotAllowed,
+    /// `0x03`: Network unreachable.
+    NetworkUnreachable,
+    /// `0x04`: Host unreachable.
+    HostUnreachable,
+    /// `0x05`: Connection refused by the destination.
+    ConnectionRefused,
+    /// `0x06`: TTL expired. Tor uses this when building the circuit timed out.
+    TtlExpired,
+    /// `0x07`: Command not supported.
+    CommandNotSupported,
+    /// `0x08`: Address type not supported.
+    AddressTypeNotSupported,
+    /// `0xF0`: The onion service descriptor could not be found.
+    OnionServiceNotFound,
+    /// `0xF1`: The onion service descriptor is invalid.
+    OnionServiceInvalidDescriptor,
+    /// `0xF2`: All introduction points of the onion service failed.
+    OnionServiceIntroFailed,
+    /// `0xF3`: The rendezvous with the onion service failed.
+    OnionServiceRendezvousFailed,
+    /// `0xF4`: The onion service requires client authorization, but none was provided.
+    OnionServiceMissingClientAuth,
+    /// `0xF5`: The client authorization for the onion service was rejected.
+    OnionServiceWrongClientAuth,
+    /// `0xF6`: The onion address is invalid.
+    OnionServiceBadAddress,
+    /// `0xF7`: The introduction to the onion service timed out.
+    OnionServiceIntroTimedOut,
+    /// A reply code not covered by any of the other variants.
+    UnknownReply(u8),
+}
+
+impl TorError {
+    /// Decodes a non-zero SOCKS5 reply code.
+    ///
+    /// Returns `None` for `0x00`, which indicates success.
+    pub fn from_reply_code(code: u8) -> Option<TorError> {
+        Some(match code {
+            0x00 => return None,
+            0x01 => TorError::GeneralFailure,
+            0x02 => TorError::NotAllowed,
+            0x03 => TorError::NetworkUnreachable,
+            0x04 => TorError::HostUnreachable,
+            0x05 => TorError::ConnectionRefused,
+            0x06 => TorError::TtlExpired,
+            0x07 => TorError::CommandNotSupported,
+            0x08 => TorError::AddressTypeNotSupported,
+            0xF0 => TorError::OnionServiceNotFound,
+            0xF1 => TorError::OnionServiceInvalidDescriptor,
+            0xF2 => TorError::OnionServiceIntroFailed,
+            0xF3 => TorError::OnionServiceRendezvousFailed,
+            0xF4 => TorError::OnionServiceMissingClientAuth,
+            0xF5 => TorError::OnionServiceWrongClientAuth,
+            0xF6 => TorError::OnionServiceBadAddress,
+            0xF7 => TorError::OnionServiceIntroTimedOut,
+            code => TorError::UnknownReply(code),
+        })
+    }
+
+    /// Returns the SOCKS5 reply code, if this error was reported by the proxy.
+    pub fn reply_code(&self) -> Option<u8> {
+        Some(match self {
+            TorError::GeneralFailure => 0x01,
+            TorError::NotAllowed => 0x02,
+            TorError::NetworkUnreachable => 0x03,
+            TorError::HostUnreachable => 0x04,
+            TorError::ConnectionRefused => 0x05,
+            TorError::TtlExpired => 0x06,
+            TorError::CommandNotSupported => 0x07,
+            TorError::AddressTypeNotSupported => 0x08,
+            TorError::OnionServiceNotFound => 0xF0,
+            TorError::OnionServiceInvalidDescriptor => 0xF1,
+            TorError::OnionServiceIntroFailed => 0xF2,
+            TorError::OnionServiceRendezvousFailed => 0xF3,
+            TorError::OnionServiceMissingClientAuth => 0xF4,
+            TorError::OnionServiceWrongClientAuth => 0xF5,
+            TorError::OnionServiceBadAddress => 0xF6,
+            TorError::OnionServiceIntroTimedOut => 0xF7,
+            TorError::UnknownReply(code) => *code,
+            _ => return None,
+        })
+    }
+
+    /// Returns whether the error concerns an onion service.
+    pub fn is_onion_service_error(&self) -> bool {
+        matches!(self.reply_code(), Some(0xF0..=0xF7))
+    }
+
+    /// Returns the corresponding `io::ErrorKind`.
+    pub fn kind(&self) -> io::ErrorKind {
+        match self {
+            TorError::ProxyUnreachable(e) | TorError::Io(e) => e.kind(),
+            TorError::TimedOut | TorError::TtlExpired | TorError::OnionServiceIntroTimedOut => {
+                io::ErrorKind::TimedOut
+            }
+            TorError::Protocol(_) => io::ErrorKind::InvalidData,
+            TorError::AuthenticationFailed => io::ErrorKind::PermissionDenied,
+            TorError::NotAllowed => io::ErrorKind::PermissionDenied,
+            TorError::ConnectionRefused => io::ErrorKind::ConnectionRefused,
+            TorError::CommandNotSupported | TorError::AddressTypeNotSupported => {
+                io::ErrorKind::Unsupported
+            }
+            TorError::LocallyResolved(_) | TorError::OnionServiceBadAddress => {
+                io::ErrorKind::InvalidInput
+            }
+            TorError::OnionServiceMissingClientAuth | TorError::OnionServiceWrongClientAuth => {
+                io::ErrorKind::PermissionDenied
+            }
+            _ => io::ErrorKind::Other,
+        }
+    }
+
+    /// Gets the `TorError` wrapped by an `io::Error`, if there is one.
+    ///
+    /// This is useful for inspecting errors returned by functions like [`TorStream::connect()`].
+    ///
+    /// [`TorStream::connect()`]: struct.TorStream.html#method.connect
+    pub fn from_io_error(error: &io::Error) -> Option<&TorError> {
+        error.get_ref().and_then(|e| e.downcast_ref())
+    }
+}
+
+impl fmt::Display for TorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TorError::ProxyUnreachable(e) => write!(f, "failed to connect to the Tor proxy: {}", e),
+            TorError::Io(e) => e.fmt(f),
+            TorError::TimedOut => f.write_str("connection timed out"),
+            TorError::Protocol(message) => write!(f, "SOCKS5 protocol error: {}", message),
+            TorError::LocallyResolved(address) => write!(
+                f,
+                "refusing {}, which may have been resolved outside of Tor",
+                address
+            ),
+            TorError::AuthenticationFailed => f.write_str("SOCKS5 authentication failed"),
+            TorError::GeneralFailure => f.write_str("general SOCKS server failure"),
+            TorError::NotAllowed => f.write_str("connection not allowed by ruleset"),
+            TorError::NetworkUnreachable => f.write_str("network unreachable"),
+            TorError::HostUnreachable => f.write_str("host unreachable"),
+            TorError::ConnectionRefused => f.write_str("connection refused"),
+            TorError::TtlExpired => f.write_str("TTL expired"),
+            TorError::CommandNotSupported => f.write_str("command not supported"),
+            TorError::AddressTypeNotSupported => f.write_str("address type not supported"),
+            TorError::OnionServiceNotFound => f.write_str("onion service descriptor not found"),
+            TorError::OnionServiceInvalidDescriptor => {
+                f.write_str("onion service descriptor is invalid")
+            }
+            TorError::OnionServiceIntroFailed => f.write_str("onion service introduction failed"),
+            TorError::OnionServiceRendezvousFailed => {
+                f.write_str("onion service rendezvous failed")
+            }
+            TorError::OnionServiceMissingClientAuth => {
+                f.write_str("onion service requires client authorization")
+            }
+            TorError::OnionServiceWrongClientAuth => {
+                f.write_str("onion service client authorization was rejected")
+            }
+            TorError::OnionServiceBadAddress => f.write_str("invalid onion service address"),
+            TorError::OnionServiceIntroTimedOut => {
+                f.write_str("onion service introduction timed out")
+            }
+            TorError::UnknownReply(code) => write!(f, "unknown reply code {:#04x}", code),
+        }
+    }
+}
+
+impl Error for TorError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TorError::ProxyUnreachable(e) | TorError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for TorError {
+    fn from(error: io::Error) -> TorError {
+        TorError::Io(error)
+    }
+}
+
+impl From<TorError> for io::Error {
+    fn from(error: TorError) -> io::Error {
+        match error {
+            TorError::Io(e) => e,
+            error => io::Error::new(error.kind(), error),
+        }
+    }
+}
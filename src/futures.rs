@@ -0,0 +1,197 @@
+//! Asynchronous Tor streams for any executor, using the [`futures-io`] traits.
+//!
+//! Since connecting to the proxy depends on the runtime, the connection has to be
+//! established by the caller and is then passed as transport. This works with
+//! the TCP streams of async-std and smol, among others.
+//!
+//! This module requires the `futures-io` feature.
+//!
+//! ```no_run
+//! use futures_io::{AsyncRead, AsyncWrite};
+//! use tor_stream::futures::TorStream;
+//!
+//! # async fn run<S: AsyncRead + AsyncWrite + Unpin>(transport: S) -> std::io::Result<()> {
+//! // `transport` is a connection to the Tor proxy, for example
+//! // `smol::net::TcpStream::connect(*tor_stream::TOR_PROXY).await?`
+//! let stream = TorStream::connect_with_transport(transport, "www.example.com:80").await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`futures-io`]: https://docs.rs/futures-io/
+
+use crate::socks5::{self, Handshake, Step};
+use crate::{TargetAddr, ToTargetAddr, TorError, TorStreamBuilder};
+
+use futures_io::{AsyncRead, AsyncWrite};
+use std::future::poll_fn;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// An asynchronous stream proxied over the Tor network.
+///
+/// The stream implements the `AsyncRead` and `AsyncWrite` traits of [`futures-io`].
+///
+/// [`futures-io`]: https://docs.rs/futures-io/
+#[derive(Debug)]
+pub struct TorStream<S> {
+    stream: S,
+    target: TargetAddr,
+    bind_addr: TargetAddr,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> TorStream<S> {
+    /// Connects to a destination address over the Tor network,
+    /// using an established connection to the Tor proxy as `transport`.
+    pub async fn connect_with_transport(
+        transport: S,
+        destination: impl ToTargetAddr,
+    ) -> io::Result<TorStream<S>> {
+        TorStreamBuilder::new()
+            .connect_with_transport(transport, destination)
+            .await
+            .map_err(io::Error::from)
+    }
+}
+
+impl<S> TorStream<S> {
+    /// Returns the destination address the stream was connected to.
+    #[inline]
+    pub fn target_addr(&self) -> &TargetAddr {
+        &self.target
+    }
+
+    /// Returns the bound address reported by the proxy.
+    #[inline]
+    pub fn bind_addr(&self) -> &TargetAddr {
+        &self.bind_addr
+    }
+
+    /// Gets a reference to the underlying transport.
+    #[inline]
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Gets a mutable reference to the underlying transport.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// Unwraps the `TorStream`.
+    #[inline]
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl TorStreamBuilder {
+    /// Connects to a destination address over the Tor network asynchronously,
+    /// using an established connection to the Tor proxy as `transport`.
+    ///
+    /// Only the isolation settings of the builder are used, because the proxy
+    /// address and timeouts are the responsibility of the transport.
+    ///
+    /// This method requires the `futures-io` feature.
+    pub async fn connect_with_transport<S: AsyncRead + AsyncWrite + Unpin>(
+        &self,
+        mut transport: S,
+        destination: impl ToTargetAddr,
+    ) -> Result<TorStream<S>, TorError> {
+        let target = self.target_addr(&destination)?;
+        let mut handshake = Handshake::new(
+            socks5::CMD_CONNECT,
+            target.clone(),
+            self.isolation.to_credentials(),
+        );
+
+        let mut buf = Vec::new();
+        let bind_addr = loop {
+            match handshake.step(&buf)? {
+                Step::Write(data) => {
+                    write_all(&mut transport, &data).await?;
+                    buf.clear();
+                }
+                Step::Read(len) => {
+                    buf.resize(len, 0);
+                    read_exact(&mut transport, &mut buf).await?;
+                }
+                Step::Done(bind_addr) => break bind_addr,
+            }
+        };
+
+        Ok(TorStream {
+            stream: transport,
+            target,
+            bind_addr,
+        })
+    }
+}
+
+async fn write_all<S: AsyncWrite + Unpin>(stream: &mut S, mut buf: &[u8]) -> io::Result<()> {
+    while !buf.is_empty() {
+        let written = poll_fn(|cx| Pin::new(&mut *stream).poll_write(cx, buf)).await?;
+        if written == 0 {
+            return Err(io::ErrorKind::WriteZero.into());
+        }
+        buf = &buf[written..];
+    }
+    poll_fn(|cx| Pin::new(&mut *stream).poll_flush(cx)).await
+}
+
+async fn read_exact<S: AsyncRead + Unpin>(stream: &mut S, mut buf: &mut [u8]) -> io::Result<()> {
+    while !buf.is_empty() {
+        let read = poll_fn(|cx| Pin::new(&mut *stream).poll_read(cx, buf)).await?;
+        if read == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        buf = &mut buf[read..];
+    }
+    Ok(())
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for TorStream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+
+    fn poll_read_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        bufs: &mut [io::IoSliceMut],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_read_vectored(cx, bufs)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for TorStream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        bufs: &[io::IoSlice],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write_vectored(cx, bufs)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_close(cx)
+    }
+}
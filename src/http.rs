@@ -0,0 +1,804 @@
+//! A minimal blocking HTTP/1.1 client over Tor.
+//!
+//! The [`Client`] sends one request per [`TorStream`] with `Connection: close`,
+//! and reads bodies delimited by `Content-Length`, chunked transfer encoding
+//! or the end of the stream. Redirects are followed, except from the clearnet
+//! to onion services unless allowed with [`Client::onion_redirects()`].
+//!
+//! `https` URLs require the `rustls` or `native-tls` feature. If both are enabled, rustls is used.
+//!
+//! ```no_run
+//! let response = tor_stream::http::get("http://www.example.com/").expect("Request failed");
+//!
+//! println!("{} {}", response.status(), response.reason());
+//! println!("{}", response.text().expect("Invalid UTF-8"));
+//! ```
+//!
+//! [`Client`]: struct.Client.html
+//! [`TorStream`]: ../struct.TorStream.html
+//! [`Client::onion_redirects()`]: struct.Client.html#method.onion_redirects
+
+use crate::{TorError, TorStream, TorStreamBuilder};
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::str;
+
+/// The maximum length of the status line and of each header line.
+const MAX_LINE_LEN: u64 = 16 * 1024;
+const MAX_HEADERS: usize = 128;
+/// The default maximum length of a response body.
+const MAX_BODY_LEN: u64 = 64 * 1024 * 1024;
+
+/// An error that occurred while sending a request or reading the response.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum HttpError {
+    /// The stream could not be established through Tor.
+    Tor(TorError),
+    /// The TLS handshake of an `https` request failed.
+    Tls(Box<dyn Error + Send + Sync>),
+    /// An I/O error occurred while talking to the server.
+    Io(io::Error),
+    /// The URL could not be parsed.
+    InvalidUrl(String),
+    /// The URL uses a scheme other than `http`, or `https` without a TLS feature.
+    UnsupportedScheme(String),
+    /// The request method contains characters other than those of an HTTP token.
+    InvalidMethod(String),
+    /// A header name or value contains invalid characters.
+    InvalidHeader(String),
+    /// The server sent an invalid response.
+    Protocol(&'static str),
+    /// The response body is longer than allowed by [`Client::max_body_len()`].
+    ///
+    /// [`Client::max_body_len()`]: struct.Client.html#method.max_body_len
+    BodyTooLong,
+    /// More redirects than allowed by [`Client::max_redirects()`] were received.
+    ///
+    /// [`Client::max_redirects()`]: struct.Client.html#method.max_redirects
+    TooManyRedirects,
+    /// A clearnet site redirected to this onion service URL,
+    /// which is refused unless allowed with [`Client::onion_redirects()`].
+    ///
+    /// [`Client::onion_redirects()`]: struct.Client.html#method.onion_redirects
+    OnionRedirect(String),
+}
+
+/// A blocking HTTP/1.1 client.
+///
+/// ```no_run
+/// use tor_stream::http::Client;
+/// use tor_stream::{Isolation, TorStreamBuilder};
+/// use std::time::Duration;
+///
+/// let client = Client::new()
+///     .stream_builder(
+///         TorStreamBuilder::new()
+///             .isolation(Isolation::Random)
+///             .connect_timeout(Duration::from_secs(60)),
+///     )
+///     .header("User-Agent", "tor-stream");
+/// let response = client.get("http://www.example.com/").expect("Request failed");
+/// assert!(response.is_success());
+/// ```
+#[derive(Debug, Clone)]
+pub struct Client {
+    stream_builder: TorStreamBuilder,
+    headers: Vec<(String, String)>,
+    max_redirects: usize,
+    onion_redirects: bool,
+    max_body_len: u64,
+}
+
+/// An HTTP request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    method: String,
+    url: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+/// An HTTP response, with the complete body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    url: String,
+    status: u16,
+    reason: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scheme {
+    Http,
+    Https,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Url {
+    scheme: Scheme,
+    host: String,
+    port: u16,
+    /// The path and query, starting with `/`.
+    path: String,
+}
+
+/// A plain or TLS connection to the server.
+trait Connection: Read + Write {}
+
+impl<T: Read + Write> Connection for T {}
+
+/// Sends a `GET` request with a default [`Client`].
+///
+/// [`Client`]: struct.Client.html
+pub fn get(url: &str) -> Result<Response, HttpError> {
+    Client::new().get(url)
+}
+
+impl Client {
+    /// Creates a client which connects like [`TorStream::connect()`] and follows up to 10 redirects.
+    ///
+    /// [`TorStream::connect()`]: ../struct.TorStream.html#method.connect
+    pub fn new() -> Client {
+        Client {
+            stream_builder: TorStreamBuilder::new(),
+            headers: Vec::new(),
+            max_redirects: 10,
+            onion_redirects: false,
+            max_body_len: MAX_BODY_LEN,
+        }
+    }
+
+    /// Sets the builder used to connect each request,
+    /// which configures the proxy, timeouts, isolation and DNS policy.
+    pub fn stream_builder(mut self, builder: TorStreamBuilder) -> Client {
+        self.stream_builder = builder;
+        self
+    }
+
+    /// Adds a header which is sent with every request.
+    ///
+    /// `Host`, `Connection` and `Content-Length` are set by the client and ignored here.
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Client {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Sets the maximum number of redirects to follow. `0` returns redirects as responses.
+    pub fn max_redirects(mut self, max_redirects: usize) -> Client {
+        self.max_redirects = max_redirects;
+        self
+    }
+
+    /// Sets whether redirects from a clearnet site to an onion service are followed.
+    ///
+    /// This is disabled by default, since a clearnet site, or anyone tampering with
+    /// a plain `http` response at the exit relay, could otherwise send the client
+    /// to an onion service of their choice.
+    /// Redirects between onion services and from onion services to the clearnet are always followed.
+    pub fn onion_redirects(mut self, allow: bool) -> Client {
+        self.onion_redirects = allow;
+        self
+    }
+
+    /// Sets the maximum length of a response body in bytes, 64 MiB by default.
+    ///
+    /// Longer responses fail with [`HttpError::BodyTooLong`] instead of being read into memory.
+    ///
+    /// [`HttpError::BodyTooLong`]: enum.HttpError.html#variant.BodyTooLong
+    pub fn max_body_len(mut self, max_len: u64) -> Client {
+        self.max_body_len = max_len;
+        self
+    }
+
+    /// Sends a `GET` request.
+    pub fn get(&self, url: &str) -> Result<Response, HttpError> {
+        self.send(&Request::new("GET", url))
+    }
+
+    /// Sends a `HEAD` request.
+    pub fn head(&self, url: &str) -> Result<Response, HttpError> {
+        self.send(&Request::new("HEAD", url))
+    }
+
+    /// Sends a `POST` request with a body.
+    pub fn post(&self, url: &str, body: impl Into<Vec<u8>>) -> Result<Response, HttpError> {
+        self.send(&Request::new("POST", url).body(body))
+    }
+
+    /// Sends a request, following redirects.
+    ///
+    /// `301`, `302` and `303` redirects are followed with `GET` and without the body,
+    /// unless the method was `HEAD`. `307` and `308` redirects repeat the request.
+    /// The `Authorization` and `Cookie` headers are dropped when redirected to another host.
+    pub fn send(&self, request: &Request) -> Result<Response, HttpError> {
+        if !is_token(&request.method) {
+            return Err(HttpError::InvalidMethod(request.method.clone()));
+        }
+        let mut url = Url::parse(&request.url)?;
+        let mut method = request.method.clone();
+        let mut body = request.body.clone();
+        let mut headers: Vec<(String, String)> = self
+            .headers
+            .iter()
+            .chain(&request.headers)
+            .cloned()
+            .collect();
+
+        for _ in 0..=self.max_redirects {
+            let response = self.send_once(&method, &url, &headers, &body)?;
+            let location = match response.header("Location") {
+                Some(location) if response.is_redirect() && self.max_redirects > 0 => location,
+                _ => return Ok(response),
+            };
+
+            let next = url.join(location)?;
+            if next.is_onion() && !url.is_onion() && !self.onion_redirects {
+                return Err(HttpError::OnionRedirect(next.to_string()));
+            }
+            if response.status <= 303 && method != "HEAD" {
+                method = "GET".to_owned();
+                body.clear();
+                headers.retain(|(name, _)| {
+                    !name.eq_ignore_ascii_case("Content-Type")
+                        && !name.eq_ignore_ascii_case("Content-Length")
+                });
+            }
+            if !next.host.eq_ignore_ascii_case(&url.host) {
+                headers.retain(|(name, _)| {
+                    !name.eq_ignore_ascii_case("Authorization")
+                        && !name.eq_ignore_ascii_case("Cookie")
+                });
+            }
+            url = next;
+        }
+        Err(HttpError::TooManyRedirects)
+    }
+
+    fn send_once(
+        &self,
+        method: &str,
+        url: &Url,
+        headers: &[(String, String)],
+        body: &[u8],
+    ) -> Result<Response, HttpError> {
+        let mut head = format!(
+            "{} {} HTTP/1.1\r\nHost: {}\r\n",
+            method,
+            url.path,
+            url.host_header()
+        );
+        for (name, value) in headers {
+            if !is_token(name) {
+                return Err(HttpError::InvalidHeader(name.clone()));
+            }
+            if value.contains(['\r', '\n', '\0']) {
+                return Err(HttpError::InvalidHeader(name.clone()));
+            }
+            // These headers are set by the client, and sending them twice would be ambiguous
+            if is_reserved_header(name) {
+                continue;
+            }
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        if !body.is_empty() || method == "POST" || method == "PUT" {
+            head.push_str(&format!("Content-Length: {}\r\n", body.len()));
+        }
+        head.push_str("Connection: close\r\n\r\n");
+
+        let mut connection = self.connect(url)?;
+        let mut request = head.into_bytes();
+        request.extend_from_slice(body);
+        connection.write_all(&request)?;
+        connection.flush()?;
+
+        let (status, reason, headers, body) =
+            read_response(&mut BufReader::new(connection), method, self.max_body_len)?;
+        Ok(Response {
+            url: url.to_string(),
+            status,
+            reason,
+            headers,
+            body,
+        })
+    }
+
+    fn connect(&self, url: &Url) -> Result<Box<dyn Connection>, HttpError> {
+        let stream = self
+            .stream_builder
+            .connect((url.host.as_str(), url.port))
+            .map_err(HttpError::Tor)?;
+        match url.scheme {
+            Scheme::Http => Ok(Box::new(stream)),
+            Scheme::Https => connect_tls(stream),
+        }
+    }
+}
+
+impl Default for Client {
+    fn default() -> Client {
+        Client::new()
+    }
+}
+
+#[cfg(feature = "rustls")]
+fn connect_tls(stream: TorStream) -> Result<Box<dyn Connection>, HttpError> {
+    match crate::rustls::TlsConnector::new().connect(stream) {
+        Ok(stream) => Ok(Box::new(stream)),
+        Err(e) => Err(HttpError::Tls(Box::new(e))),
+    }
+}
+
+#[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+fn connect_tls(stream: TorStream) -> Result<Box<dyn Connection>, HttpError> {
+    match crate::native_tls::TlsConnector::new().connect(stream) {
+        Ok(stream) => Ok(Box::new(stream)),
+        Err(e) => Err(HttpError::Tls(Box::new(e))),
+    }
+}
+
+#[cfg(not(any(feature = "rustls", feature = "native-tls")))]
+fn connect_tls(_stream: TorStream) -> Result<Box<dyn Connection>, HttpError> {
+    Err(HttpError::UnsupportedScheme("https".to_owned()))
+}
+
+impl Request {
+    /// Creates a request without headers or body.
+    pub fn new(method: impl Into<String>, url: impl Into<String>) -> Request {
+        Request {
+            method: method.into(),
+            url: url.into(),
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    /// Adds a header.
+    ///
+    /// `Host`, `Connection` and `Content-Length` are set by the client and ignored here.
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Request {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Sets the body.
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Request {
+        self.body = body.into();
+        self
+    }
+}
+
+impl Response {
+    /// Returns the URL of the response, which differs from the request after redirects.
+    #[inline]
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Returns the status code.
+    #[inline]
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// Returns the reason phrase of the status line, such as `OK`.
+    #[inline]
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+
+    /// Returns whether the status code is in the `2xx` range.
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// Returns whether the response is a redirect which can be followed.
+    pub fn is_redirect(&self) -> bool {
+        matches!(self.status, 301 | 302 | 303 | 307 | 308)
+    }
+
+    /// Returns all headers in the order they were received.
+    #[inline]
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    /// Returns the value of the first header named `name`, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the body, with the transfer encoding removed.
+    #[inline]
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Returns the body as UTF-8 text.
+    pub fn text(&self) -> Result<&str, str::Utf8Error> {
+        str::from_utf8(&self.body)
+    }
+
+    /// Unwraps the body.
+    #[inline]
+    pub fn into_body(self) -> Vec<u8> {
+        self.body
+    }
+}
+
+impl Url {
+    fn parse(url: &str) -> Result<Url, HttpError> {
+        let invalid = || HttpError::InvalidUrl(url.to_owned());
+        let (scheme, rest) = url.trim().split_once("://").ok_or_else(invalid)?;
+        let scheme = if scheme.eq_ignore_ascii_case("http") {
+            Scheme::Http
+        } else if scheme.eq_ignore_ascii_case("https") {
+            Scheme::Https
+        } else {
+            return Err(HttpError::UnsupportedScheme(scheme.to_owned()));
+        };
+
+        let rest = rest.split('#').next().unwrap_or_default();
+        let (authority, path) = match rest.find(['/', '?']) {
+            Some(i) => rest.split_at(i),
+            None => (rest, ""),
+        };
+        // Credentials in URLs are not supported,
+        // and whitespace or control characters would end up in the `Host` header
+        if authority.contains('@') || authority.bytes().any(is_unsafe_byte) {
+            return Err(invalid());
+        }
+
+        let (host, port) = if let Some(host) = authority.strip_prefix('[') {
+            let (host, port) = host.split_once(']').ok_or_else(invalid)?;
+            (host, port.strip_prefix(':'))
+        } else {
+            match authority.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (authority, None),
+            }
+        };
+        let port = match port {
+            Some(port) => port.parse().map_err(|_| invalid())?,
+            None if scheme == Scheme::Http => 80,
+            None => 443,
+        };
+        if host.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Url {
+            scheme,
+            host: host.to_ascii_lowercase(),
+            port,
+            path: match path {
+                "" => "/".to_owned(),
+                path if path.starts_with('?') => format!("/{}", encode_path(path)),
+                path => encode_path(path),
+            },
+        })
+    }
+
+    /// Resolves the `Location` of a redirect against this URL.
+    fn join(&self, location: &str) -> Result<Url, HttpError> {
+        let location = location.split('#').next().unwrap_or_default();
+        let is_absolute = location.split_once("://").is_some_and(|(scheme, _)| {
+            !scheme.is_empty()
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+        });
+        if is_absolute {
+            return Url::parse(location);
+        }
+        if location.starts_with("//") {
+            let scheme = match self.scheme {
+                Scheme::Http => "http:",
+                Scheme::Https => "https:",
+            };
+            return Url::parse(&format!("{}{}", scheme, location));
+        }
+
+        let path = if location.starts_with('/') {
+            encode_path(location)
+        } else if location.starts_with('?') {
+            let path = self.path.split('?').next().unwrap_or_default();
+            format!("{}{}", path, encode_path(location))
+        } else {
+            let path = self.path.split('?').next().unwrap_or_default();
+            let directory = &path[..path.rfind('/').map_or(0, |i| i + 1)];
+            format!("{}{}", directory, encode_path(location))
+        };
+        Ok(Url {
+            path,
+            ..self.clone()
+        })
+    }
+
+    fn is_onion(&self) -> bool {
+        self.host.ends_with(".onion")
+    }
+
+    fn host_header(&self) -> String {
+        let host = if self.host.contains(':') {
+            format!("[{}]", self.host)
+        } else {
+            self.host.clone()
+        };
+        match (self.scheme, self.port) {
+            (Scheme::Http, 80) | (Scheme::Https, 443) => host,
+            (_, port) => format!("{}:{}", host, port),
+        }
+    }
+}
+
+impl fmt::Display for Url {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let scheme = match self.scheme {
+            Scheme::Http => "http",
+            Scheme::Https => "https",
+        };
+        write!(f, "{}://{}{}", scheme, self.host_header(), self.path)
+    }
+}
+
+type Head = (u16, String, Vec<(String, String)>, Vec<u8>);
+
+fn read_response(
+    reader: &mut impl BufRead,
+    method: &str,
+    max_body_len: u64,
+) -> Result<Head, HttpError> {
+    let (status, reason, headers) = loop {
+        let line = read_line(reader)?;
+        let mut parts = line.splitn(3, ' ');
+        let version = parts.next().unwrap_or_default();
+        if !version.starts_with("HTTP/1.") {
+            return Err(HttpError::Protocol("invalid status line"));
+        }
+        let status: u16 = parts
+            .next()
+            .and_then(|status| status.parse().ok())
+            .filter(|status| (100..600).contains(status))
+            .ok_or(HttpError::Protocol("invalid status code"))?;
+        let reason = parts.next().unwrap_or_default().to_owned();
+
+        let mut headers = Vec::new();
+        loop {
+            let line = read_line(reader)?;
+            if line.is_empty() {
+                break;
+            }
+            if headers.len() == MAX_HEADERS {
+                return Err(HttpError::Protocol("too many headers"));
+            }
+            let (name, value) = line
+                .split_once(':')
+                .filter(|(name, _)| is_token(name))
+                .ok_or(HttpError::Protocol("invalid header"))?;
+            headers.push((name.to_owned(), value.trim().to_owned()));
+        }
+
+        // Interim responses like `100 Continue` are followed by the actual response
+        if status >= 200 || status == 101 {
+            break (status, reason, headers);
+        }
+    };
+
+    let header = |name: &str| {
+        headers
+            .iter()
+            .rev()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    };
+    let mut body = Vec::new();
+    if method == "HEAD" || status < 200 || status == 204 || status == 304 {
+        // No body
+    } else if header("Transfer-Encoding").is_some_and(|encoding| {
+        encoding
+            .rsplit(',')
+            .next()
+            .is_some_and(|last| last.trim().eq_ignore_ascii_case("chunked"))
+    }) {
+        read_chunked(reader, &mut body, max_body_len)?;
+    } else if let Some(length) = content_length(&headers)? {
+        if length > max_body_len {
+            return Err(HttpError::BodyTooLong);
+        }
+        reader.take(length).read_to_end(&mut body)?;
+        if (body.len() as u64) < length {
+            return Err(HttpError::Protocol(
+                "the body is shorter than its Content-Length",
+            ));
+        }
+    } else {
+        reader
+            .take(max_body_len.saturating_add(1))
+            .read_to_end(&mut body)?;
+        if body.len() as u64 > max_body_len {
+            return Err(HttpError::BodyTooLong);
+        }
+    }
+
+    Ok((status, reason, headers, body))
+}
+
+/// Parses the `Content-Length` headers, which must all agree on the same length.
+fn content_length(headers: &[(String, String)]) -> Result<Option<u64>, HttpError> {
+    let mut length = None;
+    for (_, value) in headers
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case("Content-Length"))
+    {
+        // Several lengths may also be combined into one header
+        for value in value.split(',') {
+            let value: u64 = value
+                .trim()
+                .parse()
+                .map_err(|_| HttpError::Protocol("invalid Content-Length"))?;
+            if length.is_some_and(|length| length != value) {
+                return Err(HttpError::Protocol("conflicting Content-Length headers"));
+            }
+            length = Some(value);
+        }
+    }
+    Ok(length)
+}
+
+fn read_chunked(
+    reader: &mut impl BufRead,
+    body: &mut Vec<u8>,
+    max_body_len: u64,
+) -> Result<(), HttpError> {
+    loop {
+        let line = read_line(reader)?;
+        let size = line.split(';').next().unwrap_or_default().trim();
+        let size =
+            u64::from_str_radix(size, 16).map_err(|_| HttpError::Protocol("invalid chunk size"))?;
+        if size == 0 {
+            break;
+        }
+
+        if size > max_body_len - body.len() as u64 {
+            return Err(HttpError::BodyTooLong);
+        }
+
+        let len = body.len();
+        reader.take(size).read_to_end(body)?;
+        if ((body.len() - len) as u64) < size {
+            return Err(HttpError::Protocol("truncated chunk"));
+        }
+        if !read_line(reader)?.is_empty() {
+            return Err(HttpError::Protocol("missing line break after chunk"));
+        }
+    }
+
+    // Trailers are discarded
+    while !read_line(reader)?.is_empty() {}
+    Ok(())
+}
+
+/// Reads a line, without the line break.
+fn read_line(reader: &mut impl BufRead) -> Result<String, HttpError> {
+    let mut line = Vec::new();
+    reader.take(MAX_LINE_LEN).read_until(b'\n', &mut line)?;
+    if line.last() != Some(&b'\n') {
+        return Err(if line.len() as u64 == MAX_LINE_LEN {
+            HttpError::Protocol("line too long")
+        } else {
+            HttpError::Protocol("unexpected end of response")
+        });
+    }
+    line.pop();
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    String::from_utf8(line).map_err(|_| HttpError::Protocol("invalid UTF-8 in header"))
+}
+
+/// Checks whether `b` is whitespace, a control character or not ASCII,
+/// none of which may appear in the request line.
+fn is_unsafe_byte(b: u8) -> bool {
+    b <= 0x20 || b >= 0x7f
+}
+
+/// Percent-encodes the unsafe bytes of a path, which keeps them out of the request line.
+fn encode_path(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for &b in path.as_bytes() {
+        if is_unsafe_byte(b) {
+            encoded.push_str(&format!("%{:02X}", b));
+        } else {
+            encoded.push(b as char);
+        }
+    }
+    encoded
+}
+
+/// Checks whether the header `name` is set by the client itself.
+fn is_reserved_header(name: &str) -> bool {
+    ["Host", "Connection", "Content-Length"]
+        .iter()
+        .any(|reserved| name.eq_ignore_ascii_case(reserved))
+}
+
+/// Checks whether `s` is a valid token, as required for methods and header names.
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HttpError::Tor(e) => e.fmt(f),
+            HttpError::Tls(e) => e.fmt(f),
+            HttpError::Io(e) => e.fmt(f),
+            HttpError::InvalidUrl(url) => write!(f, "invalid URL {:?}", url),
+            HttpError::UnsupportedScheme(scheme) => {
+                write!(f, "unsupported URL scheme {:?}", scheme)
+            }
+            HttpError::InvalidMethod(method) => write!(f, "invalid method {:?}", method),
+            HttpError::InvalidHeader(name) => write!(f, "invalid header {:?}", name),
+            HttpError::Protocol(message) => write!(f, "HTTP protocol error: {}", message),
+            HttpError::BodyTooLong => f.write_str("the response body is too long"),
+            HttpError::TooManyRedirects => f.write_str("too many redirects"),
+            HttpError::OnionRedirect(url) => {
+                write!(
+                    f,
+                    "refusing to follow a redirect to the onion service {}",
+                    url
+                )
+            }
+        }
+    }
+}
+
+impl Error for HttpError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            HttpError::Tor(e) => Some(e),
+            HttpError::Tls(e) => Some(&**e),
+            HttpError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for HttpError {
+    fn from(error: io::Error) -> HttpError {
+        HttpError::Io(error)
+    }
+}
+
+impl From<TorError> for HttpError {
+    fn from(error: TorError) -> HttpError {
+        HttpError::Tor(error)
+    }
+}
+
+impl From<HttpError> for io::Error {
+    fn from(error: HttpError) -> io::Error {
+        match error {
+            HttpError::Tor(e) => e.into(),
+            HttpError::Io(e) => e,
+            error @ HttpError::InvalidUrl(_)
+            | error @ HttpError::InvalidMethod(_)
+            | error @ HttpError::InvalidHeader(_) => {
+                io::Error::new(io::ErrorKind::InvalidInput, error)
+            }
+            error @ HttpError::UnsupportedScheme(_) => {
+                io::Error::new(io::ErrorKind::Unsupported, error)
+            }
+            error => io::Error::new(io::ErrorKind::InvalidData, error),
+        }
+    }
+}
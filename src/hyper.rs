@@ -0,0 +1,416 @@
+//! A [hyper] connector opening a Tor stream for every connection.
+//!
+//! [`TorConnector`] implements the connector trait of hyper-util's client,
+//! so it can be used wherever `HttpConnector` would be.
+//! `https` URIs additionally require the `hyper-rustls` feature,
+//! which performs the TLS handshake with a [`rustls::TlsConnector`].
+//!
+//! This module requires the `hyper` feature.
+//!
+//! ```no_run
+//! use http_body_util::Empty;
+//! use hyper::body::Bytes;
+//! use hyper_util::client::legacy::Client;
+//! use hyper_util::rt::TokioExecutor;
+//! use tor_stream::hyper::TorConnector;
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let client = Client::builder(TokioExecutor::new()).build::<_, Empty<Bytes>>(TorConnector::new());
+//!
+//! let response = client.get("http://www.example.com/".parse()?).await?;
+//! println!("{}", response.status());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! # Isolation
+//!
+//! Every connection is opened with the [`Isolation`] of the [`TorStreamBuilder`],
+//! or the one chosen for its URI by [`TorConnector::isolation_fn()`].
+//! hyper keeps connections alive and reuses them for later requests with the same scheme and host,
+//! without asking the connector, so a choice based on the path or query of the first request
+//! would leak into later ones. The hook should therefore only look at the scheme and host,
+//! for example to keep every host on circuits of its own:
+//!
+//! ```no_run
+//! use http_body_util::Empty;
+//! use hyper::body::Bytes;
+//! use hyper_util::client::legacy::Client;
+//! use hyper_util::rt::TokioExecutor;
+//! use tor_stream::hyper::TorConnector;
+//! use tor_stream::Isolation;
+//!
+//! let connector = TorConnector::new().isolation_fn(|uri| match uri.host() {
+//!     Some(host) => Isolation::credentials("tor-stream-host", host),
+//!     None => Isolation::Random,
+//! });
+//! let client = Client::builder(TokioExecutor::new()).build::<_, Empty<Bytes>>(connector);
+//! ```
+//!
+//! For a circuit per request, combine [`Isolation::Random`] with a pool that keeps no idle connections:
+//!
+//! ```no_run
+//! use http_body_util::Empty;
+//! use hyper::body::Bytes;
+//! use hyper_util::client::legacy::Client;
+//! use hyper_util::rt::TokioExecutor;
+//! use tor_stream::hyper::TorConnector;
+//! use tor_stream::{Isolation, TorStreamBuilder};
+//!
+//! let connector = TorConnector::new()
+//!     .stream_builder(TorStreamBuilder::new().isolation(Isolation::Random));
+//! let client = Client::builder(TokioExecutor::new())
+//!     .pool_max_idle_per_host(0)
+//!     .build::<_, Empty<Bytes>>(connector);
+//! ```
+//!
+//! Requests which should share circuits, such as those of one user session,
+//! can instead use a client whose connector has its own [`IsolationGroup`].
+//!
+//! [hyper]: https://hyper.rs
+//! [`TorConnector`]: struct.TorConnector.html
+//! [`TorConnector::isolation_fn()`]: struct.TorConnector.html#method.isolation_fn
+//! [`rustls::TlsConnector`]: ../rustls/struct.TlsConnector.html
+//! [`Isolation`]: ../enum.Isolation.html
+//! [`Isolation::Random`]: ../enum.Isolation.html#variant.Random
+//! [`IsolationGroup`]: ../struct.IsolationGroup.html
+//! [`TorStreamBuilder`]: ../struct.TorStreamBuilder.html
+
+use crate::tokio::TorStream;
+use crate::{Isolation, TargetAddr, TorError, TorStreamBuilder};
+
+use hyper::rt::{Read, ReadBufCursor, Write};
+use hyper::Uri;
+use hyper_util::client::legacy::connect::{Connected, Connection};
+use hyper_util::rt::TokioIo;
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::io::{self, IoSlice};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower_service::Service;
+
+/// An error that occurred while connecting to the host of a URI.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ConnectError {
+    /// The stream could not be established through Tor.
+    Tor(TorError),
+    /// The TLS handshake of an `https` connection failed.
+    #[cfg(feature = "hyper-rustls")]
+    Tls(crate::rustls::TlsError),
+    /// The URI has no host.
+    InvalidUri(Uri),
+    /// The URI uses a scheme other than `http`, or `https` without the `hyper-rustls` feature.
+    UnsupportedScheme(String),
+}
+
+/// A hyper connector which connects over the Tor network.
+///
+/// Every connection uses a new [`tokio::TorStream`] from the [`TorStreamBuilder`],
+/// so the proxy, timeouts, isolation and DNS policy of the builder apply.
+/// Hostnames are always resolved by Tor.
+///
+/// Isolation applies per pooled connection, not per request: hyper hands an idle connection
+/// to any later request with the same scheme and host, which then shares its circuit.
+/// See the [module documentation] for how to isolate requests from each other.
+///
+/// [module documentation]: index.html#isolation
+/// [`tokio::TorStream`]: ../tokio/struct.TorStream.html
+/// [`TorStreamBuilder`]: ../struct.TorStreamBuilder.html
+#[derive(Clone)]
+pub struct TorConnector {
+    builder: TorStreamBuilder,
+    isolation_fn: Option<Arc<IsolationFn>>,
+    #[cfg(feature = "hyper-rustls")]
+    tls: crate::rustls::TlsConnector,
+}
+
+/// A connection opened by a [`TorConnector`], with or without TLS.
+///
+/// [`TorConnector`]: struct.TorConnector.html
+#[derive(Debug)]
+pub struct TorHyperStream {
+    stream: MaybeTls,
+}
+
+type IsolationFn = dyn Fn(&Uri) -> Isolation + Send + Sync;
+
+#[derive(Debug)]
+enum MaybeTls {
+    Plain(TokioIo<TorStream>),
+    #[cfg(feature = "hyper-rustls")]
+    Tls(Box<TokioIo<tokio_rustls::client::TlsStream<TorStream>>>),
+}
+
+impl TorConnector {
+    /// Creates a connector which connects like [`TorStream::connect()`].
+    ///
+    /// With the `hyper-rustls` feature, `https` connections trust the Mozilla root certificates.
+    ///
+    /// [`TorStream::connect()`]: ../tokio/struct.TorStream.html#method.connect
+    pub fn new() -> TorConnector {
+        TorConnector {
+            builder: TorStreamBuilder::new(),
+            isolation_fn: None,
+            #[cfg(feature = "hyper-rustls")]
+            tls: crate::rustls::TlsConnector::new(),
+        }
+    }
+
+    /// Sets the builder used to open each stream.
+    pub fn stream_builder(mut self, builder: TorStreamBuilder) -> TorConnector {
+        self.builder = builder;
+        self
+    }
+
+    /// Chooses the isolation of each connection from the URI it is opened for,
+    /// instead of using the isolation of the builder.
+    ///
+    /// `f` is called once per connection, with the URI of the request which opened it.
+    /// hyper's pool is keyed by scheme and host only, so later requests to the same host
+    /// reuse that connection and its isolation whatever `f` would return for their URI.
+    /// `f` should therefore only depend on the scheme and host, as explained in the
+    /// [module documentation].
+    ///
+    /// [module documentation]: index.html#isolation
+    pub fn isolation_fn(
+        mut self,
+        f: impl Fn(&Uri) -> Isolation + Send + Sync + 'static,
+    ) -> TorConnector {
+        self.isolation_fn = Some(Arc::new(f));
+        self
+    }
+
+    /// Sets the connector used for `https` connections,
+    /// for example to trust other roots or to pin certificates.
+    ///
+    /// If it offers `h2` with ALPN and the server selects it, hyper uses HTTP/2.
+    ///
+    /// This method requires the `hyper-rustls` feature.
+    #[cfg(feature = "hyper-rustls")]
+    pub fn tls_connector(mut self, connector: crate::rustls::TlsConnector) -> TorConnector {
+        self.tls = connector;
+        self
+    }
+
+    /// Opens a connection to the host of `uri`.
+    ///
+    /// The port defaults to 80 for `http` and 443 for `https`.
+    pub async fn connect(&self, uri: Uri) -> Result<TorHyperStream, ConnectError> {
+        let https = match uri.scheme_str() {
+            Some("http") => false,
+            Some("https") if cfg!(feature = "hyper-rustls") => true,
+            scheme => {
+                return Err(ConnectError::UnsupportedScheme(
+                    scheme.unwrap_or_default().to_owned(),
+                ))
+            }
+        };
+        let host = match uri.host() {
+            // IPv6 addresses are enclosed in brackets
+            Some(host) => host.trim_start_matches('[').trim_end_matches(']'),
+            None => return Err(ConnectError::InvalidUri(uri)),
+        };
+        let port = uri.port_u16().unwrap_or(if https { 443 } else { 80 });
+
+        let stream = match &self.isolation_fn {
+            Some(isolation_fn) => {
+                let builder = self.builder.clone().isolation(isolation_fn(&uri));
+                builder.connect_tokio((host, port)).await?
+            }
+            None => self.builder.connect_tokio((host, port)).await?,
+        };
+        #[cfg(feature = "hyper-rustls")]
+        if https {
+            let config = self.tls.client_config().map_err(ConnectError::Tls)?;
+            let server_name =
+                crate::rustls::server_name(stream.target_addr()).map_err(ConnectError::Tls)?;
+            let stream = tokio_rustls::TlsConnector::from(config)
+                .connect(server_name, stream)
+                .await
+                .map_err(|e| ConnectError::Tls(e.into()))?;
+            return Ok(TorHyperStream {
+                stream: MaybeTls::Tls(Box::new(TokioIo::new(stream))),
+            });
+        }
+        Ok(TorHyperStream {
+            stream: MaybeTls::Plain(TokioIo::new(stream)),
+        })
+    }
+}
+
+impl fmt::Debug for TorConnector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Closures don't implement `Debug`
+        let mut debug = f.debug_struct("TorConnector");
+        debug
+            .field("builder", &self.builder)
+            .field("isolation_fn", &self.isolation_fn.is_some());
+        #[cfg(feature = "hyper-rustls")]
+        debug.field("tls", &self.tls);
+        debug.finish()
+    }
+}
+
+impl Default for TorConnector {
+    fn default() -> TorConnector {
+        TorConnector::new()
+    }
+}
+
+impl Service<Uri> for TorConnector {
+    type Response = TorHyperStream;
+    type Error = ConnectError;
+    type Future = Pin<Box<dyn Future<Output = Result<TorHyperStream, ConnectError>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context) -> Poll<Result<(), ConnectError>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let connector = self.clone();
+        Box::pin(async move { connector.connect(uri).await })
+    }
+}
+
+impl TorHyperStream {
+    /// Returns the destination address the stream was connected to.
+    pub fn target_addr(&self) -> &TargetAddr {
+        match &self.stream {
+            MaybeTls::Plain(stream) => stream.inner().target_addr(),
+            #[cfg(feature = "hyper-rustls")]
+            MaybeTls::Tls(stream) => stream.inner().get_ref().0.target_addr(),
+        }
+    }
+
+    /// Returns whether the connection uses TLS.
+    pub fn is_tls(&self) -> bool {
+        !matches!(self.stream, MaybeTls::Plain(_))
+    }
+}
+
+impl Connection for TorHyperStream {
+    fn connected(&self) -> Connected {
+        match &self.stream {
+            MaybeTls::Plain(_) => Connected::new(),
+            #[cfg(feature = "hyper-rustls")]
+            MaybeTls::Tls(stream) => {
+                if stream.inner().get_ref().1.alpn_protocol() == Some(b"h2") {
+                    Connected::new().negotiated_h2()
+                } else {
+                    Connected::new()
+                }
+            }
+        }
+    }
+}
+
+impl Read for TorHyperStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: ReadBufCursor,
+    ) -> Poll<io::Result<()>> {
+        match &mut self.get_mut().stream {
+            MaybeTls::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            #[cfg(feature = "hyper-rustls")]
+            MaybeTls::Tls(stream) => Pin::new(&mut **stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl Write for TorHyperStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match &mut self.get_mut().stream {
+            MaybeTls::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            #[cfg(feature = "hyper-rustls")]
+            MaybeTls::Tls(stream) => Pin::new(&mut **stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        match &mut self.get_mut().stream {
+            MaybeTls::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            #[cfg(feature = "hyper-rustls")]
+            MaybeTls::Tls(stream) => Pin::new(&mut **stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        match &mut self.get_mut().stream {
+            MaybeTls::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
+            #[cfg(feature = "hyper-rustls")]
+            MaybeTls::Tls(stream) => Pin::new(&mut **stream).poll_shutdown(cx),
+        }
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        bufs: &[IoSlice],
+    ) -> Poll<io::Result<usize>> {
+        match &mut self.get_mut().stream {
+            MaybeTls::Plain(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+            #[cfg(feature = "hyper-rustls")]
+            MaybeTls::Tls(stream) => Pin::new(&mut **stream).poll_write_vectored(cx, bufs),
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match &self.stream {
+            MaybeTls::Plain(stream) => stream.is_write_vectored(),
+            #[cfg(feature = "hyper-rustls")]
+            MaybeTls::Tls(stream) => stream.is_write_vectored(),
+        }
+    }
+}
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConnectError::Tor(e) => e.fmt(f),
+            #[cfg(feature = "hyper-rustls")]
+            ConnectError::Tls(e) => e.fmt(f),
+            ConnectError::InvalidUri(uri) => write!(f, "the URI {} has no host", uri),
+            ConnectError::UnsupportedScheme(scheme) => {
+                write!(f, "unsupported URI scheme {:?}", scheme)
+            }
+        }
+    }
+}
+
+impl Error for ConnectError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConnectError::Tor(e) => Some(e),
+            #[cfg(feature = "hyper-rustls")]
+            ConnectError::Tls(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<TorError> for ConnectError {
+    fn from(error: TorError) -> ConnectError {
+        ConnectError::Tor(error)
+    }
+}
+
+impl From<ConnectError> for io::Error {
+    fn from(error: ConnectError) -> io::Error {
+        match error {
+            ConnectError::Tor(e) => e.into(),
+            #[cfg(feature = "hyper-rustls")]
+            ConnectError::Tls(e) => e.into(),
+            error @ ConnectError::InvalidUri(_) => {
+                io::Error::new(io::ErrorKind::InvalidInput, error)
+            }
+            error @ ConnectError::UnsupportedScheme(_) => {
+                io::Error::new(io::ErrorKind::Unsupported, error)
+            }
+        }
+    }
+}
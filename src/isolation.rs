@@ -0,0 +1,138 @@
+use std::fmt::Write;
+use std::io;
+
+/// Controls which streams may share a Tor circuit.
+///
+/// Tor's `IsolateSOCKSAuth` flag, which is enabled by default, places streams
+/// that use different SOCKS5 username/password credentials on different circuits.
+/// The credentials are not checked by Tor, they only serve as an isolation token.
+///
+/// ```no_run
+/// use tor_stream::{Isolation, IsolationGroup, TorStreamBuilder};
+///
+/// // These two streams share a circuit...
+/// let group = IsolationGroup::new();
+/// let builder = TorStreamBuilder::new().isolation(Isolation::Group(group));
+/// let first = builder.connect("www.example.com:80").expect("Failed to connect");
+/// let second = builder.connect("www.example.org:80").expect("Failed to connect");
+///
+/// // ...while this one uses a circuit of its own.
+/// let third = TorStreamBuilder::new()
+///     .isolation(Isolation::Random)
+///     .connect("www.example.net:80")
+///     .expect("Failed to connect");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub enum Isolation {
+    /// No credentials are sent, so the stream may share a circuit with any
+    /// other stream that does not use credentials either.
+    #[default]
+    None,
+    /// Sends the given credentials.
+    Credentials {
+        /// The SOCKS5 username, between 1 and 255 bytes long.
+        username: String,
+        /// The SOCKS5 password, between 1 and 255 bytes long.
+        password: String,
+    },
+    /// Generates a new random token for every connection,
+    /// so the stream never shares a circuit with another one.
+    Random,
+    /// Shares circuits with the other streams of an [`IsolationGroup`].
+    ///
+    /// [`IsolationGroup`]: struct.IsolationGroup.html
+    Group(IsolationGroup),
+}
+
+impl Isolation {
+    /// Creates an `Isolation` from an explicit username and password.
+    pub fn credentials(username: impl Into<String>, password: impl Into<String>) -> Isolation {
+        Isolation::Credentials {
+            username: username.into(),
+            password: password.into(),
+        }
+    }
+
+    /// Returns the SOCKS5 credentials to send for a new connection.
+    pub(crate) fn to_credentials(&self) -> Option<(String, String)> {
+        match self {
+            Isolation::None => None,
+            Isolation::Credentials { username, password } => {
+                Some((username.clone(), password.clone()))
+            }
+            Isolation::Random => Some(("tor-stream-random".to_owned(), random_token())),
+            Isolation::Group(group) => Some(("tor-stream-group".to_owned(), group.token.clone())),
+        }
+    }
+}
+
+impl From<IsolationGroup> for Isolation {
+    fn from(group: IsolationGroup) -> Isolation {
+        Isolation::Group(group)
+    }
+}
+
+/// A reusable isolation token.
+///
+/// Streams connected with the same group may share circuits,
+/// while streams of different groups never do.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct IsolationGroup {
+    token: String,
+}
+
+impl IsolationGroup {
+    /// Creates a new group with a random token, which is distinct from all other groups.
+    pub fn new() -> IsolationGroup {
+        IsolationGroup {
+            token: random_token(),
+        }
+    }
+
+    /// Creates a group with a fixed name.
+    ///
+    /// All groups with the same name are equivalent, even across processes.
+    ///
+    /// # Errors
+    ///
+    /// The name is sent as the SOCKS5 password, so an error of kind `InvalidInput`
+    /// is returned if it is not between 1 and 255 bytes long.
+    pub fn named(name: impl Into<String>) -> io::Result<IsolationGroup> {
+        let token = name.into();
+        if token.is_empty() || token.len() > 255 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "isolation group name must be between 1 and 255 bytes long",
+            ));
+        }
+        Ok(IsolationGroup { token })
+    }
+
+    /// Returns the token identifying this group.
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+}
+
+impl Default for IsolationGroup {
+    fn default() -> IsolationGroup {
+        IsolationGroup::new()
+    }
+}
+
+/// Generates an unpredictable token from 128 random bits.
+///
+/// # Panics
+///
+/// Panics if the operating system fails to provide random bytes,
+/// like the randomly seeded hashers of the standard library.
+fn random_token() -> String {
+    let mut bytes = [0; 16];
+    getrandom::getrandom(&mut bytes).expect("failed to get random bytes from the OS");
+
+    let mut token = String::with_capacity(32);
+    for byte in &bytes {
+        let _ = write!(token, "{:02x}", byte);
+    }
+    token
+}
@@ -1,60 +1,128 @@
 //! Provides an interface for proxying network streams over the Tor network.
 //!
-//! See [setup] for information on creating a local Tor SOCKS5 proxy.
+//! See [setup] for information on creating a local Tor SOCKS5 proxy,
+//! or start a private Tor instance with the [`process`] module.
+//! Configuration files can be read and written with the [`torrc`] module.
 //!
 //! # Usage
 //!
 //! If your Tor proxy is running on the default address `127.0.0.1:9050`,
 //! you can use [`TorStream::connect()`]. If that is not the case,
-//! you can specify your address in a call to [`TorStream::connect_with_address()`].
+//! you can specify your address in a call to [`TorStream::connect_with_address()`],
+//! which also accepts the path of a Unix domain socket.
+//! Timeouts and other options can be configured with a [`TorStreamBuilder`].
 //!
-//! ```
-//! use tor_stream::TorStream;
-//! use std::io::prelude::*;
-//!
-//! let mut stream = TorStream::connect("www.example.com:80").expect("Failed to connect");
-//!
-//! // The stream can be used like a normal TCP stream
-//!
-//! stream.write_all(b"GET / HTTP/1.1\r\nConnection: Close\r\nHost: www.example.com\r\n\r\n").expect("Failed to send request");
+//! Streams can be kept on separate circuits with [`Isolation`].
+//! Hostnames can be looked up over Tor without leaking DNS requests with [`resolve()`],
+//! and a [`DnsPolicy`] refuses destinations that were resolved locally.
+//! A running Tor instance can be managed through its control port with the [`control`] module,
+//! which the [`onion`] module uses to host onion services.
+//! For simple requests, the [`http`] module has a minimal blocking HTTP/1.1 client.
 //!
-//! // If you want the raw stream, call `into_inner()`
+//! ```no_run
+//! // Connects through the proxy at the default address and sends a GET request
+//! let response = tor_stream::http::get("http://www.example.com/").expect("Request failed");
 //!
-//! let mut stream = stream.into_inner();
+//! println!("Server response:\n{}", response.text().expect("Invalid UTF-8"));
+//! ```
 //!
-//! let mut buf = String::new();
-//! stream.read_to_string(&mut buf).expect("Failed to read response");
+//! # Features
 //!
-//! println!("Server response:\n{}", buf);
-//! ```
+//! - `tokio`: Asynchronous streams for tokio in the [`tokio`] module.
+//! - `futures-io`: Asynchronous streams for other executors, such as async-std or smol,
+//!   in the [`futures`] module.
+//! - `rustls`: TLS over Tor streams with rustls in the [`rustls`] module.
+//! - `native-tls`: TLS over Tor streams with the platform TLS stack in the [`native_tls`] module.
+//! - `hyper`: A hyper connector in the [`hyper`] module.
+//! - `hyper-rustls`: `https` support for the hyper connector, using rustls.
+//! - `reqwest`: reqwest clients using Tor as their proxy, in the [`reqwest`] module.
+//! - `ureq`: ureq agents connecting with Tor streams, in the [`ureq`] module.
 //!
 //! # Credits
 //!
-//! This crate is mostly a wrapper about Steven Fackler's [`socks`] crate.
+//! The SOCKS5 client was originally provided by Steven Fackler's [`socks`] crate,
+//! whose `ToTargetAddr` interface this crate keeps.
 //!
 //! [setup]: setup/index.html
 //! [`socks`]: https://crates.io/crates/socks
 //! [`TorStream::connect()`]: struct.TorStream.html#method.connect
 //! [`TorStream::connect_with_address()`]: struct.TorStream.html#method.connect_with_address
+//! [`TorStreamBuilder`]: struct.TorStreamBuilder.html
+//! [`Isolation`]: enum.Isolation.html
+//! [`resolve()`]: fn.resolve.html
+//! [`DnsPolicy`]: enum.DnsPolicy.html
+//! [`control`]: control/index.html
+//! [`onion`]: onion/index.html
+//! [`http`]: http/index.html
+//! [`process`]: process/index.html
+//! [`torrc`]: torrc/index.html
+//! [`tokio`]: tokio/index.html
+//! [`futures`]: futures/index.html
+//! [`rustls`]: rustls/index.html
+//! [`native_tls`]: native_tls/index.html
+//! [`hyper`]: hyper/index.html
+//! [`reqwest`]: reqwest/index.html
+//! [`ureq`]: ureq/index.html
 
 #![forbid(unsafe_code)]
 
 #[macro_use]
 extern crate lazy_static;
-pub extern crate socks;
 
-pub use socks::ToTargetAddr;
+mod builder;
+pub mod control;
+pub mod discovery;
+mod dns;
+mod error;
+#[cfg(feature = "futures-io")]
+pub mod futures;
+pub mod http;
+#[cfg(feature = "hyper")]
+pub mod hyper;
+mod isolation;
+#[cfg(feature = "native-tls")]
+pub mod native_tls;
+pub mod onion;
+pub mod process;
+mod proxy;
+#[cfg(feature = "reqwest")]
+pub mod reqwest;
+mod resolve;
+#[cfg(feature = "rustls")]
+pub mod rustls;
+mod socks5;
+#[cfg(feature = "tokio")]
+pub mod tokio;
+pub mod torrc;
+#[cfg(feature = "ureq")]
+pub mod ureq;
+
+pub use builder::TorStreamBuilder;
+pub use dns::DnsPolicy;
+pub use error::TorError;
+pub use isolation::{Isolation, IsolationGroup};
+pub use proxy::{ProxyAddr, ProxyStream};
+pub use resolve::{resolve, resolve_ptr};
+pub use socks5::{TargetAddr, ToTargetAddr};
+
+/// The address types formerly re-exported from the [`socks`] crate.
+///
+/// [`socks`]: https://crates.io/crates/socks
+#[deprecated(note = "use `tor_stream::ToTargetAddr` and `tor_stream::TargetAddr` instead")]
+pub mod socks {
+    pub use crate::{TargetAddr, ToTargetAddr};
+}
 
-use socks::Socks5Stream;
 use std::io::{self, Read, Write};
-use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, TcpStream};
-use std::ops::Deref;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
 
 lazy_static! {
     /// The default TOR socks5 proxy address, `127.0.0.1:9050`.
+    /// It is used if [`discovery`] finds no other proxy.
     /// The proxy can be configured in the SOCKS section of [`/etc/tor/torrc`].
     /// See the [TOR manual] for more information on `torrc`.
     ///
+    /// [`discovery`]: discovery/index.html
     /// [`/etc/tor/torrc`]: file:///etc/tor/torrc
     /// [TOR manual]: https://www.torproject.org/docs/tor-manual.html.en
     pub static ref TOR_PROXY: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 9050));
@@ -63,73 +131,132 @@ lazy_static! {
 /// A stream proxied over the Tor network.
 /// After connecting, it can be used like a normal [`TcpStream`].
 ///
+/// The connection to the proxy is a [`ProxyStream`], which is either a TCP stream
+/// or a Unix domain socket, depending on the [`ProxyAddr`] of the proxy.
+///
 /// [`TcpStream`]: https://doc.rust-lang.org/std/net/struct.TcpStream.html
-pub struct TorStream(TcpStream);
+/// [`ProxyStream`]: enum.ProxyStream.html
+/// [`ProxyAddr`]: enum.ProxyAddr.html
+#[derive(Debug)]
+pub struct TorStream {
+    stream: ProxyStream,
+    target: TargetAddr,
+    bind_addr: TargetAddr,
+}
 
 impl TorStream {
     /// Connects to a destination address over the Tor network.
     ///
     /// # Requirements
     ///
-    /// A Tor SOCKS5 proxy must be running at `127.0.0.1:9050`.
+    /// A Tor SOCKS5 proxy must be running. Its address is found by [`discovery`],
+    /// which checks the `TOR_PROXY` environment variable, the system `torrc`
+    /// and the default ports of Tor and the Tor Browser.
     /// See [setup] for more details on configuring a local proxy.
     ///
     /// If you want to use a different Tor address, use [`connect_with_address`].
     ///
+    /// # Errors
+    ///
+    /// Errors reported by the proxy are returned as a [`TorError`] wrapped in the `io::Error`,
+    /// and can be inspected with [`TorError::from_io_error()`].
+    ///
+    /// [`discovery`]: discovery/index.html
     /// [setup]: setup/index.html
     /// [`connect_with_address`]: struct.TorStream.html#method.connect_with_address
+    /// [`TorError`]: enum.TorError.html
+    /// [`TorError::from_io_error()`]: enum.TorError.html#method.from_io_error
     pub fn connect(destination: impl ToTargetAddr) -> io::Result<TorStream> {
-        Socks5Stream::connect(TOR_PROXY.deref(), destination)
-            .map(|stream| TorStream(stream.into_inner()))
+        TorStreamBuilder::new()
+            .connect(destination)
+            .map_err(io::Error::from)
     }
 
     /// Connects to a destination address over the Tor network.
-    /// A Tor SOCKS5 proxy must be running at the `tor_proxy` address.
+    /// A Tor SOCKS5 proxy must be running at the `tor_proxy` address,
+    /// which is a `SocketAddr` or the path of a Unix domain socket.
+    ///
+    /// ```no_run
+    /// use tor_stream::TorStream;
+    /// use std::path::Path;
+    ///
+    /// let stream = TorStream::connect_with_address(Path::new("/run/tor/socks"), "www.example.com:80")
+    ///     .expect("Failed to connect");
+    /// ```
     pub fn connect_with_address(
-        tor_proxy: SocketAddr,
+        tor_proxy: impl Into<ProxyAddr>,
         destination: impl ToTargetAddr,
     ) -> io::Result<TorStream> {
-        Socks5Stream::connect(tor_proxy, destination).map(|stream| TorStream(stream.into_inner()))
+        TorStreamBuilder::new()
+            .proxy(tor_proxy)
+            .connect(destination)
+            .map_err(io::Error::from)
     }
 
-    /// Gets a reference to the underlying TCP stream.
+    /// Creates a [`TorStreamBuilder`] for configuring timeouts and the proxy address.
+    ///
+    /// [`TorStreamBuilder`]: struct.TorStreamBuilder.html
+    #[inline]
+    pub fn builder() -> TorStreamBuilder {
+        TorStreamBuilder::new()
+    }
+
+    /// Returns the destination address the stream was connected to.
     #[inline]
-    pub fn get_ref(&self) -> &TcpStream {
-        &self.0
+    pub fn target_addr(&self) -> &TargetAddr {
+        &self.target
     }
 
-    /// Gets a mutable reference to the underlying TCP stream.
+    /// Returns the bound address reported by the proxy.
+    ///
+    /// Tor does not expose the address of the exit relay, so this is usually `0.0.0.0:0`.
     #[inline]
-    pub fn get_mut(&mut self) -> &mut TcpStream {
-        &mut self.0
+    pub fn bind_addr(&self) -> &TargetAddr {
+        &self.bind_addr
+    }
+
+    /// Gets a reference to the underlying connection to the proxy.
+    ///
+    /// Use [`ProxyStream::as_tcp()`] to access the TCP stream.
+    ///
+    /// [`ProxyStream::as_tcp()`]: enum.ProxyStream.html#method.as_tcp
+    #[inline]
+    pub fn get_ref(&self) -> &ProxyStream {
+        &self.stream
+    }
+
+    /// Gets a mutable reference to the underlying connection to the proxy.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut ProxyStream {
+        &mut self.stream
     }
 
     #[doc(hidden)]
     #[inline]
-    pub fn unwrap(self) -> TcpStream {
-        self.0
+    pub fn unwrap(self) -> ProxyStream {
+        self.stream
     }
 
     /// Unwraps the `TorStream`.
     #[inline]
-    pub fn into_inner(self) -> TcpStream {
-        self.0
+    pub fn into_inner(self) -> ProxyStream {
+        self.stream
     }
 }
 
 impl Read for TorStream {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.0.read(buf)
+        self.stream.read(buf)
     }
 }
 
 impl Write for TorStream {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.0.write(buf)
+        self.stream.write(buf)
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        self.0.flush()
+        self.stream.flush()
     }
 }
 
@@ -145,12 +272,20 @@ impl Write for TorStream {
 ///
 /// To check whether everything is working correctly, run `cargo run`.
 /// If you want to use a special Tor address, you can pass it as first argument
-/// or set the `TOR_PROXY` environment variable:
+/// or set the `TOR_PROXY` environment variable, which [`TorStream::connect()`] uses as well:
 ///
 /// `cargo run -- 127.0.0.1:9050`
 ///
 /// `TOR_PROXY=127.0.0.1:9050 cargo run`
 ///
+/// If Tor only listens on a Unix domain socket, such as `SocksPort unix:/run/tor/socks`,
+/// pass it as `unix:/run/tor/socks` instead.
+///
+/// Alternatively, a program can start its own Tor instance with a [`TorProcess`],
+/// which only requires the `tor` binary to be installed.
+///
 /// [Tor installation guide]: https://www.torproject.org/docs/installguide.html.en
+/// [`TorStream::connect()`]: ../struct.TorStream.html#method.connect
+/// [`TorProcess`]: ../process/struct.TorProcess.html
 #[allow(unused)]
 pub mod setup {}
@@ -0,0 +1,318 @@
+//! TLS over Tor streams with the platform TLS stack, using [native-tls].
+//!
+//! This is the counterpart of the [`rustls`] module for deployments which must use
+//! OpenSSL, Secure Transport or SChannel. A [`TlsConnector`] performs the TLS handshake
+//! over an established [`TorStream`], using the hostname the stream was connected to
+//! for SNI and certificate validation.
+//!
+//! This module requires the `native-tls` feature.
+//!
+//! ```no_run
+//! use tor_stream::native_tls::TorTlsStream;
+//! use std::io::prelude::*;
+//!
+//! let mut stream = TorTlsStream::connect("www.example.com:443").expect("Failed to connect");
+//!
+//! stream.write_all(b"GET / HTTP/1.1\r\nConnection: Close\r\nHost: www.example.com\r\n\r\n").expect("Failed to send request");
+//!
+//! let mut buf = String::new();
+//! stream.read_to_string(&mut buf).expect("Failed to read response");
+//! ```
+//!
+//! [native-tls]: https://docs.rs/native-tls/
+//! [`rustls`]: ../rustls/index.html
+//! [`TlsConnector`]: struct.TlsConnector.html
+//! [`TorStream`]: ../struct.TorStream.html
+
+use crate::{TargetAddr, ToTargetAddr, TorError, TorStream};
+
+use native_tls::{Certificate, HandshakeError, TlsStream};
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::iter;
+
+/// An error that occurred while establishing a TLS stream over Tor.
+///
+/// Failures of Tor and the proxy are kept apart from failures of the TLS handshake.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum TlsError {
+    /// The stream could not be established through Tor.
+    Tor(TorError),
+    /// The TLS handshake failed, for example because the certificate was rejected.
+    Tls(native_tls::Error),
+    /// The server's certificate matches none of the pins.
+    PinMismatch,
+    /// An I/O error occurred during the handshake.
+    ///
+    /// If the stream has a read or write timeout, an expired timeout is reported as `TimedOut`.
+    Io(io::Error),
+}
+
+/// A builder for TLS sessions over Tor streams.
+///
+/// ```no_run
+/// use native_tls::Certificate;
+/// use tor_stream::native_tls::TlsConnector;
+/// use tor_stream::TorStream;
+///
+/// // Trust only the certificate authority of an internal service
+/// let ca = std::fs::read("ca.pem").expect("Failed to read certificate");
+/// let ca = Certificate::from_pem(&ca).expect("Invalid certificate");
+///
+/// let connector = TlsConnector::with_root_certificates(vec![ca]);
+/// let stream = TorStream::connect("internal.example.com:443").expect("Failed to connect");
+/// let stream = connector.connect(stream).expect("TLS handshake failed");
+/// ```
+#[derive(Clone)]
+pub struct TlsConnector {
+    roots: Vec<Certificate>,
+    built_in_roots: bool,
+    pins: Vec<[u8; 32]>,
+    alpn_protocols: Vec<String>,
+}
+
+/// A TLS stream over the Tor network.
+///
+/// It implements `Read` and `Write` for the decrypted data.
+#[derive(Debug)]
+pub struct TorTlsStream {
+    stream: TlsStream<TorStream>,
+}
+
+impl TlsConnector {
+    /// Creates a connector trusting the root certificates of the platform, without pins.
+    pub fn new() -> TlsConnector {
+        TlsConnector {
+            roots: Vec::new(),
+            built_in_roots: true,
+            pins: Vec::new(),
+            alpn_protocols: Vec::new(),
+        }
+    }
+
+    /// Creates a connector trusting only `roots`, instead of the root certificates of the platform.
+    pub fn with_root_certificates(roots: Vec<Certificate>) -> TlsConnector {
+        TlsConnector {
+            roots,
+            built_in_roots: false,
+            ..TlsConnector::new()
+        }
+    }
+
+    /// Pins the SHA-256 hash of the server's DER-encoded end-entity certificate.
+    ///
+    /// The certificate chain is still verified by the platform.
+    /// Can be called multiple times, so a certificate can be replaced without downtime;
+    /// the server must then present one of the pinned certificates.
+    /// Since native-tls has no hook into the verification, the pins are checked
+    /// right after the handshake, before any data is sent.
+    pub fn pin_sha256(mut self, hash: [u8; 32]) -> TlsConnector {
+        self.pins.push(hash);
+        self
+    }
+
+    /// Sets the protocols offered with ALPN, such as `"http/1.1"`.
+    pub fn alpn_protocols(mut self, protocols: Vec<String>) -> TlsConnector {
+        self.alpn_protocols = protocols;
+        self
+    }
+
+    /// Performs the TLS handshake over `stream`.
+    ///
+    /// The server name is the hostname from the [`target_addr()`] of the stream,
+    /// or its IP address if it was connected to one.
+    ///
+    /// [`target_addr()`]: ../struct.TorStream.html#method.target_addr
+    pub fn connect(&self, stream: TorStream) -> Result<TorTlsStream, TlsError> {
+        let domain = match stream.target_addr() {
+            TargetAddr::Ip(address) => address.ip().to_string(),
+            TargetAddr::Domain(host, _) => host.clone(),
+        };
+        let stream = self
+            .native_connector()?
+            .connect(&domain, stream)
+            .map_err(|e| match e {
+                HandshakeError::Failure(e) => handshake_failure(e),
+                HandshakeError::WouldBlock(_) => TlsError::Io(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "the TLS handshake timed out",
+                )),
+            })?;
+
+        let stream = TorTlsStream { stream };
+        if !self.pins.is_empty() {
+            let hash = stream
+                .peer_certificate_sha256()?
+                .ok_or(TlsError::PinMismatch)?;
+            if !self.pins.contains(&hash) {
+                return Err(TlsError::PinMismatch);
+            }
+        }
+        Ok(stream)
+    }
+
+    fn native_connector(&self) -> Result<native_tls::TlsConnector, TlsError> {
+        let mut builder = native_tls::TlsConnector::builder();
+        builder.disable_built_in_roots(!self.built_in_roots);
+        for root in &self.roots {
+            builder.add_root_certificate(root.clone());
+        }
+        let protocols: Vec<&str> = self.alpn_protocols.iter().map(String::as_str).collect();
+        builder.request_alpns(&protocols);
+        builder.build().map_err(TlsError::Tls)
+    }
+}
+
+/// Failures of the underlying stream are reported as I/O errors,
+/// which native-tls only exposes as a source of the TLS error.
+fn handshake_failure(error: native_tls::Error) -> TlsError {
+    let kind = iter::successors(error.source(), |&e| e.source())
+        .find_map(|e| e.downcast_ref::<io::Error>())
+        .map(io::Error::kind);
+    match kind {
+        Some(io::ErrorKind::WouldBlock) => {
+            TlsError::Io(io::Error::new(io::ErrorKind::TimedOut, error))
+        }
+        Some(kind) => TlsError::Io(io::Error::new(kind, error)),
+        None => TlsError::Tls(error),
+    }
+}
+
+impl fmt::Debug for TlsConnector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Certificates don't implement `Debug`
+        f.debug_struct("TlsConnector")
+            .field("roots", &self.roots.len())
+            .field("built_in_roots", &self.built_in_roots)
+            .field("pins", &self.pins)
+            .field("alpn_protocols", &self.alpn_protocols)
+            .finish()
+    }
+}
+
+impl Default for TlsConnector {
+    fn default() -> TlsConnector {
+        TlsConnector::new()
+    }
+}
+
+impl TorTlsStream {
+    /// Connects to a destination address over the Tor network and performs the TLS handshake,
+    /// trusting the root certificates of the platform.
+    ///
+    /// The proxy is found like in [`TorStream::connect()`].
+    /// Use a [`TlsConnector`] to configure the certificate validation.
+    ///
+    /// [`TorStream::connect()`]: ../struct.TorStream.html#method.connect
+    /// [`TlsConnector`]: struct.TlsConnector.html
+    pub fn connect(destination: impl ToTargetAddr) -> io::Result<TorTlsStream> {
+        let stream = TorStream::connect(destination)?;
+        TlsConnector::new().connect(stream).map_err(io::Error::from)
+    }
+
+    /// Returns the destination address the stream was connected to.
+    #[inline]
+    pub fn target_addr(&self) -> &TargetAddr {
+        self.stream.get_ref().target_addr()
+    }
+
+    /// Returns the certificate presented by the server.
+    pub fn peer_certificate(&self) -> Result<Option<Certificate>, TlsError> {
+        self.stream.peer_certificate().map_err(TlsError::Tls)
+    }
+
+    /// Returns the SHA-256 hash of the server's DER-encoded certificate,
+    /// as used by [`TlsConnector::pin_sha256()`].
+    ///
+    /// [`TlsConnector::pin_sha256()`]: struct.TlsConnector.html#method.pin_sha256
+    pub fn peer_certificate_sha256(&self) -> Result<Option<[u8; 32]>, TlsError> {
+        match self.peer_certificate()? {
+            Some(certificate) => {
+                let der = certificate.to_der().map_err(TlsError::Tls)?;
+                Ok(Some(Sha256::digest(der).into()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the protocol negotiated with ALPN.
+    pub fn alpn_protocol(&self) -> Result<Option<Vec<u8>>, TlsError> {
+        self.stream.negotiated_alpn().map_err(TlsError::Tls)
+    }
+
+    /// Gets a reference to the underlying Tor stream.
+    #[inline]
+    pub fn get_ref(&self) -> &TorStream {
+        self.stream.get_ref()
+    }
+
+    /// Gets a mutable reference to the underlying Tor stream.
+    ///
+    /// Reading or writing through it corrupts the TLS session.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut TorStream {
+        self.stream.get_mut()
+    }
+
+    /// Sends a TLS `close_notify` alert to the server.
+    pub fn shutdown(&mut self) -> io::Result<()> {
+        self.stream.shutdown()
+    }
+}
+
+impl Read for TorTlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.read(buf)
+    }
+}
+
+impl Write for TorTlsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl fmt::Display for TlsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TlsError::Tor(e) => e.fmt(f),
+            TlsError::Tls(e) => write!(f, "TLS error: {}", e),
+            TlsError::PinMismatch => f.write_str("the certificate matches none of the pins"),
+            TlsError::Io(e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for TlsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TlsError::Tor(e) => Some(e),
+            TlsError::Tls(e) => Some(e),
+            TlsError::Io(e) => Some(e),
+            TlsError::PinMismatch => None,
+        }
+    }
+}
+
+impl From<TorError> for TlsError {
+    fn from(error: TorError) -> TlsError {
+        TlsError::Tor(error)
+    }
+}
+
+impl From<TlsError> for io::Error {
+    fn from(error: TlsError) -> io::Error {
+        match error {
+            TlsError::Tor(e) => e.into(),
+            TlsError::Io(e) => e,
+            error => io::Error::new(io::ErrorKind::InvalidData, error),
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use super::OnionError;
+use crate::{TargetAddr, ToTargetAddr};
+
+use data_encoding::BASE32_NOPAD;
+use sha3::{Digest, Sha3_256};
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+const VERSION: u8 = 3;
+const SERVICE_ID_LEN: usize = 56;
+const V2_SERVICE_ID_LEN: usize = 16;
+
+/// A validated v3 onion service address.
+///
+/// The address encodes the ed25519 public key of the service together with a checksum,
+/// so parsing it catches typos before connecting.
+/// Subdomains, as in `www.<service id>.onion`, are kept and passed on to Tor.
+///
+/// ```
+/// use tor_stream::onion::OnionAddress;
+///
+/// let address: OnionAddress = "www.darkfailenbsdla5mal2mxn2uz66od5vtzd5qozslagrfzachha3f3id.onion"
+///     .parse()
+///     .expect("Invalid address");
+/// assert_eq!(address.subdomain(), Some("www"));
+/// assert_eq!(address.service_id(), "darkfailenbsdla5mal2mxn2uz66od5vtzd5qozslagrfzachha3f3id");
+///
+/// // Addresses can be used as destination together with a port
+/// let destination = (address, 80);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct OnionAddress {
+    service_id: String,
+    public_key: [u8; 32],
+    subdomain: Option<String>,
+}
+
+impl OnionAddress {
+    /// Creates the address of the service with the given ed25519 public key.
+    ///
+    /// ```
+    /// use tor_stream::onion::{OnionAddress, OnionSecretKey};
+    ///
+    /// let key = OnionSecretKey::generate().expect("Failed to generate key");
+    /// let address = OnionAddress::from_public_key(&key.public_key());
+    /// assert_eq!(address.to_string().parse::<OnionAddress>().unwrap(), address);
+    /// ```
+    pub fn from_public_key(public_key: &[u8; 32]) -> OnionAddress {
+        let mut bytes = Vec::with_capacity(35);
+        bytes.extend_from_slice(public_key);
+        bytes.extend_from_slice(&checksum(public_key));
+        bytes.push(VERSION);
+
+        OnionAddress {
+            service_id: BASE32_NOPAD.encode(&bytes).to_ascii_lowercase(),
+            public_key: *public_key,
+            subdomain: None,
+        }
+    }
+
+    /// Returns the service ID, which is the address without subdomain and `.onion` suffix.
+    #[inline]
+    pub fn service_id(&self) -> &str {
+        &self.service_id
+    }
+
+    /// Returns the ed25519 public key of the service.
+    #[inline]
+    pub fn public_key(&self) -> &[u8; 32] {
+        &self.public_key
+    }
+
+    /// Returns the subdomain, such as `www` for `www.<service id>.onion`.
+    #[inline]
+    pub fn subdomain(&self) -> Option<&str> {
+        self.subdomain.as_deref()
+    }
+
+    /// Parses a lowercase service ID without subdomain and `.onion` suffix.
+    fn from_service_id(service_id: &str) -> Result<OnionAddress, OnionError> {
+        if service_id.len() == V2_SERVICE_ID_LEN {
+            return Err(OnionError::V2Address);
+        }
+        if service_id.len() != SERVICE_ID_LEN {
+            return Err(OnionError::InvalidAddress(
+                "service ID must be 56 characters long",
+            ));
+        }
+
+        let bytes = BASE32_NOPAD
+            .decode(service_id.to_ascii_uppercase().as_bytes())
+            .map_err(|_| OnionError::InvalidAddress("invalid base32"))?;
+        let mut public_key = [0; 32];
+        public_key.copy_from_slice(&bytes[..32]);
+        if bytes[34] != VERSION {
+            return Err(OnionError::InvalidAddress("unsupported version"));
+        }
+        if bytes[32..34] != checksum(&public_key) {
+            return Err(OnionError::InvalidAddress("invalid checksum"));
+        }
+
+        Ok(OnionAddress {
+            service_id: service_id.to_owned(),
+            public_key,
+            subdomain: None,
+        })
+    }
+}
+
+/// Computes the two checksum bytes of a v3 address.
+fn checksum(public_key: &[u8; 32]) -> [u8; 2] {
+    let mut hasher = Sha3_256::new();
+    hasher.update(b".onion checksum");
+    hasher.update(public_key);
+    hasher.update([VERSION]);
+    let hash = hasher.finalize();
+    [hash[0], hash[1]]
+}
+
+impl FromStr for OnionAddress {
+    type Err = OnionError;
+
+    fn from_str(s: &str) -> Result<OnionAddress, OnionError> {
+        // Host names are case-insensitive
+        let s = s.to_ascii_lowercase();
+        let host = s
+            .strip_suffix(".onion")
+            .ok_or(OnionError::InvalidAddress("missing .onion suffix"))?;
+        let (subdomain, service_id) = match host.rfind('.') {
+            Some(i) => (Some(&host[..i]), &host[i + 1..]),
+            None => (None, host),
+        };
+
+        let mut address = OnionAddress::from_service_id(service_id)?;
+        if let Some(subdomain) = subdomain {
+            if subdomain.split('.').any(str::is_empty) {
+                return Err(OnionError::InvalidAddress("empty subdomain label"));
+            }
+            address.subdomain = Some(subdomain.to_owned());
+        }
+        Ok(address)
+    }
+}
+
+impl fmt::Display for OnionAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(subdomain) = &self.subdomain {
+            write!(f, "{}.", subdomain)?;
+        }
+        write!(f, "{}.onion", self.service_id)
+    }
+}
+
+impl ToTargetAddr for (OnionAddress, u16) {
+    fn to_target_addr(&self) -> io::Result<TargetAddr> {
+        (&self.0, self.1).to_target_addr()
+    }
+
+    fn may_be_resolved(&self) -> bool {
+        false
+    }
+}
+
+impl ToTargetAddr for (&OnionAddress, u16) {
+    fn to_target_addr(&self) -> io::Result<TargetAddr> {
+        Ok(TargetAddr::Domain(self.0.to_string(), self.1))
+    }
+
+    fn may_be_resolved(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,307 @@
+use super::{OnionAddress, OnionError};
+use crate::control::{parse_key_values, ControlError, TorControl};
+
+use curve25519_dalek::MontgomeryPoint;
+use data_encoding::{BASE32_NOPAD, BASE64};
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::str::FromStr;
+
+const AUTH_PREFIX: &str = "descriptor:x25519:";
+const MAX_CLIENT_NAME_LEN: usize = 16;
+
+/// The x25519 secret key of a client authorized to connect to an onion service.
+///
+/// Clients use it to decrypt the descriptor of a service which requires client authorization.
+/// The matching [`ClientAuthPublicKey`] has to be added to the service.
+///
+/// [`ClientAuthPublicKey`]: struct.ClientAuthPublicKey.html
+#[derive(Clone, PartialEq, Eq)]
+pub struct ClientAuthKey([u8; 32]);
+
+/// The x25519 public key of a client authorized to connect to an onion service.
+///
+/// Services store it in `authorized_clients/<name>.auth` files in their `HiddenServiceDir`,
+/// or receive it through [`TorListenerBuilder::authorized_client()`].
+///
+/// [`TorListenerBuilder::authorized_client()`]: struct.TorListenerBuilder.html#method.authorized_client
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ClientAuthPublicKey([u8; 32]);
+
+/// The credentials of a client for a single onion service.
+///
+/// They can be read from and written to the `.auth_private` files of the `ClientOnionAuthDir` option,
+/// or be added to a running Tor with [`TorControl::onion_client_auth_add()`].
+///
+/// ```no_run
+/// use tor_stream::control::{TorControl, TOR_CONTROL};
+/// use tor_stream::onion::ClientAuth;
+/// use tor_stream::TorStream;
+///
+/// let auth: ClientAuth = std::fs::read_to_string("service.auth_private")
+///     .expect("Failed to read credentials")
+///     .parse()
+///     .expect("Invalid credentials");
+///
+/// let mut control = TorControl::connect(*TOR_CONTROL).expect("Failed to connect");
+/// control.authenticate_auto().expect("Failed to authenticate");
+/// control.onion_client_auth_add(&auth).expect("Failed to add credentials");
+///
+/// let stream = TorStream::connect((&auth.address, 80)).expect("Failed to connect");
+/// ```
+///
+/// [`TorControl::onion_client_auth_add()`]: ../control/struct.TorControl.html#method.onion_client_auth_add
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientAuth {
+    /// The address of the service.
+    pub address: OnionAddress,
+    /// The secret key of the client.
+    pub key: ClientAuthKey,
+    /// An optional nickname for the credentials, stored by Tor.
+    ///
+    /// Tor accepts up to 16 ASCII letters, digits, `-` and `_`.
+    pub client_name: Option<String>,
+    /// Whether Tor stores the credentials in its `ClientOnionAuthDir`, so they survive a restart.
+    pub permanent: bool,
+}
+
+impl ClientAuthKey {
+    /// Generates a new random key.
+    pub fn generate() -> io::Result<ClientAuthKey> {
+        let mut key = [0; 32];
+        getrandom::getrandom(&mut key).map_err(io::Error::from)?;
+        key[0] &= 248;
+        key[31] &= 127;
+        key[31] |= 64;
+        Ok(ClientAuthKey(key))
+    }
+
+    /// Creates a key from its 32 bytes.
+    #[inline]
+    pub fn from_bytes(bytes: [u8; 32]) -> ClientAuthKey {
+        ClientAuthKey(bytes)
+    }
+
+    /// Returns the 32 bytes of the key.
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// Returns the public key, which has to be added to the service.
+    pub fn public_key(&self) -> ClientAuthPublicKey {
+        ClientAuthPublicKey(MontgomeryPoint::mul_base_clamped(self.0).to_bytes())
+    }
+
+    /// Parses the base32 encoding used in `.auth_private` files.
+    pub fn from_base32(s: &str) -> Result<ClientAuthKey, OnionError> {
+        decode_base32(s).map(ClientAuthKey)
+    }
+
+    /// Returns the base32 encoding used in `.auth_private` files.
+    pub fn to_base32(&self) -> String {
+        BASE32_NOPAD.encode(&self.0)
+    }
+}
+
+impl fmt::Debug for ClientAuthKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Avoid leaking the key into logs
+        f.write_str("ClientAuthKey(..)")
+    }
+}
+
+impl ClientAuthPublicKey {
+    /// Creates a key from its 32 bytes.
+    #[inline]
+    pub fn from_bytes(bytes: [u8; 32]) -> ClientAuthPublicKey {
+        ClientAuthPublicKey(bytes)
+    }
+
+    /// Returns the 32 bytes of the key.
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// Parses the base32 encoding used by `ADD_ONION`.
+    pub fn from_base32(s: &str) -> Result<ClientAuthPublicKey, OnionError> {
+        decode_base32(s).map(ClientAuthPublicKey)
+    }
+
+    /// Returns the base32 encoding used by `ADD_ONION`.
+    pub fn to_base32(&self) -> String {
+        BASE32_NOPAD.encode(&self.0)
+    }
+
+    /// Parses the contents of a `.auth` file, `descriptor:x25519:<base32 key>`.
+    pub fn from_auth(s: &str) -> Result<ClientAuthPublicKey, OnionError> {
+        let key = s
+            .trim_end()
+            .strip_prefix(AUTH_PREFIX)
+            .ok_or(OnionError::InvalidKey("expected descriptor:x25519 key"))?;
+        ClientAuthPublicKey::from_base32(key)
+    }
+
+    /// Returns the contents of a `.auth` file, `descriptor:x25519:<base32 key>`.
+    pub fn to_auth(&self) -> String {
+        format!("{}{}\n", AUTH_PREFIX, self.to_base32())
+    }
+}
+
+impl ClientAuth {
+    /// Creates non-permanent credentials without client name.
+    pub fn new(address: OnionAddress, key: ClientAuthKey) -> ClientAuth {
+        ClientAuth {
+            address,
+            key,
+            client_name: None,
+            permanent: false,
+        }
+    }
+
+    /// Parses the contents of an `.auth_private` file,
+    /// `<service id>:descriptor:x25519:<base32 key>`.
+    pub fn from_auth_private(s: &str) -> Result<ClientAuth, OnionError> {
+        let (service_id, key) = s.trim_end().split_once(':').ok_or(OnionError::InvalidKey(
+            "expected <service id>:descriptor:x25519:<key>",
+        ))?;
+        let address = if service_id.ends_with(".onion") {
+            service_id.parse()?
+        } else {
+            format!("{}.onion", service_id).parse()?
+        };
+        let key = key
+            .strip_prefix(AUTH_PREFIX)
+            .ok_or(OnionError::InvalidKey("expected descriptor:x25519 key"))?;
+        Ok(ClientAuth::new(address, ClientAuthKey::from_base32(key)?))
+    }
+
+    /// Returns the contents of an `.auth_private` file,
+    /// `<service id>:descriptor:x25519:<base32 key>`.
+    pub fn to_auth_private(&self) -> String {
+        format!(
+            "{}:{}{}\n",
+            self.address.service_id(),
+            AUTH_PREFIX,
+            self.key.to_base32()
+        )
+    }
+}
+
+impl FromStr for ClientAuth {
+    type Err = OnionError;
+
+    fn from_str(s: &str) -> Result<ClientAuth, OnionError> {
+        ClientAuth::from_auth_private(s)
+    }
+}
+
+fn decode_base32(s: &str) -> Result<[u8; 32], OnionError> {
+    let bytes = BASE32_NOPAD
+        .decode(s.to_ascii_uppercase().as_bytes())
+        .map_err(|_| OnionError::InvalidKey("invalid base32"))?;
+    let mut key = [0; 32];
+    if bytes.len() != key.len() {
+        return Err(OnionError::InvalidKey("key must be 32 bytes long"));
+    }
+    key.copy_from_slice(&bytes);
+    Ok(key)
+}
+
+/// Client names are command arguments, so they must not contain spaces or other separators.
+fn is_valid_client_name(name: &str) -> bool {
+    (1..=MAX_CLIENT_NAME_LEN).contains(&name.len())
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+impl<S: Read + Write> TorControl<S> {
+    /// Adds client credentials for an onion service with `ONION_CLIENT_AUTH_ADD`.
+    ///
+    /// Existing credentials for the same service are replaced.
+    /// Fails with `ErrorKind::InvalidInput` before sending the command
+    /// if the [`client_name`] is not a valid nickname.
+    ///
+    /// [`client_name`]: ../onion/struct.ClientAuth.html#structfield.client_name
+    pub fn onion_client_auth_add(&mut self, auth: &ClientAuth) -> Result<(), ControlError> {
+        let mut command = format!(
+            "ONION_CLIENT_AUTH_ADD {} x25519:{}",
+            auth.address.service_id(),
+            BASE64.encode(auth.key.as_bytes())
+        );
+        if let Some(name) = &auth.client_name {
+            if !is_valid_client_name(name) {
+                return Err(ControlError::Io(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "client name must be 1 to 16 ASCII letters, digits, '-' or '_'",
+                )));
+            }
+            command.push_str(&format!(" ClientName={}", name));
+        }
+        if auth.permanent {
+            command.push_str(" Flags=Permanent");
+        }
+        self.send(&command).map(drop)
+    }
+
+    /// Removes the client credentials for an onion service with `ONION_CLIENT_AUTH_REMOVE`.
+    pub fn onion_client_auth_remove(&mut self, address: &OnionAddress) -> Result<(), ControlError> {
+        self.send(&format!(
+            "ONION_CLIENT_AUTH_REMOVE {}",
+            address.service_id()
+        ))
+        .map(drop)
+    }
+
+    /// Lists the client credentials known to Tor with `ONION_CLIENT_AUTH_VIEW`,
+    /// optionally only those for a single onion service.
+    pub fn onion_client_auth_view(
+        &mut self,
+        address: Option<&OnionAddress>,
+    ) -> Result<Vec<ClientAuth>, ControlError> {
+        let mut command = String::from("ONION_CLIENT_AUTH_VIEW");
+        if let Some(address) = address {
+            command.push(' ');
+            command.push_str(address.service_id());
+        }
+        let reply = self.send(&command)?;
+
+        let mut credentials = Vec::new();
+        for line in reply.lines() {
+            let rest = match line.text().strip_prefix("CLIENT ") {
+                Some(rest) => rest,
+                None => continue,
+            };
+            let mut words = rest.splitn(3, ' ');
+            let (service_id, key) = match (words.next(), words.next()) {
+                (Some(service_id), Some(key)) => (service_id, key),
+                _ => return Err(ControlError::Protocol("invalid CLIENT line")),
+            };
+
+            let address = format!("{}.onion", service_id)
+                .parse()
+                .map_err(|_| ControlError::Protocol("invalid onion address"))?;
+            let key = key
+                .strip_prefix("x25519:")
+                .and_then(|key| BASE64.decode(key.as_bytes()).ok())
+                .filter(|key| key.len() == 32)
+                .ok_or(ControlError::Protocol("invalid client key"))?;
+            let mut bytes = [0; 32];
+            bytes.copy_from_slice(&key);
+
+            let mut auth = ClientAuth::new(address, ClientAuthKey(bytes));
+            for (key, value) in parse_key_values(words.next().unwrap_or(""))? {
+                match key.as_str() {
+                    "ClientName" => auth.client_name = Some(value),
+                    "Flags" => auth.permanent = value.split(',').any(|flag| flag == "Permanent"),
+                    _ => {}
+                }
+            }
+            credentials.push(auth);
+        }
+
+        Ok(credentials)
+    }
+}
@@ -0,0 +1,35 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// An error that occurred while parsing onion service keys or addresses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum OnionError {
+    /// The key is malformed.
+    InvalidKey(&'static str),
+    /// The address is not a valid v3 onion address.
+    InvalidAddress(&'static str),
+    /// The address is a v2 onion address, which Tor no longer supports.
+    V2Address,
+}
+
+impl fmt::Display for OnionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OnionError::InvalidKey(message) => write!(f, "invalid onion service key: {}", message),
+            OnionError::InvalidAddress(message) => write!(f, "invalid onion address: {}", message),
+            OnionError::V2Address => f.write_str(
+                "v2 onion addresses are deprecated and no longer supported by Tor, use a v3 address",
+            ),
+        }
+    }
+}
+
+impl Error for OnionError {}
+
+impl From<OnionError> for io::Error {
+    fn from(error: OnionError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, error)
+    }
+}
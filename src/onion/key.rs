@@ -0,0 +1,195 @@
+use super::{OnionAddress, OnionError};
+
+use curve25519_dalek::EdwardsPoint;
+use data_encoding::{BASE64, BASE64_NOPAD};
+use sha2::{Digest, Sha512};
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt};
+
+const SECRET_KEY_HEADER: &[u8; 32] = b"== ed25519v1-secret: type0 ==\0\0\0";
+const PUBLIC_KEY_HEADER: &[u8; 32] = b"== ed25519v1-public: type0 ==\0\0\0";
+const BLOB_PREFIX: &str = "ED25519-V3:";
+
+/// The secret key of a v3 onion service.
+///
+/// This is an expanded ed25519 secret key, as Tor stores it: the clamped scalar
+/// followed by the 32 bytes used to derive signature nonces.
+/// It determines the `.onion` address, so keeping it allows recreating a service at the same address.
+///
+/// The key can be converted from and to the `ED25519-V3:<base64>` format of `ADD_ONION`,
+/// and read from and written to `hs_ed25519_secret_key` files of a `HiddenServiceDir`.
+/// New keys can be generated without Tor.
+///
+/// ```
+/// use tor_stream::onion::OnionSecretKey;
+///
+/// let key = OnionSecretKey::generate().expect("Failed to generate key");
+/// println!("Generated {}", key.onion_address());
+///
+/// let blob = key.to_blob();
+/// assert!(blob.starts_with("ED25519-V3:"));
+/// assert_eq!(blob.parse::<OnionSecretKey>().unwrap(), key);
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct OnionSecretKey([u8; 64]);
+
+impl OnionSecretKey {
+    /// Generates a new random key.
+    pub fn generate() -> io::Result<OnionSecretKey> {
+        let mut seed = [0; 32];
+        getrandom::getrandom(&mut seed).map_err(io::Error::from)?;
+        Ok(OnionSecretKey::from_seed(seed))
+    }
+
+    /// Expands a 32-byte ed25519 secret key, the format used by most other ed25519 implementations.
+    pub fn from_seed(seed: [u8; 32]) -> OnionSecretKey {
+        let mut key = [0; 64];
+        key.copy_from_slice(&Sha512::digest(seed));
+        key[0] &= 248;
+        key[31] &= 127;
+        key[31] |= 64;
+        OnionSecretKey(key)
+    }
+
+    /// Creates a key from its 64 bytes.
+    #[inline]
+    pub fn from_bytes(bytes: [u8; 64]) -> OnionSecretKey {
+        OnionSecretKey(bytes)
+    }
+
+    /// Returns the 64 bytes of the key.
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8; 64] {
+        &self.0
+    }
+
+    /// Returns the ed25519 public key.
+    pub fn public_key(&self) -> [u8; 32] {
+        let mut scalar = [0; 32];
+        scalar.copy_from_slice(&self.0[..32]);
+        EdwardsPoint::mul_base_clamped(scalar).compress().to_bytes()
+    }
+
+    /// Returns the address of the service with this key.
+    pub fn onion_address(&self) -> OnionAddress {
+        OnionAddress::from_public_key(&self.public_key())
+    }
+
+    /// Parses the `ED25519-V3:<base64>` format used by `ADD_ONION`.
+    pub fn from_blob(blob: &str) -> Result<OnionSecretKey, OnionError> {
+        let encoded = blob
+            .strip_prefix(BLOB_PREFIX)
+            .ok_or(OnionError::InvalidKey("expected ED25519-V3 key"))?;
+        let bytes = BASE64
+            .decode(encoded.as_bytes())
+            .or_else(|_| BASE64_NOPAD.decode(encoded.as_bytes()))
+            .map_err(|_| OnionError::InvalidKey("invalid base64"))?;
+        OnionSecretKey::from_slice(&bytes)
+    }
+
+    /// Returns the `ED25519-V3:<base64>` format used by `ADD_ONION`.
+    pub fn to_blob(&self) -> String {
+        format!("{}{}", BLOB_PREFIX, BASE64.encode(&self.0))
+    }
+
+    /// Parses the contents of an `hs_ed25519_secret_key` file.
+    pub fn from_file_bytes(bytes: &[u8]) -> Result<OnionSecretKey, OnionError> {
+        let key = bytes
+            .strip_prefix(&SECRET_KEY_HEADER[..])
+            .ok_or(OnionError::InvalidKey(
+                "missing hs_ed25519_secret_key header",
+            ))?;
+        OnionSecretKey::from_slice(key)
+    }
+
+    /// Returns the contents of an `hs_ed25519_secret_key` file.
+    pub fn to_file_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(SECRET_KEY_HEADER.len() + self.0.len());
+        bytes.extend_from_slice(SECRET_KEY_HEADER);
+        bytes.extend_from_slice(&self.0);
+        bytes
+    }
+
+    /// Reads an `hs_ed25519_secret_key` file.
+    pub fn read_from_file(path: impl AsRef<Path>) -> io::Result<OnionSecretKey> {
+        let bytes = fs::read(path)?;
+        Ok(OnionSecretKey::from_file_bytes(&bytes)?)
+    }
+
+    /// Writes an `hs_ed25519_secret_key` file.
+    ///
+    /// On Unix, the file is only accessible by the current user.
+    pub fn write_to_file(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let mut file = options.open(path)?;
+        // The mode only applies to new files, so an existing file is restricted as well
+        #[cfg(unix)]
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+        file.write_all(&self.to_file_bytes())
+    }
+
+    /// Writes the files of a `HiddenServiceDir`, which Tor loads to host the service.
+    ///
+    /// The directory is created if necessary and receives the `hostname`,
+    /// `hs_ed25519_public_key` and `hs_ed25519_secret_key` files.
+    /// Tor refuses directories which are accessible by other users,
+    /// so on Unix the permissions of the directory are restricted to the current user.
+    ///
+    /// ```no_run
+    /// use tor_stream::onion::OnionSecretKey;
+    ///
+    /// let key = OnionSecretKey::generate().expect("Failed to generate key");
+    /// key.write_service_dir("/var/lib/tor/my_service").expect("Failed to write service");
+    /// // torrc: HiddenServiceDir /var/lib/tor/my_service
+    /// ```
+    pub fn write_service_dir(&self, dir: impl AsRef<Path>) -> io::Result<()> {
+        let dir = dir.as_ref();
+        let mut builder = fs::DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        builder.mode(0o700);
+        builder.create(dir)?;
+        #[cfg(unix)]
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))?;
+
+        let mut public_key = PUBLIC_KEY_HEADER.to_vec();
+        public_key.extend_from_slice(&self.public_key());
+
+        self.write_to_file(dir.join("hs_ed25519_secret_key"))?;
+        fs::write(dir.join("hs_ed25519_public_key"), public_key)?;
+        fs::write(dir.join("hostname"), format!("{}\n", self.onion_address()))
+    }
+
+    fn from_slice(bytes: &[u8]) -> Result<OnionSecretKey, OnionError> {
+        let mut key = [0; 64];
+        if bytes.len() != key.len() {
+            return Err(OnionError::InvalidKey("key must be 64 bytes long"));
+        }
+        key.copy_from_slice(bytes);
+        Ok(OnionSecretKey(key))
+    }
+}
+
+impl FromStr for OnionSecretKey {
+    type Err = OnionError;
+
+    fn from_str(s: &str) -> Result<OnionSecretKey, OnionError> {
+        OnionSecretKey::from_blob(s)
+    }
+}
+
+impl fmt::Debug for OnionSecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Avoid leaking the key into logs
+        f.write_str("OnionSecretKey(..)")
+    }
+}
@@ -0,0 +1,343 @@
+use super::{ClientAuthPublicKey, OnionAddress, OnionSecretKey};
+use crate::control::{ControlError, TorControl};
+
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+
+/// An onion service which accepts connections like a `TcpListener`.
+///
+/// The service is created with `ADD_ONION` and forwards connections on its virtual port
+/// to a `TcpListener` bound to a random local port, unless set with [`TorListenerBuilder::local_addr()`].
+/// It is removed with `DEL_ONION` when the listener is dropped.
+/// Tor also removes it if the control connection is closed, so the listener keeps it open,
+/// unless it was created with [`TorListenerBuilder::detach()`].
+///
+/// Tor publishes the service descriptor in the background,
+/// so it may take a moment until the service is reachable.
+///
+/// [`TorListenerBuilder::detach()`]: struct.TorListenerBuilder.html#method.detach
+/// [`TorListenerBuilder::local_addr()`]: struct.TorListenerBuilder.html#method.local_addr
+#[derive(Debug)]
+pub struct TorListener<S: Read + Write = TcpStream> {
+    listener: TcpListener,
+    control: TorControl<S>,
+    address: OnionAddress,
+    private_key: OnionSecretKey,
+    virtual_port: u16,
+    detached: bool,
+    resumed: bool,
+}
+
+/// A builder for configuring how a [`TorListener`] is created.
+///
+/// ```no_run
+/// use tor_stream::control::{TorControl, TOR_CONTROL};
+/// use tor_stream::onion::{OnionSecretKey, TorListenerBuilder};
+///
+/// let mut control = TorControl::connect(*TOR_CONTROL).expect("Failed to connect");
+/// control.authenticate_auto().expect("Failed to authenticate");
+///
+/// let key = OnionSecretKey::read_from_file("hs_ed25519_secret_key").expect("Failed to read key");
+/// let listener = TorListenerBuilder::new()
+///     .key(key)
+///     .bind(control, 80)
+///     .expect("Failed to create onion service");
+/// ```
+///
+/// [`TorListener`]: struct.TorListener.html
+#[derive(Debug, Clone, Default)]
+pub struct TorListenerBuilder {
+    key: Option<OnionSecretKey>,
+    detach: bool,
+    local_addr: Option<SocketAddr>,
+    authorized_clients: Vec<ClientAuthPublicKey>,
+}
+
+/// An iterator over the connections of a [`TorListener`].
+///
+/// [`TorListener`]: struct.TorListener.html
+#[derive(Debug)]
+pub struct Incoming<'a, S: Read + Write> {
+    listener: &'a TorListener<S>,
+}
+
+impl<S: Read + Write> TorListener<S> {
+    /// Creates a new onion service with a fresh ed25519 key,
+    /// reachable on `virtual_port` of its `.onion` address.
+    ///
+    /// The control connection must already be authenticated.
+    /// Use a [`TorListenerBuilder`] to reuse an existing key.
+    ///
+    /// [`TorListenerBuilder`]: struct.TorListenerBuilder.html
+    pub fn bind(control: TorControl<S>, virtual_port: u16) -> Result<Self, ControlError> {
+        TorListenerBuilder::new().bind(control, virtual_port)
+    }
+
+    /// Returns the service ID, which is the `.onion` address without the suffix.
+    #[inline]
+    pub fn service_id(&self) -> &str {
+        self.address.service_id()
+    }
+
+    /// Returns the `.onion` address of the service.
+    #[inline]
+    pub fn onion_address(&self) -> &OnionAddress {
+        &self.address
+    }
+
+    /// Returns the port on the `.onion` address which clients connect to.
+    ///
+    /// For a [resumed] service, this is the requested port,
+    /// as Tor does not report the ports of a running service.
+    ///
+    /// [resumed]: #method.is_resumed
+    #[inline]
+    pub fn virtual_port(&self) -> u16 {
+        self.virtual_port
+    }
+
+    /// Returns the secret key of the service.
+    ///
+    /// Passing it to [`TorListenerBuilder::key()`] recreates the service at the same address.
+    ///
+    /// [`TorListenerBuilder::key()`]: struct.TorListenerBuilder.html#method.key
+    #[inline]
+    pub fn private_key(&self) -> &OnionSecretKey {
+        &self.private_key
+    }
+
+    /// Returns whether the service outlives the control connection.
+    #[inline]
+    pub fn is_detached(&self) -> bool {
+        self.detached
+    }
+
+    /// Returns whether a detached service which was already running has been resumed,
+    /// instead of creating the service.
+    ///
+    /// Tor only confirms that a detached service with the key is running.
+    /// Its virtual port, local address and other settings are the ones it was created with,
+    /// which may differ from the ones requested.
+    /// See [`TorListenerBuilder::detach()`].
+    ///
+    /// [`TorListenerBuilder::detach()`]: struct.TorListenerBuilder.html#method.detach
+    #[inline]
+    pub fn is_resumed(&self) -> bool {
+        self.resumed
+    }
+
+    /// Returns the local address which Tor forwards connections to.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accepts a new connection.
+    ///
+    /// The returned address is the local address of Tor, as the client is anonymous.
+    pub fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
+        self.listener.accept()
+    }
+
+    /// Returns an iterator over incoming connections, which never returns `None`.
+    pub fn incoming(&self) -> Incoming<'_, S> {
+        Incoming { listener: self }
+    }
+
+    /// Gets a reference to the underlying `TcpListener`.
+    #[inline]
+    pub fn get_ref(&self) -> &TcpListener {
+        &self.listener
+    }
+
+    /// Gets a mutable reference to the control connection.
+    #[inline]
+    pub fn control_mut(&mut self) -> &mut TorControl<S> {
+        &mut self.control
+    }
+}
+
+impl TorListenerBuilder {
+    /// Creates a builder which generates a new key and removes the service when dropped.
+    pub fn new() -> TorListenerBuilder {
+        TorListenerBuilder::default()
+    }
+
+    /// Sets the secret key of the service, which determines its `.onion` address.
+    pub fn key(mut self, key: OnionSecretKey) -> TorListenerBuilder {
+        self.key = Some(key);
+        self
+    }
+
+    /// Sets whether the service outlives the control connection and the listener,
+    /// using the `Detach` flag of `ADD_ONION`.
+    ///
+    /// A detached service keeps running until Tor exits or it is removed with
+    /// [`TorControl::del_onion()`].
+    /// Connections are still forwarded to the local port of the listener,
+    /// so the service is unreachable while the listener is not running.
+    ///
+    /// To resume a detached service after the program was restarted,
+    /// bind again with the same [`key()`] and [`local_addr()`].
+    /// Tor refuses to add the service a second time, so if `GETINFO onions/detached`
+    /// lists the service, the running service is reused.
+    /// Tor does not report how a running service forwards connections,
+    /// so it must have been created with the same virtual port, local address
+    /// and authorized clients.
+    /// [`TorListener::is_resumed()`] tells whether this happened.
+    ///
+    /// [`TorControl::del_onion()`]: ../control/struct.TorControl.html#method.del_onion
+    /// [`key()`]: #method.key
+    /// [`local_addr()`]: #method.local_addr
+    /// [`TorListener::is_resumed()`]: struct.TorListener.html#method.is_resumed
+    pub fn detach(mut self, detach: bool) -> TorListenerBuilder {
+        self.detach = detach;
+        self
+    }
+
+    /// Sets the local address which Tor forwards connections to,
+    /// instead of a random port on `127.0.0.1`.
+    ///
+    /// ```no_run
+    /// use tor_stream::control::{TorControl, TOR_CONTROL};
+    /// use tor_stream::onion::{OnionSecretKey, TorListenerBuilder};
+    ///
+    /// let mut control = TorControl::connect(*TOR_CONTROL).expect("Failed to connect");
+    /// control.authenticate_auto().expect("Failed to authenticate");
+    ///
+    /// // Resumes the service if it is still running from before a restart
+    /// let key = OnionSecretKey::read_from_file("hs_ed25519_secret_key").expect("Failed to read key");
+    /// let listener = TorListenerBuilder::new()
+    ///     .key(key)
+    ///     .detach(true)
+    ///     .local_addr("127.0.0.1:8080".parse().unwrap())
+    ///     .bind(control, 80)
+    ///     .expect("Failed to create onion service");
+    /// ```
+    pub fn local_addr(mut self, address: SocketAddr) -> TorListenerBuilder {
+        self.local_addr = Some(address);
+        self
+    }
+
+    /// Restricts access to clients holding the secret key of `client`.
+    ///
+    /// Can be called multiple times to authorize several clients.
+    /// Without authorized clients, everyone knowing the address can connect.
+    pub fn authorized_client(mut self, client: ClientAuthPublicKey) -> TorListenerBuilder {
+        self.authorized_clients.push(client);
+        self
+    }
+
+    /// Creates the onion service, reachable on `virtual_port` of its `.onion` address.
+    ///
+    /// The control connection must already be authenticated.
+    /// If a detached service with the same key is already running and a local address is set,
+    /// it is resumed instead, as described for [`detach()`].
+    ///
+    /// [`detach()`]: #method.detach
+    pub fn bind<S: Read + Write>(
+        &self,
+        mut control: TorControl<S>,
+        virtual_port: u16,
+    ) -> Result<TorListener<S>, ControlError> {
+        let listener = match self.local_addr {
+            Some(address) => TcpListener::bind(address)?,
+            None => TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?,
+        };
+        let target = listener.local_addr()?;
+
+        let mut command = String::from("ADD_ONION ");
+        match &self.key {
+            Some(key) => command.push_str(&key.to_blob()),
+            None => command.push_str("NEW:ED25519-V3"),
+        }
+        let mut flags = Vec::new();
+        if self.detach {
+            flags.push("Detach");
+        }
+        if !self.authorized_clients.is_empty() {
+            flags.push("V3Auth");
+        }
+        if !flags.is_empty() {
+            command.push_str(&format!(" Flags={}", flags.join(",")));
+        }
+        command.push_str(&format!(" Port={},{}", virtual_port, target));
+        for client in &self.authorized_clients {
+            command.push_str(&format!(" ClientAuthV3={}", client.to_base32()));
+        }
+
+        let reply = match (control.send(&command), &self.key) {
+            (Ok(reply), _) => reply,
+            (Err(ControlError::Reply(reply)), Some(key))
+                if self.detach
+                    && self.local_addr.is_some()
+                    && reply.code() == 550
+                    && reply.message().contains("collision") =>
+            {
+                // The key may also be in use by a service of another control connection
+                let address = key.onion_address();
+                let detached = control.get_info("onions/detached")?;
+                if !detached
+                    .split_whitespace()
+                    .any(|service_id| service_id == address.service_id())
+                {
+                    return Err(ControlError::Reply(reply));
+                }
+                return Ok(TorListener {
+                    listener,
+                    control,
+                    address,
+                    private_key: key.clone(),
+                    virtual_port,
+                    detached: true,
+                    resumed: true,
+                });
+            }
+            (Err(e), _) => return Err(e),
+        };
+        let mut address = None;
+        let mut private_key = self.key.clone();
+        for (key, value) in reply.lines().iter().filter_map(|line| line.key_value()) {
+            match key {
+                "ServiceID" => {
+                    let service_id = format!("{}.onion", value)
+                        .parse()
+                        .map_err(|_| ControlError::Protocol("invalid ServiceID"))?;
+                    address = Some(service_id);
+                }
+                "PrivateKey" => {
+                    let key = OnionSecretKey::from_blob(value)
+                        .map_err(|_| ControlError::Protocol("invalid PrivateKey"))?;
+                    private_key = Some(key);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(TorListener {
+            listener,
+            control,
+            address: address.ok_or(ControlError::Protocol("missing ServiceID"))?,
+            private_key: private_key.ok_or(ControlError::Protocol("missing PrivateKey"))?,
+            virtual_port,
+            detached: self.detach,
+            resumed: false,
+        })
+    }
+}
+
+impl<S: Read + Write> Iterator for Incoming<'_, S> {
+    type Item = io::Result<TcpStream>;
+
+    fn next(&mut self) -> Option<io::Result<TcpStream>> {
+        Some(self.listener.accept().map(|(stream, _)| stream))
+    }
+}
+
+impl<S: Read + Write> Drop for TorListener<S> {
+    fn drop(&mut self) {
+        if !self.detached {
+            // Tor removes the service anyway when the control connection is closed
+            let _ = self.control.del_onion(self.address.service_id());
+        }
+    }
+}
@@ -0,0 +1,51 @@
+//! Onion service addresses, and hosting onion services through the Tor control port.
+//!
+//! A [`TorListener`] creates an onion service with the `ADD_ONION` control command
+//! and accepts the connections which Tor forwards to it.
+//!
+//! ```no_run
+//! use tor_stream::control::{TorControl, TOR_CONTROL};
+//! use tor_stream::onion::TorListener;
+//! use std::io::Write;
+//!
+//! let mut control = TorControl::connect(*TOR_CONTROL).expect("Failed to connect");
+//! control.authenticate_auto().expect("Failed to authenticate");
+//!
+//! let listener = TorListener::bind(control, 80).expect("Failed to create onion service");
+//! println!("Listening on {}", listener.onion_address());
+//!
+//! for stream in listener.incoming() {
+//!     stream.unwrap().write_all(b"Hello from an onion service\n").unwrap();
+//! }
+//! ```
+//!
+//! Onion addresses can be validated before connecting with [`OnionAddress`].
+//!
+//! Service keys can be generated offline with [`OnionSecretKey::generate()`],
+//! and written to a directory for the `HiddenServiceDir` option of Tor.
+//!
+//! Services are ephemeral by default. A [`TorListenerBuilder`] can recreate a service
+//! from an [`OnionSecretKey`] and keep it running after the control connection is closed.
+//!
+//! Services can restrict access to clients holding a [`ClientAuthKey`].
+//! Clients pass their credentials to Tor as [`ClientAuth`] before connecting.
+//!
+//! [`OnionAddress`]: struct.OnionAddress.html
+//! [`ClientAuth`]: struct.ClientAuth.html
+//! [`ClientAuthKey`]: struct.ClientAuthKey.html
+//! [`TorListener`]: struct.TorListener.html
+//! [`TorListenerBuilder`]: struct.TorListenerBuilder.html
+//! [`OnionSecretKey`]: struct.OnionSecretKey.html
+//! [`OnionSecretKey::generate()`]: struct.OnionSecretKey.html#method.generate
+
+mod address;
+mod client_auth;
+mod error;
+mod key;
+mod listener;
+
+pub use self::address::OnionAddress;
+pub use self::client_auth::{ClientAuth, ClientAuthKey, ClientAuthPublicKey};
+pub use self::error::OnionError;
+pub use self::key::OnionSecretKey;
+pub use self::listener::{Incoming, TorListener, TorListenerBuilder};
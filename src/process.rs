@@ -0,0 +1,401 @@
+//! Launching a private Tor process.
+//!
+//! Instead of relying on a system-wide Tor installation being configured and running,
+//! a [`TorProcess`] starts its own `tor` binary with a generated configuration.
+//! The `tor` binary still has to be installed.
+//!
+//! ```no_run
+//! use tor_stream::process::TorProcess;
+//! use tor_stream::TorStream;
+//! use std::time::Duration;
+//!
+//! let mut tor = TorProcess::spawn().expect("Failed to start tor");
+//! tor.wait_for_bootstrap(Duration::from_secs(120)).expect("Failed to bootstrap");
+//!
+//! let stream = TorStream::connect_with_address(tor.socks_addr(), "www.example.com:80")
+//!     .expect("Failed to connect");
+//! ```
+//!
+//! [`TorProcess`]: struct.TorProcess.html
+
+use crate::control::{ControlError, TorControl};
+use crate::torrc::Torrc;
+
+use std::fs;
+use std::io::{self, BufRead, BufReader};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::process::{self, Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(unix)]
+use std::os::unix::fs::DirBuilderExt;
+
+/// How long to wait for Tor to open its SOCKS and control ports after starting.
+const LISTENER_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A builder for configuring how a [`TorProcess`] is started.
+///
+/// ```no_run
+/// use tor_stream::process::TorProcessBuilder;
+///
+/// let tor = TorProcessBuilder::new()
+///     .tor_path("/usr/local/bin/tor")
+///     .data_dir("/var/cache/my-app/tor")
+///     .option("ExitNodes", "{de}")
+///     .spawn()
+///     .expect("Failed to start tor");
+/// ```
+///
+/// [`TorProcess`]: struct.TorProcess.html
+#[derive(Debug, Clone)]
+pub struct TorProcessBuilder {
+    tor_path: PathBuf,
+    data_dir: Option<PathBuf>,
+    options: Vec<(String, String)>,
+}
+
+/// A `tor` process owned by this program.
+///
+/// The process listens on local ports chosen by Tor for SOCKS and control connections,
+/// and keeps its state in a private data directory.
+/// It is killed when the `TorProcess` is dropped, which also removes a temporary data directory.
+///
+/// Tor needs some time to connect to the network after starting,
+/// so wait with [`wait_for_bootstrap()`] before connecting.
+///
+/// [`wait_for_bootstrap()`]: #method.wait_for_bootstrap
+#[derive(Debug)]
+pub struct TorProcess {
+    child: Child,
+    log: Receiver<String>,
+    last_log_line: Option<String>,
+    progress: u8,
+    socks_addr: SocketAddr,
+    control_addr: SocketAddr,
+    data_dir: PathBuf,
+    temporary: bool,
+}
+
+impl TorProcessBuilder {
+    /// Creates a builder which runs `tor` from the `PATH` with a temporary data directory.
+    pub fn new() -> TorProcessBuilder {
+        TorProcessBuilder {
+            tor_path: PathBuf::from("tor"),
+            data_dir: None,
+            options: Vec::new(),
+        }
+    }
+
+    /// Sets the path of the `tor` binary.
+    pub fn tor_path(mut self, path: impl Into<PathBuf>) -> TorProcessBuilder {
+        self.tor_path = path.into();
+        self
+    }
+
+    /// Sets the data directory, which is kept after the process exits.
+    ///
+    /// Reusing the data directory speeds up bootstrapping,
+    /// because Tor does not need to download the network consensus again.
+    pub fn data_dir(mut self, path: impl Into<PathBuf>) -> TorProcessBuilder {
+        self.data_dir = Some(path.into());
+        self
+    }
+
+    /// Adds a configuration option, as it would appear in `torrc`.
+    ///
+    /// Options can be added multiple times, such as `HiddenServicePort`.
+    pub fn option(mut self, key: impl Into<String>, value: impl Into<String>) -> TorProcessBuilder {
+        self.options.push((key.into(), value.into()));
+        self
+    }
+
+    /// Starts the process.
+    ///
+    /// Blocks until Tor has opened its SOCKS and control ports,
+    /// which usually happens right after it started.
+    pub fn spawn(&self) -> io::Result<TorProcess> {
+        let (data_dir, temporary) = match &self.data_dir {
+            Some(data_dir) => {
+                create_private_dir(data_dir)?;
+                (data_dir.clone(), false)
+            }
+            None => (create_temporary_dir()?, true),
+        };
+
+        let result = self.spawn_in(&data_dir, temporary);
+        if result.is_err() && temporary {
+            let _ = fs::remove_dir_all(&data_dir);
+        }
+        result
+    }
+
+    fn spawn_in(&self, data_dir: &Path, temporary: bool) -> io::Result<TorProcess> {
+        // Tor picks free ports itself and logs them, which avoids racing other programs for them
+        let mut config = Torrc::new();
+        config.add("DataDirectory", data_dir.to_string_lossy());
+        config.add("SocksPort", "127.0.0.1:auto");
+        config.add("ControlPort", "127.0.0.1:auto");
+        config.add("CookieAuthentication", "1");
+        config.add("Log", "notice stdout");
+        config.add("__OwningControllerProcess", process::id().to_string());
+        for (key, value) in &self.options {
+            config.add(key, value.as_str());
+        }
+        let torrc = data_dir.join("torrc");
+        fs::write(&torrc, config.to_string())?;
+
+        let mut child = Command::new(&self.tor_path)
+            .arg("-f")
+            .arg(&torrc)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        // Tor logs to stdout, which is read in the background to track the bootstrap progress.
+        // Once Tor has opened its listeners and bootstrapped, the log is only drained,
+        // so the lines do not pile up in the channel when nobody reads them.
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let (sender, log) = mpsc::channel();
+        thread::spawn(move || {
+            let (mut socks, mut control, mut bootstrapped) = (false, false, false);
+            for line in BufReader::new(stdout).lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => break,
+                };
+                if socks && control && bootstrapped {
+                    continue;
+                }
+                socks |= parse_listener_addr(&line, "Socks").is_some();
+                control |= parse_listener_addr(&line, "Control").is_some();
+                bootstrapped |= parse_bootstrap_progress(&line) == Some(100);
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let unbound = SocketAddr::from((Ipv4Addr::LOCALHOST, 0));
+        let mut tor = TorProcess {
+            child,
+            log,
+            last_log_line: None,
+            progress: 0,
+            socks_addr: unbound,
+            control_addr: unbound,
+            data_dir: data_dir.to_owned(),
+            temporary,
+        };
+        tor.wait_for_listeners()?;
+        Ok(tor)
+    }
+}
+
+impl Default for TorProcessBuilder {
+    fn default() -> TorProcessBuilder {
+        TorProcessBuilder::new()
+    }
+}
+
+impl TorProcess {
+    /// Starts `tor` from the `PATH` with a temporary data directory.
+    pub fn spawn() -> io::Result<TorProcess> {
+        TorProcessBuilder::new().spawn()
+    }
+
+    /// Returns a builder to configure the process.
+    pub fn builder() -> TorProcessBuilder {
+        TorProcessBuilder::new()
+    }
+
+    /// Blocks until Tor has finished bootstrapping, or `timeout` has elapsed.
+    ///
+    /// Returns an error of kind `TimedOut` on timeout,
+    /// or of kind `UnexpectedEof` if Tor exited, including the last line it logged.
+    pub fn wait_for_bootstrap(&mut self, timeout: Duration) -> io::Result<()> {
+        let deadline = Instant::now() + timeout;
+        while self.progress < 100 {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.log.recv_timeout(remaining) {
+                Ok(line) => self.handle_log_line(line),
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!(
+                            "tor bootstrapped only {}% before the timeout",
+                            self.progress
+                        ),
+                    ));
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        format!(
+                            "tor exited before bootstrapping: {}",
+                            self.last_log_line.as_deref().unwrap_or("no output")
+                        ),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the log until Tor reports the addresses of its SOCKS and control ports.
+    fn wait_for_listeners(&mut self) -> io::Result<()> {
+        let deadline = Instant::now() + LISTENER_TIMEOUT;
+        let mut socks_addr = None;
+        let mut control_addr = None;
+        loop {
+            if let (Some(socks_addr), Some(control_addr)) = (socks_addr, control_addr) {
+                self.socks_addr = socks_addr;
+                self.control_addr = control_addr;
+                return Ok(());
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.log.recv_timeout(remaining) {
+                Ok(line) => {
+                    if let Some(address) = parse_listener_addr(&line, "Socks") {
+                        socks_addr = Some(address);
+                    } else if let Some(address) = parse_listener_addr(&line, "Control") {
+                        control_addr = Some(address);
+                    }
+                    self.handle_log_line(line);
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "tor did not open its listeners before the timeout",
+                    ));
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        format!(
+                            "tor exited before opening its listeners: {}",
+                            self.last_log_line.as_deref().unwrap_or("no output")
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Returns the bootstrap progress in percent, without blocking.
+    pub fn bootstrap_progress(&mut self) -> u8 {
+        while let Ok(line) = self.log.try_recv() {
+            self.handle_log_line(line);
+        }
+        self.progress
+    }
+
+    fn handle_log_line(&mut self, line: String) {
+        if let Some(progress) = parse_bootstrap_progress(&line) {
+            self.progress = progress;
+        }
+        self.last_log_line = Some(line);
+    }
+
+    /// Returns the address of the SOCKS proxy,
+    /// to be used with [`TorStream::connect_with_address()`] or [`TorStreamBuilder::proxy()`].
+    ///
+    /// [`TorStream::connect_with_address()`]: ../struct.TorStream.html#method.connect_with_address
+    /// [`TorStreamBuilder::proxy()`]: ../struct.TorStreamBuilder.html#method.proxy
+    #[inline]
+    pub fn socks_addr(&self) -> SocketAddr {
+        self.socks_addr
+    }
+
+    /// Returns the address of the control port.
+    #[inline]
+    pub fn control_addr(&self) -> SocketAddr {
+        self.control_addr
+    }
+
+    /// Connects and authenticates to the control port.
+    pub fn control(&self) -> Result<TorControl, ControlError> {
+        let mut control = TorControl::connect(self.control_addr)?;
+        control.authenticate_cookie(self.data_dir.join("control_auth_cookie"))?;
+        Ok(control)
+    }
+
+    /// Returns the data directory.
+    #[inline]
+    pub fn data_dir(&self) -> &Path {
+        &self.data_dir
+    }
+
+    /// Returns the OS-assigned process identifier.
+    #[inline]
+    pub fn id(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// Kills the process and waits for it to exit.
+    pub fn kill(&mut self) -> io::Result<()> {
+        self.child.kill()?;
+        self.child.wait().map(drop)
+    }
+}
+
+impl Drop for TorProcess {
+    fn drop(&mut self) {
+        let _ = self.kill();
+        if self.temporary {
+            let _ = fs::remove_dir_all(&self.data_dir);
+        }
+    }
+}
+
+/// Parses the progress from a log line such as
+/// `Oct 17 10:00:00.000 [notice] Bootstrapped 100% (done): Done`.
+fn parse_bootstrap_progress(line: &str) -> Option<u8> {
+    let (_, rest) = line.split_once("Bootstrapped ")?;
+    let (progress, _) = rest.split_once('%')?;
+    progress.parse().ok().filter(|&progress| progress <= 100)
+}
+
+/// Parses the address from a log line such as
+/// `Oct 17 10:00:00.000 [notice] Opened Socks listener connection (ready) on 127.0.0.1:9050`.
+///
+/// Older versions of Tor omit `connection (ready)`.
+fn parse_listener_addr(line: &str, kind: &str) -> Option<SocketAddr> {
+    let (_, rest) = line.split_once(&format!("Opened {} listener", kind))?;
+    let (_, address) = rest.rsplit_once(" on ")?;
+    address.trim().parse().ok()
+}
+
+/// Creates a directory which is only accessible by the current user, as Tor requires.
+fn create_private_dir(path: &Path) -> io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    builder.mode(0o700);
+    builder.create(path)
+}
+
+fn create_temporary_dir() -> io::Result<PathBuf> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    loop {
+        let path = std::env::temp_dir().join(format!(
+            "tor-stream-{}-{}",
+            process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let mut builder = fs::DirBuilder::new();
+        #[cfg(unix)]
+        builder.mode(0o700);
+        match builder.create(&path) {
+            Ok(()) => return Ok(path),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
@@ -0,0 +1,255 @@
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, SocketAddrV4, SocketAddrV6, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// The address of a Tor SOCKS5 proxy.
+///
+/// Besides TCP addresses, Tor can listen on a Unix domain socket,
+/// configured as `SocksPort unix:/run/tor/socks`.
+/// Parsing and formatting use the same `unix:<path>` syntax.
+///
+/// ```
+/// use tor_stream::ProxyAddr;
+/// use std::path::Path;
+///
+/// let proxy: ProxyAddr = "unix:/run/tor/socks".parse().unwrap();
+/// assert_eq!(proxy, ProxyAddr::from(Path::new("/run/tor/socks")));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ProxyAddr {
+    /// A TCP address.
+    Tcp(SocketAddr),
+    /// The path of a Unix domain socket, which is only supported on Unix platforms.
+    Unix(PathBuf),
+}
+
+/// A connection to the Tor proxy, over TCP or a Unix domain socket.
+///
+/// This is the transport underlying a [`TorStream`].
+///
+/// [`TorStream`]: struct.TorStream.html
+#[derive(Debug)]
+pub enum ProxyStream {
+    /// A TCP connection.
+    Tcp(TcpStream),
+    /// A connection over a Unix domain socket.
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl ProxyAddr {
+    /// Returns the TCP address, if this is one.
+    pub fn as_tcp(&self) -> Option<SocketAddr> {
+        match self {
+            ProxyAddr::Tcp(address) => Some(*address),
+            ProxyAddr::Unix(_) => None,
+        }
+    }
+
+    /// Returns the socket path, if this is a Unix domain socket.
+    pub fn as_unix(&self) -> Option<&Path> {
+        match self {
+            ProxyAddr::Tcp(_) => None,
+            ProxyAddr::Unix(path) => Some(path),
+        }
+    }
+}
+
+impl From<SocketAddr> for ProxyAddr {
+    fn from(address: SocketAddr) -> ProxyAddr {
+        ProxyAddr::Tcp(address)
+    }
+}
+
+impl From<SocketAddrV4> for ProxyAddr {
+    fn from(address: SocketAddrV4) -> ProxyAddr {
+        ProxyAddr::Tcp(address.into())
+    }
+}
+
+impl From<SocketAddrV6> for ProxyAddr {
+    fn from(address: SocketAddrV6) -> ProxyAddr {
+        ProxyAddr::Tcp(address.into())
+    }
+}
+
+impl From<PathBuf> for ProxyAddr {
+    fn from(path: PathBuf) -> ProxyAddr {
+        ProxyAddr::Unix(path)
+    }
+}
+
+impl From<&Path> for ProxyAddr {
+    fn from(path: &Path) -> ProxyAddr {
+        ProxyAddr::Unix(path.to_owned())
+    }
+}
+
+impl FromStr for ProxyAddr {
+    type Err = &'static str;
+
+    /// Parses `unix:<path>` or a TCP socket address such as `127.0.0.1:9050`.
+    fn from_str(s: &str) -> Result<ProxyAddr, &'static str> {
+        match s.strip_prefix("unix:") {
+            Some("") => Err("missing socket path"),
+            Some(path) => Ok(ProxyAddr::Unix(PathBuf::from(path))),
+            None => s
+                .parse()
+                .map(ProxyAddr::Tcp)
+                .map_err(|_| "invalid socket address"),
+        }
+    }
+}
+
+impl fmt::Display for ProxyAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProxyAddr::Tcp(address) => address.fmt(f),
+            ProxyAddr::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+impl ProxyStream {
+    /// Connects to the proxy.
+    ///
+    /// The timeout only applies to TCP, since connecting to a Unix socket does not block.
+    pub(crate) fn connect(proxy: &ProxyAddr, timeout: Option<Duration>) -> io::Result<ProxyStream> {
+        match proxy {
+            ProxyAddr::Tcp(address) => match timeout {
+                Some(timeout) => TcpStream::connect_timeout(address, timeout),
+                None => TcpStream::connect(address),
+            }
+            .map(ProxyStream::Tcp),
+            #[cfg(unix)]
+            ProxyAddr::Unix(path) => UnixStream::connect(path).map(ProxyStream::Unix),
+            #[cfg(not(unix))]
+            ProxyAddr::Unix(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Unix domain sockets are not supported on this platform",
+            )),
+        }
+    }
+
+    /// Returns the TCP stream, if the proxy is connected over TCP.
+    pub fn as_tcp(&self) -> Option<&TcpStream> {
+        match self {
+            ProxyStream::Tcp(stream) => Some(stream),
+            #[cfg(unix)]
+            ProxyStream::Unix(_) => None,
+        }
+    }
+
+    /// Returns the Unix stream, if the proxy is connected over a Unix domain socket.
+    #[cfg(unix)]
+    pub fn as_unix(&self) -> Option<&UnixStream> {
+        match self {
+            ProxyStream::Tcp(_) => None,
+            ProxyStream::Unix(stream) => Some(stream),
+        }
+    }
+
+    /// Sets the read timeout, like [`TcpStream::set_read_timeout`].
+    ///
+    /// [`TcpStream::set_read_timeout`]: https://doc.rust-lang.org/std/net/struct.TcpStream.html#method.set_read_timeout
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            ProxyStream::Tcp(stream) => stream.set_read_timeout(timeout),
+            #[cfg(unix)]
+            ProxyStream::Unix(stream) => stream.set_read_timeout(timeout),
+        }
+    }
+
+    /// Sets the write timeout, like [`TcpStream::set_write_timeout`].
+    ///
+    /// [`TcpStream::set_write_timeout`]: https://doc.rust-lang.org/std/net/struct.TcpStream.html#method.set_write_timeout
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            ProxyStream::Tcp(stream) => stream.set_write_timeout(timeout),
+            #[cfg(unix)]
+            ProxyStream::Unix(stream) => stream.set_write_timeout(timeout),
+        }
+    }
+
+    /// Returns the read timeout.
+    pub fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        match self {
+            ProxyStream::Tcp(stream) => stream.read_timeout(),
+            #[cfg(unix)]
+            ProxyStream::Unix(stream) => stream.read_timeout(),
+        }
+    }
+
+    /// Returns the write timeout.
+    pub fn write_timeout(&self) -> io::Result<Option<Duration>> {
+        match self {
+            ProxyStream::Tcp(stream) => stream.write_timeout(),
+            #[cfg(unix)]
+            ProxyStream::Unix(stream) => stream.write_timeout(),
+        }
+    }
+
+    /// Shuts down the read half, the write half or both halves of the connection.
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        match self {
+            ProxyStream::Tcp(stream) => stream.shutdown(how),
+            #[cfg(unix)]
+            ProxyStream::Unix(stream) => stream.shutdown(how),
+        }
+    }
+
+    /// Creates a new handle to the same connection.
+    pub fn try_clone(&self) -> io::Result<ProxyStream> {
+        match self {
+            ProxyStream::Tcp(stream) => stream.try_clone().map(ProxyStream::Tcp),
+            #[cfg(unix)]
+            ProxyStream::Unix(stream) => stream.try_clone().map(ProxyStream::Unix),
+        }
+    }
+}
+
+impl From<TcpStream> for ProxyStream {
+    fn from(stream: TcpStream) -> ProxyStream {
+        ProxyStream::Tcp(stream)
+    }
+}
+
+#[cfg(unix)]
+impl From<UnixStream> for ProxyStream {
+    fn from(stream: UnixStream) -> ProxyStream {
+        ProxyStream::Unix(stream)
+    }
+}
+
+impl Read for ProxyStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            ProxyStream::Tcp(stream) => stream.read(buf),
+            #[cfg(unix)]
+            ProxyStream::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for ProxyStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            ProxyStream::Tcp(stream) => stream.write(buf),
+            #[cfg(unix)]
+            ProxyStream::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            ProxyStream::Tcp(stream) => stream.flush(),
+            #[cfg(unix)]
+            ProxyStream::Unix(stream) => stream.flush(),
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//! [reqwest] clients which connect over Tor.
+//!
+//! reqwest does not accept a custom transport, so the helpers configure its SOCKS support
+//! with the settings of a [`TorStreamBuilder`]:
+//! the proxy is [discovered] if not set, the isolation becomes the SOCKS credentials,
+//! and hostnames are always resolved by Tor through a `socks5h` proxy.
+//!
+//! This module requires the `reqwest` feature.
+//! reqwest's `rustls-tls` feature is enabled for `https`.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let client = tor_stream::reqwest::client()?;
+//!
+//! let body = client.get("http://www.example.com/").send().await?.text().await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! # Isolation
+//!
+//! The credentials are fixed when the client is built, so all requests of a client
+//! may share circuits, even with [`Isolation::Random`], which gives the client circuits of its own.
+//! Build a client per [`IsolationGroup`] to keep requests apart.
+//!
+//! [reqwest]: https://docs.rs/reqwest/
+//! [`TorStreamBuilder`]: ../struct.TorStreamBuilder.html
+//! [discovered]: ../discovery/index.html
+//! [`Isolation::Random`]: ../enum.Isolation.html#variant.Random
+//! [`IsolationGroup`]: ../struct.IsolationGroup.html
+
+use crate::{ProxyAddr, TorError, TorStreamBuilder};
+
+use reqwest::{Client, ClientBuilder, Proxy};
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// An error that occurred while configuring a reqwest client.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ClientError {
+    /// The proxy could not be discovered.
+    Tor(TorError),
+    /// reqwest only supports SOCKS proxies over TCP.
+    UnsupportedProxy(ProxyAddr),
+    /// reqwest rejected the configuration.
+    Reqwest(reqwest::Error),
+}
+
+/// Builds a client which connects like [`TorStream::connect()`].
+///
+/// [`TorStream::connect()`]: ../struct.TorStream.html#method.connect
+pub fn client() -> Result<Client, ClientError> {
+    client_builder(&TorStreamBuilder::new())?
+        .build()
+        .map_err(ClientError::Reqwest)
+}
+
+/// Returns a client builder with the proxy, isolation and timeouts of `builder`,
+/// which can be configured further.
+///
+/// The read timeout of `builder` applies to each read of a response,
+/// and its write timeout is ignored.
+/// The DNS policy does not apply, since reqwest passes hostnames to Tor unresolved.
+///
+/// ```no_run
+/// use tor_stream::{Isolation, IsolationGroup, TorStreamBuilder};
+/// use std::time::Duration;
+///
+/// let builder = TorStreamBuilder::new()
+///     .isolation(Isolation::Group(IsolationGroup::new()))
+///     .connect_timeout(Duration::from_secs(60));
+/// let client = tor_stream::reqwest::client_builder(&builder)
+///     .expect("Failed to find the proxy")
+///     .user_agent("tor-stream")
+///     .build()
+///     .expect("Failed to build the client");
+/// ```
+pub fn client_builder(builder: &TorStreamBuilder) -> Result<ClientBuilder, ClientError> {
+    let proxy = match builder.proxy_addr(None)? {
+        ProxyAddr::Tcp(address) => address,
+        proxy => return Err(ClientError::UnsupportedProxy(proxy)),
+    };
+    let mut proxy = Proxy::all(format!("socks5h://{}", proxy)).map_err(ClientError::Reqwest)?;
+    if let Some((username, password)) = builder.isolation.to_credentials() {
+        proxy = proxy.basic_auth(&username, &password);
+    }
+
+    let mut client = Client::builder().proxy(proxy);
+    if let Some(timeout) = builder.connect_timeout {
+        client = client.connect_timeout(timeout);
+    }
+    if let Some(timeout) = builder.read_timeout {
+        client = client.read_timeout(timeout);
+    }
+    Ok(client)
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClientError::Tor(e) => e.fmt(f),
+            ClientError::UnsupportedProxy(proxy) => {
+                write!(f, "reqwest does not support the proxy {}", proxy)
+            }
+            ClientError::Reqwest(e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for ClientError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ClientError::Tor(e) => Some(e),
+            ClientError::Reqwest(e) => Some(e),
+            ClientError::UnsupportedProxy(_) => None,
+        }
+    }
+}
+
+impl From<TorError> for ClientError {
+    fn from(error: TorError) -> ClientError {
+        ClientError::Tor(error)
+    }
+}
+
+impl From<ClientError> for io::Error {
+    fn from(error: ClientError) -> io::Error {
+        match error {
+            ClientError::Tor(e) => e.into(),
+            error @ ClientError::UnsupportedProxy(_) => {
+                io::Error::new(io::ErrorKind::Unsupported, error)
+            }
+            error => io::Error::other(error),
+        }
+    }
+}
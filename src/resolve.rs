@@ -0,0 +1,77 @@
+use crate::socks5;
+use crate::{TargetAddr, TorError, TorStreamBuilder};
+
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+
+/// Resolves a hostname over the Tor network, using Tor's `RESOLVE` extension of SOCKS5.
+///
+/// The lookup is done by an exit relay, so no DNS request leaks locally.
+/// Tor returns a single address, which may be IPv4 or IPv6.
+/// The proxy is found like in [`TorStream::connect()`]; use
+/// [`TorStreamBuilder::resolve()`] to configure it.
+///
+/// ```no_run
+/// let address = tor_stream::resolve("www.torproject.org").expect("Failed to resolve");
+/// println!("www.torproject.org has address {}", address);
+/// ```
+///
+/// # Errors
+///
+/// If the name does not exist, Tor usually replies with [`TorError::HostUnreachable`],
+/// which is wrapped in the `io::Error`.
+///
+/// [`TorStream::connect()`]: struct.TorStream.html#method.connect
+/// [`TorStreamBuilder::resolve()`]: struct.TorStreamBuilder.html#method.resolve
+/// [`TorError::HostUnreachable`]: enum.TorError.html#variant.HostUnreachable
+pub fn resolve(host: &str) -> io::Result<IpAddr> {
+    TorStreamBuilder::new()
+        .resolve(host)
+        .map_err(io::Error::from)
+}
+
+/// Looks up the hostname of an IP address over the Tor network,
+/// using Tor's `RESOLVE_PTR` extension of SOCKS5.
+///
+/// The proxy is found like in [`TorStream::connect()`]; use
+/// [`TorStreamBuilder::resolve_ptr()`] to configure it.
+///
+/// [`TorStream::connect()`]: struct.TorStream.html#method.connect
+/// [`TorStreamBuilder::resolve_ptr()`]: struct.TorStreamBuilder.html#method.resolve_ptr
+pub fn resolve_ptr(ip: IpAddr) -> io::Result<String> {
+    TorStreamBuilder::new()
+        .resolve_ptr(ip)
+        .map_err(io::Error::from)
+}
+
+impl TorStreamBuilder {
+    /// Resolves a hostname over the Tor network with the proxy, timeout and isolation of the builder.
+    ///
+    /// See [`resolve()`] for details.
+    ///
+    /// [`resolve()`]: fn.resolve.html
+    pub fn resolve(&self, host: &str) -> Result<IpAddr, TorError> {
+        let target = TargetAddr::Domain(host.to_owned(), 0);
+        match self.request(socks5::CMD_RESOLVE, target)?.1 {
+            TargetAddr::Ip(address) => Ok(address.ip()),
+            TargetAddr::Domain(..) => Err(TorError::Protocol(
+                "RESOLVE reply does not contain an IP address",
+            )),
+        }
+    }
+
+    /// Looks up the hostname of an IP address with the proxy, timeout and isolation of the builder.
+    ///
+    /// See [`resolve_ptr()`] for details.
+    ///
+    /// [`resolve_ptr()`]: fn.resolve_ptr.html
+    pub fn resolve_ptr(&self, ip: IpAddr) -> Result<String, TorError> {
+        let target = TargetAddr::Ip(SocketAddr::new(ip, 0));
+        match self.request(socks5::CMD_RESOLVE_PTR, target)?.1 {
+            TargetAddr::Domain(host, _) => Ok(host),
+            TargetAddr::Ip(_) => Err(TorError::Protocol(
+                "RESOLVE_PTR reply does not contain a hostname",
+            )),
+        }
+    }
+}
@@ -0,0 +1,363 @@
+//! TLS over Tor streams with [rustls].
+//!
+//! A [`TlsConnector`] performs the TLS handshake over an established [`TorStream`],
+//! using the hostname the stream was connected to for SNI and certificate validation.
+//! Certificates are checked against the Mozilla root certificates of [webpki-roots],
+//! or a custom root store, and can additionally be pinned.
+//!
+//! This module requires the `rustls` feature.
+//!
+//! ```no_run
+//! use tor_stream::rustls::TorTlsStream;
+//! use std::io::prelude::*;
+//!
+//! let mut stream = TorTlsStream::connect("www.example.com:443").expect("Failed to connect");
+//!
+//! stream.write_all(b"GET / HTTP/1.1\r\nConnection: Close\r\nHost: www.example.com\r\n\r\n").expect("Failed to send request");
+//!
+//! let mut buf = String::new();
+//! stream.read_to_string(&mut buf).expect("Failed to read response");
+//! ```
+//!
+//! [rustls]: https://docs.rs/rustls/
+//! [webpki-roots]: https://docs.rs/webpki-roots/
+//! [`TlsConnector`]: struct.TlsConnector.html
+//! [`TorStream`]: ../struct.TorStream.html
+
+use crate::{TargetAddr, ToTargetAddr, TorError, TorStream};
+
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::crypto::ring;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{
+    CertificateError, ClientConfig, ClientConnection, DigitallySignedStruct, RootCertStore,
+    SignatureScheme, StreamOwned,
+};
+use sha2::{Digest, Sha256};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+
+/// An error that occurred while establishing a TLS stream over Tor.
+///
+/// Failures of Tor and the proxy are kept apart from failures of the TLS handshake.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum TlsError {
+    /// The stream could not be established through Tor.
+    Tor(TorError),
+    /// The destination is not a valid TLS server name.
+    InvalidServerName(String),
+    /// The TLS handshake failed, for example because the certificate was rejected.
+    ///
+    /// A certificate which matches none of the pins is reported as
+    /// `InvalidCertificate(CertificateError::ApplicationVerificationFailure)`.
+    Tls(rustls::Error),
+    /// An I/O error occurred during the handshake.
+    Io(io::Error),
+}
+
+/// A builder for TLS sessions over Tor streams.
+///
+/// ```no_run
+/// use rustls::pki_types::pem::PemObject;
+/// use rustls::pki_types::CertificateDer;
+/// use rustls::RootCertStore;
+/// use tor_stream::rustls::TlsConnector;
+/// use tor_stream::TorStream;
+///
+/// // Trust only the certificate authority of an internal service
+/// let mut roots = RootCertStore::empty();
+/// roots.add(CertificateDer::from_pem_file("ca.pem").expect("Invalid certificate")).unwrap();
+///
+/// let connector = TlsConnector::with_root_store(roots);
+/// let stream = TorStream::connect("internal.example.com:443").expect("Failed to connect");
+/// let stream = connector.connect(stream).expect("TLS handshake failed");
+/// ```
+#[derive(Debug, Clone)]
+pub struct TlsConnector {
+    roots: Arc<RootCertStore>,
+    pins: Vec<[u8; 32]>,
+    alpn_protocols: Vec<Vec<u8>>,
+}
+
+/// A TLS stream over the Tor network.
+///
+/// It implements `Read` and `Write` for the decrypted data.
+#[derive(Debug)]
+pub struct TorTlsStream {
+    stream: StreamOwned<ClientConnection, TorStream>,
+}
+
+/// Checks the certificate chain like `inner`, and the end-entity certificate against the pins.
+#[derive(Debug)]
+struct PinnedVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    pins: Vec<[u8; 32]>,
+}
+
+impl TlsConnector {
+    /// Creates a connector trusting the Mozilla root certificates, without pins.
+    pub fn new() -> TlsConnector {
+        let mut roots = RootCertStore::empty();
+        roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        TlsConnector::with_root_store(roots)
+    }
+
+    /// Creates a connector trusting only the certificates of `roots`.
+    pub fn with_root_store(roots: RootCertStore) -> TlsConnector {
+        TlsConnector {
+            roots: Arc::new(roots),
+            pins: Vec::new(),
+            alpn_protocols: Vec::new(),
+        }
+    }
+
+    /// Pins the SHA-256 hash of the server's DER-encoded end-entity certificate.
+    ///
+    /// The certificate chain is still verified against the root store.
+    /// Can be called multiple times, so a certificate can be replaced without downtime;
+    /// the server must then present one of the pinned certificates.
+    pub fn pin_sha256(mut self, hash: [u8; 32]) -> TlsConnector {
+        self.pins.push(hash);
+        self
+    }
+
+    /// Sets the protocols offered with ALPN, such as `b"http/1.1"`.
+    pub fn alpn_protocols(mut self, protocols: Vec<Vec<u8>>) -> TlsConnector {
+        self.alpn_protocols = protocols;
+        self
+    }
+
+    /// Performs the TLS handshake over `stream`.
+    ///
+    /// The server name is the hostname from the [`target_addr()`] of the stream,
+    /// or its IP address if it was connected to one.
+    ///
+    /// [`target_addr()`]: ../struct.TorStream.html#method.target_addr
+    pub fn connect(&self, mut stream: TorStream) -> Result<TorTlsStream, TlsError> {
+        let server_name = server_name(stream.target_addr())?;
+        let mut connection =
+            ClientConnection::new(self.client_config()?, server_name).map_err(TlsError::Tls)?;
+        while connection.is_handshaking() {
+            connection
+                .complete_io(&mut stream)
+                .map_err(TlsError::from)?;
+        }
+
+        Ok(TorTlsStream {
+            stream: StreamOwned::new(connection, stream),
+        })
+    }
+
+    /// Builds the rustls configuration.
+    ///
+    /// The `ring` provider is used explicitly, so other providers in the dependency graph
+    /// do not make the process-wide default ambiguous.
+    pub(crate) fn client_config(&self) -> Result<Arc<ClientConfig>, TlsError> {
+        let provider = Arc::new(ring::default_provider());
+        let verifier =
+            WebPkiServerVerifier::builder_with_provider(self.roots.clone(), provider.clone())
+                .build()
+                .map_err(|e| TlsError::Tls(rustls::Error::General(e.to_string())))?;
+        let mut config = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .map_err(TlsError::Tls)?
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(PinnedVerifier {
+                inner: verifier,
+                pins: self.pins.clone(),
+            }))
+            .with_no_client_auth();
+        config.alpn_protocols = self.alpn_protocols.clone();
+        Ok(Arc::new(config))
+    }
+}
+
+impl Default for TlsConnector {
+    fn default() -> TlsConnector {
+        TlsConnector::new()
+    }
+}
+
+impl TorTlsStream {
+    /// Connects to a destination address over the Tor network and performs the TLS handshake,
+    /// trusting the Mozilla root certificates.
+    ///
+    /// The proxy is found like in [`TorStream::connect()`].
+    /// Use a [`TlsConnector`] to configure the certificate validation.
+    ///
+    /// [`TorStream::connect()`]: ../struct.TorStream.html#method.connect
+    /// [`TlsConnector`]: struct.TlsConnector.html
+    pub fn connect(destination: impl ToTargetAddr) -> io::Result<TorTlsStream> {
+        let stream = TorStream::connect(destination)?;
+        TlsConnector::new().connect(stream).map_err(io::Error::from)
+    }
+
+    /// Returns the destination address the stream was connected to.
+    #[inline]
+    pub fn target_addr(&self) -> &TargetAddr {
+        self.stream.sock.target_addr()
+    }
+
+    /// Returns the certificate chain presented by the server, starting with its own certificate.
+    pub fn peer_certificates(&self) -> Option<&[CertificateDer<'static>]> {
+        self.stream.conn.peer_certificates()
+    }
+
+    /// Returns the protocol negotiated with ALPN.
+    pub fn alpn_protocol(&self) -> Option<&[u8]> {
+        self.stream.conn.alpn_protocol()
+    }
+
+    /// Gets a reference to the TLS session.
+    #[inline]
+    pub fn connection(&self) -> &ClientConnection {
+        &self.stream.conn
+    }
+
+    /// Gets a reference to the underlying Tor stream.
+    #[inline]
+    pub fn get_ref(&self) -> &TorStream {
+        &self.stream.sock
+    }
+
+    /// Gets a mutable reference to the underlying Tor stream.
+    ///
+    /// Reading or writing through it corrupts the TLS session.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut TorStream {
+        &mut self.stream.sock
+    }
+}
+
+impl Read for TorTlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.read(buf)
+    }
+}
+
+impl Write for TorTlsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer,
+        intermediates: &[CertificateDer],
+        server_name: &ServerName,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        )?;
+        if self.pins.is_empty() {
+            return Ok(verified);
+        }
+        let hash: [u8; 32] = Sha256::digest(end_entity.as_ref()).into();
+        if self.pins.contains(&hash) {
+            Ok(verified)
+        } else {
+            Err(rustls::Error::InvalidCertificate(
+                CertificateError::ApplicationVerificationFailure,
+            ))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+pub(crate) fn server_name(target: &TargetAddr) -> Result<ServerName<'static>, TlsError> {
+    match target {
+        TargetAddr::Ip(address) => Ok(ServerName::IpAddress(address.ip().into())),
+        TargetAddr::Domain(host, _) => ServerName::try_from(host.clone())
+            .map_err(|_| TlsError::InvalidServerName(host.clone())),
+    }
+}
+
+impl fmt::Display for TlsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TlsError::Tor(e) => e.fmt(f),
+            TlsError::InvalidServerName(name) => write!(f, "invalid TLS server name {:?}", name),
+            TlsError::Tls(e) => write!(f, "TLS error: {}", e),
+            TlsError::Io(e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for TlsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TlsError::Tor(e) => Some(e),
+            TlsError::Tls(e) => Some(e),
+            TlsError::Io(e) => Some(e),
+            TlsError::InvalidServerName(_) => None,
+        }
+    }
+}
+
+impl From<TorError> for TlsError {
+    fn from(error: TorError) -> TlsError {
+        TlsError::Tor(error)
+    }
+}
+
+/// rustls reports handshake failures as `io::Error`s wrapping a `rustls::Error`.
+impl From<io::Error> for TlsError {
+    fn from(error: io::Error) -> TlsError {
+        match error
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<rustls::Error>())
+        {
+            Some(e) => TlsError::Tls(e.clone()),
+            None => TlsError::Io(error),
+        }
+    }
+}
+
+impl From<TlsError> for io::Error {
+    fn from(error: TlsError) -> io::Error {
+        match error {
+            TlsError::Tor(e) => e.into(),
+            TlsError::Io(e) => e,
+            TlsError::InvalidServerName(_) => io::Error::new(io::ErrorKind::InvalidInput, error),
+            error => io::Error::new(io::ErrorKind::InvalidData, error),
+        }
+    }
+}
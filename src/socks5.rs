@@ -0,0 +1,437 @@
+//! The client side of the SOCKS5 protocol ([RFC 1928]).
+//!
+//! The handshake itself is implemented by [`Handshake`] without doing any I/O,
+//! so that it can be driven by blocking and non-blocking transports alike.
+//!
+//! [RFC 1928]: https://tools.ietf.org/html/rfc1928
+
+use crate::{ProxyStream, TorError};
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use std::time::{Duration, Instant};
+
+const VERSION: u8 = 5;
+const METHOD_NO_AUTH: u8 = 0;
+const METHOD_PASSWORD: u8 = 2;
+const METHOD_NONE_ACCEPTABLE: u8 = 0xFF;
+pub(crate) const CMD_CONNECT: u8 = 1;
+/// Tor's extension for resolving a hostname.
+pub(crate) const CMD_RESOLVE: u8 = 0xF0;
+/// Tor's extension for the reverse lookup of an IP address.
+pub(crate) const CMD_RESOLVE_PTR: u8 = 0xF1;
+
+const ATYP_IPV4: u8 = 1;
+const ATYP_DOMAIN: u8 = 3;
+const ATYP_IPV6: u8 = 4;
+
+/// A destination address, which is passed on to the Tor proxy.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TargetAddr {
+    /// Connect to an IP address.
+    Ip(SocketAddr),
+    /// Connect to a domain name.
+    ///
+    /// The domain name is resolved by the Tor network, so no DNS requests leak locally.
+    Domain(String, u16),
+}
+
+impl TargetAddr {
+    /// Returns the port of the address.
+    pub fn port(&self) -> u16 {
+        match self {
+            TargetAddr::Ip(addr) => addr.port(),
+            TargetAddr::Domain(_, port) => *port,
+        }
+    }
+}
+
+impl fmt::Display for TargetAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TargetAddr::Ip(addr) => addr.fmt(f),
+            TargetAddr::Domain(domain, port) => write!(f, "{}:{}", domain, port),
+        }
+    }
+}
+
+/// A trait for values that can be converted to a [`TargetAddr`].
+///
+/// Strings are parsed as IP addresses if possible, and treated as domain names otherwise.
+///
+/// # DNS leaks
+///
+/// None of the conversions resolve names locally: domain names are passed to Tor,
+/// which resolves them at the exit relay. Leaks happen before the conversion,
+/// when a caller resolves a name itself and passes the resulting address.
+///
+/// These inputs are leak-free, and accepted by [`DnsPolicy::Strict`]:
+///
+/// - `&str` and `String`, such as `"www.example.com:80"` or `"192.0.2.1:80"`
+/// - `(&str, u16)`
+/// - [`TargetAddr`]
+/// - `(OnionAddress, u16)` and `(&OnionAddress, u16)`
+///
+/// Typed addresses like `SocketAddr`, `SocketAddrV4`, `SocketAddrV6`,
+/// `(Ipv4Addr, u16)` and `(Ipv6Addr, u16)` are what `ToSocketAddrs` and other
+/// local resolvers return, so they are refused by [`DnsPolicy::Strict`].
+/// Wrap an address in [`TargetAddr::Ip`] to connect to it deliberately.
+///
+/// [`TargetAddr`]: enum.TargetAddr.html
+/// [`TargetAddr::Ip`]: enum.TargetAddr.html#variant.Ip
+/// [`DnsPolicy::Strict`]: enum.DnsPolicy.html#variant.Strict
+pub trait ToTargetAddr {
+    /// Converts the value to a `TargetAddr`.
+    fn to_target_addr(&self) -> io::Result<TargetAddr>;
+
+    /// Returns whether an IP address produced by this value may have been resolved locally.
+    ///
+    /// This is only checked if the [`DnsPolicy`] is not `Permissive`,
+    /// and only if [`to_target_addr()`] returned an IP address.
+    /// Implementations should return `false` only if the address is given explicitly,
+    /// for example as a string.
+    ///
+    /// [`DnsPolicy`]: enum.DnsPolicy.html
+    /// [`to_target_addr()`]: #tymethod.to_target_addr
+    fn may_be_resolved(&self) -> bool {
+        true
+    }
+}
+
+impl ToTargetAddr for TargetAddr {
+    fn to_target_addr(&self) -> io::Result<TargetAddr> {
+        Ok(self.clone())
+    }
+
+    fn may_be_resolved(&self) -> bool {
+        false
+    }
+}
+
+impl ToTargetAddr for SocketAddr {
+    fn to_target_addr(&self) -> io::Result<TargetAddr> {
+        Ok(TargetAddr::Ip(*self))
+    }
+}
+
+impl ToTargetAddr for SocketAddrV4 {
+    fn to_target_addr(&self) -> io::Result<TargetAddr> {
+        SocketAddr::V4(*self).to_target_addr()
+    }
+}
+
+impl ToTargetAddr for SocketAddrV6 {
+    fn to_target_addr(&self) -> io::Result<TargetAddr> {
+        SocketAddr::V6(*self).to_target_addr()
+    }
+}
+
+impl ToTargetAddr for (Ipv4Addr, u16) {
+    fn to_target_addr(&self) -> io::Result<TargetAddr> {
+        SocketAddrV4::new(self.0, self.1).to_target_addr()
+    }
+}
+
+impl ToTargetAddr for (Ipv6Addr, u16) {
+    fn to_target_addr(&self) -> io::Result<TargetAddr> {
+        SocketAddrV6::new(self.0, self.1, 0, 0).to_target_addr()
+    }
+}
+
+impl ToTargetAddr for (&str, u16) {
+    fn to_target_addr(&self) -> io::Result<TargetAddr> {
+        if let Ok(addr) = self.0.parse::<Ipv4Addr>() {
+            return (addr, self.1).to_target_addr();
+        }
+
+        if let Ok(addr) = self.0.parse::<Ipv6Addr>() {
+            return (addr, self.1).to_target_addr();
+        }
+
+        Ok(TargetAddr::Domain(self.0.to_owned(), self.1))
+    }
+
+    fn may_be_resolved(&self) -> bool {
+        false
+    }
+}
+
+impl ToTargetAddr for &str {
+    fn to_target_addr(&self) -> io::Result<TargetAddr> {
+        if let Ok(addr) = self.parse::<SocketAddr>() {
+            return addr.to_target_addr();
+        }
+
+        let invalid = || io::Error::new(io::ErrorKind::InvalidInput, "invalid socket address");
+        let mut parts = self.rsplitn(2, ':');
+        let port = parts.next().ok_or_else(invalid)?;
+        let host = parts.next().ok_or_else(invalid)?;
+        let port = port.parse().map_err(|_| invalid())?;
+
+        Ok(TargetAddr::Domain(host.to_owned(), port))
+    }
+
+    fn may_be_resolved(&self) -> bool {
+        false
+    }
+}
+
+impl ToTargetAddr for String {
+    fn to_target_addr(&self) -> io::Result<TargetAddr> {
+        self.as_str().to_target_addr()
+    }
+
+    fn may_be_resolved(&self) -> bool {
+        false
+    }
+}
+
+/// The next action required to advance a [`Handshake`].
+#[derive(Debug)]
+pub(crate) enum Step {
+    /// Write all of the bytes to the proxy.
+    Write(Vec<u8>),
+    /// Read exactly this many bytes from the proxy.
+    Read(usize),
+    /// The handshake completed, and the proxy replied with this bound address.
+    Done(TargetAddr),
+}
+
+#[derive(Debug)]
+enum State {
+    Start,
+    SentGreeting,
+    MethodSelection,
+    SentAuth,
+    AuthReply,
+    SentRequest,
+    ReplyHeader,
+    ReplyAddress([u8; 2]),
+    Done,
+}
+
+/// A SOCKS5 client handshake.
+///
+/// Call [`step()`] with the data requested by the previous [`Step::Read`],
+/// or an empty slice otherwise, until it returns [`Step::Done`].
+///
+/// [`step()`]: #method.step
+#[derive(Debug)]
+pub(crate) struct Handshake {
+    command: u8,
+    target: TargetAddr,
+    credentials: Option<(String, String)>,
+    state: State,
+}
+
+impl Handshake {
+    /// Creates a handshake for `command`.
+    ///
+    /// If `credentials` are given, only username/password authentication ([RFC 1929]) is offered.
+    ///
+    /// [RFC 1929]: https://tools.ietf.org/html/rfc1929
+    pub(crate) fn new(
+        command: u8,
+        target: TargetAddr,
+        credentials: Option<(String, String)>,
+    ) -> Handshake {
+        Handshake {
+            command,
+            target,
+            credentials,
+            state: State::Start,
+        }
+    }
+
+    pub(crate) fn step(&mut self, input: &[u8]) -> Result<Step, TorError> {
+        let (step, state) = match self.state {
+            State::Start => {
+                let method = match self.credentials {
+                    Some(_) => METHOD_PASSWORD,
+                    None => METHOD_NO_AUTH,
+                };
+                (Step::Write(vec![VERSION, 1, method]), State::SentGreeting)
+            }
+            State::SentGreeting => (Step::Read(2), State::MethodSelection),
+            State::MethodSelection => {
+                if input[0] != VERSION {
+                    return Err(TorError::Protocol("invalid response version"));
+                }
+                match (input[1], &self.credentials) {
+                    (METHOD_NO_AUTH, None) => (Step::Write(self.request()?), State::SentRequest),
+                    (METHOD_PASSWORD, Some((username, password))) => {
+                        let mut auth = vec![1];
+                        write_credential(&mut auth, username)?;
+                        write_credential(&mut auth, password)?;
+                        (Step::Write(auth), State::SentAuth)
+                    }
+                    (METHOD_NONE_ACCEPTABLE, _) => {
+                        return Err(TorError::Protocol("no acceptable authentication methods"))
+                    }
+                    _ => return Err(TorError::Protocol("invalid authentication method")),
+                }
+            }
+            State::SentAuth => (Step::Read(2), State::AuthReply),
+            State::AuthReply => {
+                if input[0] != 1 {
+                    return Err(TorError::Protocol("invalid authentication version"));
+                }
+                if input[1] != 0 {
+                    return Err(TorError::AuthenticationFailed);
+                }
+                (Step::Write(self.request()?), State::SentRequest)
+            }
+            // The header is read together with the first byte of the address,
+            // which is the length of the domain name for `ATYP_DOMAIN`.
+            State::SentRequest => (Step::Read(5), State::ReplyHeader),
+            State::ReplyHeader => {
+                if input[0] != VERSION {
+                    return Err(TorError::Protocol("invalid response version"));
+                }
+                if let Some(error) = TorError::from_reply_code(input[1]) {
+                    return Err(error);
+                }
+
+                let remaining = match input[3] {
+                    ATYP_IPV4 => 4 + 2 - 1,
+                    ATYP_IPV6 => 16 + 2 - 1,
+                    ATYP_DOMAIN => input[4] as usize + 2,
+                    _ => return Err(TorError::Protocol("invalid address type")),
+                };
+                (
+                    Step::Read(remaining),
+                    State::ReplyAddress([input[3], input[4]]),
+                )
+            }
+            State::ReplyAddress([atyp, first]) => {
+                let addr = read_addr(atyp, first, input)?;
+                (Step::Done(addr), State::Done)
+            }
+            State::Done => panic!("handshake already completed"),
+        };
+
+        self.state = state;
+        Ok(step)
+    }
+
+    fn request(&self) -> Result<Vec<u8>, TorError> {
+        let mut request = vec![VERSION, self.command, 0];
+        write_addr(&mut request, &self.target)?;
+        Ok(request)
+    }
+}
+
+fn write_credential(buf: &mut Vec<u8>, credential: &str) -> Result<(), TorError> {
+    if credential.is_empty() || credential.len() > 255 {
+        return Err(TorError::Io(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "username and password must be between 1 and 255 bytes long",
+        )));
+    }
+    buf.push(credential.len() as u8);
+    buf.extend_from_slice(credential.as_bytes());
+    Ok(())
+}
+
+fn write_addr(buf: &mut Vec<u8>, target: &TargetAddr) -> Result<(), TorError> {
+    match target {
+        TargetAddr::Ip(SocketAddr::V4(addr)) => {
+            buf.push(ATYP_IPV4);
+            buf.extend_from_slice(&addr.ip().octets());
+        }
+        TargetAddr::Ip(SocketAddr::V6(addr)) => {
+            buf.push(ATYP_IPV6);
+            buf.extend_from_slice(&addr.ip().octets());
+        }
+        TargetAddr::Domain(domain, _) => {
+            if domain.is_empty() || domain.len() > 255 {
+                return Err(TorError::Io(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "domain name must be between 1 and 255 bytes long",
+                )));
+            }
+            buf.push(ATYP_DOMAIN);
+            buf.push(domain.len() as u8);
+            buf.extend_from_slice(domain.as_bytes());
+        }
+    };
+    buf.extend_from_slice(&target.port().to_be_bytes());
+
+    Ok(())
+}
+
+/// Parses an address, whose first byte has already been consumed.
+fn read_addr(atyp: u8, first: u8, rest: &[u8]) -> Result<TargetAddr, TorError> {
+    let (addr, port) = rest.split_at(rest.len() - 2);
+    let port = u16::from_be_bytes([port[0], port[1]]);
+
+    Ok(match atyp {
+        ATYP_IPV4 => {
+            let ip = Ipv4Addr::new(first, addr[0], addr[1], addr[2]);
+            TargetAddr::Ip(SocketAddr::new(ip.into(), port))
+        }
+        ATYP_IPV6 => {
+            let mut octets = [0; 16];
+            octets[0] = first;
+            octets[1..].copy_from_slice(addr);
+            TargetAddr::Ip(SocketAddr::new(Ipv6Addr::from(octets).into(), port))
+        }
+        _ => {
+            let domain = String::from_utf8(addr.to_vec())
+                .map_err(|_| TorError::Protocol("invalid domain name"))?;
+            TargetAddr::Domain(domain, port)
+        }
+    })
+}
+
+/// Performs a handshake on an established connection to the proxy.
+///
+/// If a `deadline` is given, every read and write is limited to the time remaining,
+/// so the whole handshake fails with [`TorError::TimedOut`] once it has passed.
+///
+/// [`TorError::TimedOut`]: ../enum.TorError.html#variant.TimedOut
+pub(crate) fn handshake(
+    stream: &mut ProxyStream,
+    mut handshake: Handshake,
+    deadline: Option<Instant>,
+) -> Result<TargetAddr, TorError> {
+    let mut buf = Vec::new();
+    loop {
+        match handshake.step(&buf)? {
+            Step::Write(data) => {
+                if let Some(deadline) = deadline {
+                    stream.set_write_timeout(Some(remaining(deadline)?))?;
+                }
+                stream.write_all(&data).map_err(timed_out)?;
+                buf.clear();
+            }
+            Step::Read(len) => {
+                if let Some(deadline) = deadline {
+                    stream.set_read_timeout(Some(remaining(deadline)?))?;
+                }
+                buf.resize(len, 0);
+                stream.read_exact(&mut buf).map_err(timed_out)?;
+            }
+            Step::Done(bind_addr) => return Ok(bind_addr),
+        }
+    }
+}
+
+/// Returns the time left until the deadline, or `TorError::TimedOut` once it has passed.
+pub(crate) fn remaining(deadline: Instant) -> Result<Duration, TorError> {
+    let now = Instant::now();
+    if now >= deadline {
+        Err(TorError::TimedOut)
+    } else {
+        Ok(deadline - now)
+    }
+}
+
+/// Depending on the platform, an expired socket timeout is reported as `WouldBlock` or `TimedOut`.
+fn timed_out(e: io::Error) -> TorError {
+    match e.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => TorError::TimedOut,
+        _ => TorError::Io(e),
+    }
+}
@@ -0,0 +1,327 @@
+//! Asynchronous Tor streams for [tokio].
+//!
+//! This module requires the `tokio` feature.
+//!
+//! ```no_run
+//! use tor_stream::tokio::TorStream;
+//! use tokio::io::{AsyncReadExt, AsyncWriteExt};
+//!
+//! # async fn run() -> std::io::Result<()> {
+//! let mut stream = TorStream::connect("www.example.com:80").await?;
+//!
+//! stream.write_all(b"GET / HTTP/1.1\r\nConnection: Close\r\nHost: www.example.com\r\n\r\n").await?;
+//!
+//! let mut buf = String::new();
+//! stream.read_to_string(&mut buf).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [tokio]: https://tokio.rs
+
+use crate::socks5::{self, Handshake, Step};
+use crate::{discovery, ProxyAddr, TargetAddr, ToTargetAddr, TorError, TorStreamBuilder};
+
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::net::TcpStream;
+#[cfg(unix)]
+use tokio::net::UnixStream;
+
+/// An asynchronous stream proxied over the Tor network.
+/// After connecting, it can be used like a normal tokio [`TcpStream`].
+///
+/// [`TcpStream`]: https://docs.rs/tokio/1/tokio/net/struct.TcpStream.html
+#[derive(Debug)]
+pub struct TorStream {
+    stream: ProxyStream,
+    target: TargetAddr,
+    bind_addr: TargetAddr,
+}
+
+/// An asynchronous connection to the Tor proxy, over TCP or a Unix domain socket.
+///
+/// This is the asynchronous version of [`ProxyStream`].
+///
+/// [`ProxyStream`]: ../enum.ProxyStream.html
+#[derive(Debug)]
+pub enum ProxyStream {
+    /// A TCP connection.
+    Tcp(TcpStream),
+    /// A connection over a Unix domain socket.
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl TorStream {
+    /// Connects to a destination address over the Tor network.
+    ///
+    /// This is the asynchronous version of [`TorStream::connect()`].
+    ///
+    /// [`TorStream::connect()`]: ../struct.TorStream.html#method.connect
+    pub async fn connect(destination: impl ToTargetAddr) -> io::Result<TorStream> {
+        TorStreamBuilder::new()
+            .connect_tokio(destination)
+            .await
+            .map_err(io::Error::from)
+    }
+
+    /// Connects to a destination address over the Tor network.
+    /// A Tor SOCKS5 proxy must be running at the `tor_proxy` address,
+    /// which is a `SocketAddr` or the path of a Unix domain socket.
+    pub async fn connect_with_address(
+        tor_proxy: impl Into<ProxyAddr>,
+        destination: impl ToTargetAddr,
+    ) -> io::Result<TorStream> {
+        TorStreamBuilder::new()
+            .proxy(tor_proxy)
+            .connect_tokio(destination)
+            .await
+            .map_err(io::Error::from)
+    }
+
+    /// Returns the destination address the stream was connected to.
+    #[inline]
+    pub fn target_addr(&self) -> &TargetAddr {
+        &self.target
+    }
+
+    /// Returns the bound address reported by the proxy.
+    #[inline]
+    pub fn bind_addr(&self) -> &TargetAddr {
+        &self.bind_addr
+    }
+
+    /// Gets a reference to the underlying connection to the proxy.
+    #[inline]
+    pub fn get_ref(&self) -> &ProxyStream {
+        &self.stream
+    }
+
+    /// Gets a mutable reference to the underlying connection to the proxy.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut ProxyStream {
+        &mut self.stream
+    }
+
+    /// Unwraps the `TorStream`.
+    #[inline]
+    pub fn into_inner(self) -> ProxyStream {
+        self.stream
+    }
+}
+
+impl TorStreamBuilder {
+    /// Connects to a destination address over the Tor network asynchronously.
+    ///
+    /// The connect timeout is applied to the whole operation, just like in [`connect()`].
+    /// Read and write timeouts are not supported by tokio streams and are ignored;
+    /// use [`tokio::time::timeout`] instead.
+    ///
+    /// If no proxy was set, it is [discovered] on tokio's blocking thread pool,
+    /// so the discovery counts towards the connect timeout without blocking the runtime.
+    ///
+    /// This method requires the `tokio` feature.
+    ///
+    /// [`connect()`]: #method.connect
+    /// [discovered]: ../discovery/index.html
+    /// [`tokio::time::timeout`]: https://docs.rs/tokio/1/tokio/time/fn.timeout.html
+    pub async fn connect_tokio(
+        &self,
+        destination: impl ToTargetAddr,
+    ) -> Result<TorStream, TorError> {
+        let target = self.target_addr(&destination)?;
+        let deadline = self.connect_timeout.map(|timeout| Instant::now() + timeout);
+        let connect = async {
+            let proxy = match &self.proxy {
+                Some(proxy) => proxy.clone(),
+                None => {
+                    tokio::task::spawn_blocking(move || discovery::default_proxy_until(deadline))
+                        .await
+                        .map_err(io::Error::other)??
+                        .address
+                }
+            };
+            let mut stream = ProxyStream::connect(&proxy)
+                .await
+                .map_err(|e| self.proxy_unreachable(e))?;
+
+            let handshake = Handshake::new(
+                socks5::CMD_CONNECT,
+                target.clone(),
+                self.isolation.to_credentials(),
+            );
+            let bind_addr = handshake_async(&mut stream, handshake).await?;
+            Ok::<_, TorError>((stream, bind_addr))
+        };
+
+        let (stream, bind_addr) = match self.connect_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, connect)
+                .await
+                .map_err(|_| TorError::TimedOut)??,
+            None => connect.await?,
+        };
+
+        Ok(TorStream {
+            stream,
+            target,
+            bind_addr,
+        })
+    }
+}
+
+async fn handshake_async<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    mut handshake: Handshake,
+) -> Result<TargetAddr, TorError> {
+    let mut buf = Vec::new();
+    loop {
+        match handshake.step(&buf)? {
+            Step::Write(data) => {
+                stream.write_all(&data).await?;
+                buf.clear();
+            }
+            Step::Read(len) => {
+                buf.resize(len, 0);
+                stream.read_exact(&mut buf).await?;
+            }
+            Step::Done(bind_addr) => return Ok(bind_addr),
+        }
+    }
+}
+
+impl AsyncRead for TorStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut ReadBuf,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for TorStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        bufs: &[io::IoSlice],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.stream.is_write_vectored()
+    }
+}
+
+impl ProxyStream {
+    async fn connect(proxy: &ProxyAddr) -> io::Result<ProxyStream> {
+        match proxy {
+            ProxyAddr::Tcp(address) => TcpStream::connect(address).await.map(ProxyStream::Tcp),
+            #[cfg(unix)]
+            ProxyAddr::Unix(path) => UnixStream::connect(path).await.map(ProxyStream::Unix),
+            #[cfg(not(unix))]
+            ProxyAddr::Unix(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Unix domain sockets are not supported on this platform",
+            )),
+        }
+    }
+
+    /// Returns the TCP stream, if the proxy is connected over TCP.
+    pub fn as_tcp(&self) -> Option<&TcpStream> {
+        match self {
+            ProxyStream::Tcp(stream) => Some(stream),
+            #[cfg(unix)]
+            ProxyStream::Unix(_) => None,
+        }
+    }
+
+    /// Returns the Unix stream, if the proxy is connected over a Unix domain socket.
+    #[cfg(unix)]
+    pub fn as_unix(&self) -> Option<&UnixStream> {
+        match self {
+            ProxyStream::Tcp(_) => None,
+            ProxyStream::Unix(stream) => Some(stream),
+        }
+    }
+}
+
+impl AsyncRead for ProxyStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut ReadBuf,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            ProxyStream::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            #[cfg(unix)]
+            ProxyStream::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for ProxyStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            ProxyStream::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            #[cfg(unix)]
+            ProxyStream::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            ProxyStream::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            #[cfg(unix)]
+            ProxyStream::Unix(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            ProxyStream::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            #[cfg(unix)]
+            ProxyStream::Unix(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        bufs: &[io::IoSlice],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            ProxyStream::Tcp(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+            #[cfg(unix)]
+            ProxyStream::Unix(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match self {
+            ProxyStream::Tcp(stream) => stream.is_write_vectored(),
+            #[cfg(unix)]
+            ProxyStream::Unix(stream) => stream.is_write_vectored(),
+        }
+    }
+}
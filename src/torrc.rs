@@ -0,0 +1,760 @@
+//! Reading and writing Tor configuration files.
+//!
+//! A [`Torrc`] keeps the lines of a `torrc` file, including comments,
+//! so it can be modified and written back without losing the layout.
+//! Common options can be read with typed accessors.
+//!
+//! ```no_run
+//! use tor_stream::torrc::Torrc;
+//!
+//! let torrc = Torrc::load("/etc/tor/torrc").expect("Failed to read torrc");
+//! if let Some(proxy) = torrc.socks_addr().expect("Invalid SocksPort") {
+//!     println!("Tor is listening on {}", proxy);
+//! }
+//! ```
+//!
+//! [`Torrc`]: struct.Torrc.html
+
+use crate::control::{quote, unquote};
+use crate::ProxyAddr;
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The maximum nesting of `%include` directives, as in Tor.
+const MAX_INCLUDE_DEPTH: usize = 31;
+const DEFAULT_SOCKS_PORT: u16 = 9050;
+
+/// An error that occurred while reading a `torrc` file or one of its options.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum TorrcError {
+    /// An I/O error occurred while reading a file.
+    Io(io::Error),
+    /// A line could not be parsed.
+    Syntax {
+        /// The line number, starting at 1.
+        line: usize,
+        /// A description of the problem.
+        message: &'static str,
+    },
+    /// `%include` directives are nested too deeply, or include each other.
+    IncludeDepth,
+    /// An option has an invalid value.
+    InvalidValue {
+        /// The name of the option.
+        key: String,
+        /// A description of the problem.
+        message: &'static str,
+    },
+}
+
+impl fmt::Display for TorrcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TorrcError::Io(e) => e.fmt(f),
+            TorrcError::Syntax { line, message } => write!(f, "line {}: {}", line, message),
+            TorrcError::IncludeDepth => f.write_str("%include directives are nested too deeply"),
+            TorrcError::InvalidValue { key, message } => {
+                write!(f, "invalid value for {}: {}", key, message)
+            }
+        }
+    }
+}
+
+impl Error for TorrcError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TorrcError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for TorrcError {
+    fn from(error: io::Error) -> TorrcError {
+        TorrcError::Io(error)
+    }
+}
+
+impl From<TorrcError> for io::Error {
+    fn from(error: TorrcError) -> io::Error {
+        match error {
+            TorrcError::Io(e) => e,
+            _ => io::Error::new(io::ErrorKind::InvalidData, error),
+        }
+    }
+}
+
+/// A Tor configuration file.
+///
+/// Option names are case-insensitive, and options may occur multiple times.
+/// Options of files included with `%include` are only loaded by [`load()`],
+/// and are visible to the accessors but not modified by [`set()`], [`add()`] and [`remove()`].
+///
+/// ```
+/// use tor_stream::torrc::Torrc;
+///
+/// let mut torrc: Torrc = "# Local proxy\nSocksPort 9150 IsolateDestAddr\n".parse().unwrap();
+/// torrc.add("ExitNodes", "{de},{nl}");
+/// assert_eq!(torrc.exit_nodes(), ["{de}", "{nl}"]);
+/// assert_eq!(
+///     torrc.to_string(),
+///     "# Local proxy\nSocksPort 9150 IsolateDestAddr\nExitNodes {de},{nl}\n"
+/// );
+/// ```
+///
+/// [`load()`]: #method.load
+/// [`set()`]: #method.set
+/// [`add()`]: #method.add
+/// [`remove()`]: #method.remove
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Torrc {
+    lines: Vec<Line>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Line {
+    Blank,
+    Comment(String),
+    /// An option, with the comment following its value starting at the `#`.
+    Option {
+        key: String,
+        value: String,
+        comment: Option<String>,
+    },
+    Include {
+        path: String,
+        files: Vec<Torrc>,
+    },
+}
+
+/// The address of a listener such as `SocksPort` or `ControlPort`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PortAddress {
+    /// A TCP address. A port without address listens on `127.0.0.1`.
+    Tcp(SocketAddr),
+    /// A Unix domain socket, configured as `unix:<path>`.
+    Unix(PathBuf),
+    /// A port chosen by Tor, configured as `auto`.
+    Auto,
+    /// The listener is disabled, configured as `0`.
+    Disabled,
+}
+
+/// A listener option such as `SocksPort` or `ControlPort`, with its flags.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PortConfig {
+    /// The address of the listener.
+    pub address: PortAddress,
+    /// Flags such as `IsolateDestAddr` or `GroupWritable`.
+    pub flags: Vec<String>,
+}
+
+/// A `HiddenServiceDir` with its `HiddenServicePort` options.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct HiddenService {
+    /// The directory containing the keys of the service.
+    pub dir: PathBuf,
+    /// The virtual ports, each with an optional target such as `127.0.0.1:8080`.
+    pub ports: Vec<(u16, Option<String>)>,
+}
+
+/// A `Bridge` option, which is a relay used to enter the Tor network.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Bridge {
+    /// The pluggable transport, such as `obfs4`.
+    pub transport: Option<String>,
+    /// The address of the bridge.
+    pub address: String,
+    /// The fingerprint of the bridge.
+    pub fingerprint: Option<String>,
+    /// Arguments for the pluggable transport, such as `cert=...`.
+    pub args: Vec<String>,
+}
+
+/// A `ClientTransportPlugin` option, which provides pluggable transports for bridges.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TransportPlugin {
+    /// The names of the provided transports.
+    pub transports: Vec<String>,
+    /// Either `exec`, `socks4` or `socks5`.
+    pub method: String,
+    /// The command line for `exec`, or the proxy address.
+    pub target: String,
+}
+
+impl Torrc {
+    /// Creates an empty configuration.
+    pub fn new() -> Torrc {
+        Torrc::default()
+    }
+
+    /// Reads a configuration file, including the files referenced by `%include`.
+    ///
+    /// Relative include paths are resolved against the directory of the including file.
+    /// Included directories contain files which are read in alphabetical order,
+    /// except those starting with a dot.
+    /// The last component of the path may contain the wildcards `*` and `?`,
+    /// such as `%include /etc/torrc.d/*.conf`, which includes the matches in alphabetical order.
+    /// Files starting with a dot only match patterns starting with a dot.
+    pub fn load(path: impl AsRef<Path>) -> Result<Torrc, TorrcError> {
+        Torrc::load_nested(path.as_ref(), 0)
+    }
+
+    fn load_nested(path: &Path, depth: usize) -> Result<Torrc, TorrcError> {
+        if depth > MAX_INCLUDE_DEPTH {
+            return Err(TorrcError::IncludeDepth);
+        }
+        let mut torrc: Torrc = fs::read_to_string(path)?.parse()?;
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+
+        for line in &mut torrc.lines {
+            if let Line::Include { path, files } = line {
+                let path = base.join(&path);
+                let pattern = path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .filter(|name| name.contains(['*', '?']));
+                match (pattern, path.parent()) {
+                    (Some(pattern), Some(dir)) => {
+                        let pattern: Vec<char> = pattern.chars().collect();
+                        for entry in sorted_entries(dir)? {
+                            // Like shell globs, wildcards do not match a leading dot
+                            let matched = entry.file_name().is_some_and(|name| {
+                                let name: Vec<char> = name.to_string_lossy().chars().collect();
+                                (name.first() != Some(&'.') || pattern.first() == Some(&'.'))
+                                    && glob_match(&pattern, &name)
+                            });
+                            if matched {
+                                Torrc::load_include(&entry, depth, files)?;
+                            }
+                        }
+                    }
+                    _ => Torrc::load_include(&path, depth, files)?,
+                }
+            }
+        }
+
+        Ok(torrc)
+    }
+
+    fn load_include(path: &Path, depth: usize, files: &mut Vec<Torrc>) -> Result<(), TorrcError> {
+        if !path.is_dir() {
+            files.push(Torrc::load_nested(path, depth + 1)?);
+            return Ok(());
+        }
+        for entry in sorted_entries(path)? {
+            let hidden = entry
+                .file_name()
+                .is_none_or(|name| name.to_string_lossy().starts_with('.'));
+            if !hidden && entry.is_file() {
+                files.push(Torrc::load_nested(&entry, depth + 1)?);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns all options in order, including those of included files.
+    pub fn options(&self) -> Vec<(&str, &str)> {
+        let mut options = Vec::new();
+        self.collect_options(&mut options);
+        options
+    }
+
+    fn collect_options<'a>(&'a self, options: &mut Vec<(&'a str, &'a str)>) {
+        for line in &self.lines {
+            match line {
+                Line::Option { key, value, .. } => options.push((key, value)),
+                Line::Include { files, .. } => {
+                    for file in files {
+                        file.collect_options(options);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Returns the last value of an option, which is the one Tor uses for options occurring once.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.get_all(key).pop()
+    }
+
+    /// Returns all values of an option.
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        self.options()
+            .into_iter()
+            .filter(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value)
+            .collect()
+    }
+
+    /// Replaces all occurrences of an option with a single value,
+    /// keeping the position of the first occurrence.
+    pub fn set(&mut self, key: &str, value: impl Into<String>) {
+        let value = value.into();
+        match self.lines.iter().position(|line| line.is_option(key)) {
+            Some(i) => {
+                self.lines[i] = Line::Option {
+                    key: key.to_owned(),
+                    value,
+                    comment: None,
+                };
+                let mut index = 0;
+                self.lines.retain(|line| {
+                    let keep = index <= i || !line.is_option(key);
+                    index += 1;
+                    keep
+                });
+            }
+            None => self.add(key, value),
+        }
+    }
+
+    /// Appends an option, keeping existing occurrences.
+    pub fn add(&mut self, key: &str, value: impl Into<String>) {
+        self.lines.push(Line::Option {
+            key: key.to_owned(),
+            value: value.into(),
+            comment: None,
+        });
+    }
+
+    /// Removes all occurrences of an option.
+    pub fn remove(&mut self, key: &str) {
+        self.lines.retain(|line| !line.is_option(key));
+    }
+
+    /// Returns the `SocksPort` options.
+    pub fn socks_ports(&self) -> Result<Vec<PortConfig>, TorrcError> {
+        self.port_configs("SocksPort")
+    }
+
+    /// Returns the `ControlPort` options.
+    pub fn control_ports(&self) -> Result<Vec<PortConfig>, TorrcError> {
+        self.port_configs("ControlPort")
+    }
+
+    fn port_configs(&self, key: &str) -> Result<Vec<PortConfig>, TorrcError> {
+        self.get_all(key)
+            .into_iter()
+            .map(|value| {
+                value.parse().map_err(|message| TorrcError::InvalidValue {
+                    key: key.to_owned(),
+                    message,
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the first TCP address of the SOCKS proxy.
+    ///
+    /// Without a `SocksPort` option, Tor listens on `127.0.0.1:9050`.
+    /// Returns `None` if the SOCKS proxy is disabled or only listens on Unix sockets
+    /// or automatically chosen ports.
+    pub fn socks_addr(&self) -> Result<Option<SocketAddr>, TorrcError> {
+        let ports = self.socks_ports()?;
+        if ports.is_empty() {
+            return Ok(Some(SocketAddr::new(
+                IpAddr::V4(Ipv4Addr::LOCALHOST),
+                DEFAULT_SOCKS_PORT,
+            )));
+        }
+        Ok(ports.into_iter().find_map(|port| match port.address {
+            PortAddress::Tcp(address) => Some(address),
+            _ => None,
+        }))
+    }
+
+    /// Returns the first address of the SOCKS proxy, which may be a Unix domain socket.
+    ///
+    /// Like [`socks_addr()`], this returns `127.0.0.1:9050` without a `SocksPort` option.
+    ///
+    /// [`socks_addr()`]: #method.socks_addr
+    pub fn socks_proxy(&self) -> Result<Option<ProxyAddr>, TorrcError> {
+        let ports = self.socks_ports()?;
+        if ports.is_empty() {
+            return Ok(self.socks_addr()?.map(ProxyAddr::Tcp));
+        }
+        Ok(ports.into_iter().find_map(|port| match port.address {
+            PortAddress::Tcp(address) => Some(ProxyAddr::Tcp(address)),
+            PortAddress::Unix(path) => Some(ProxyAddr::Unix(path)),
+            _ => None,
+        }))
+    }
+
+    /// Returns the onion services, each `HiddenServiceDir` with the `HiddenServicePort` options following it.
+    pub fn hidden_services(&self) -> Result<Vec<HiddenService>, TorrcError> {
+        let mut services: Vec<HiddenService> = Vec::new();
+        for (key, value) in self.options() {
+            if key.eq_ignore_ascii_case("HiddenServiceDir") {
+                services.push(HiddenService {
+                    dir: PathBuf::from(value),
+                    ports: Vec::new(),
+                });
+            } else if key.eq_ignore_ascii_case("HiddenServicePort") {
+                let invalid = |message| TorrcError::InvalidValue {
+                    key: key.to_owned(),
+                    message,
+                };
+                let service = services
+                    .last_mut()
+                    .ok_or_else(|| invalid("HiddenServicePort before HiddenServiceDir"))?;
+                let mut words = value.split_whitespace();
+                let port = words
+                    .next()
+                    .and_then(|port| port.parse().ok())
+                    .ok_or_else(|| invalid("invalid virtual port"))?;
+                service.ports.push((port, words.next().map(str::to_owned)));
+            }
+        }
+        Ok(services)
+    }
+
+    /// Returns the `Bridge` options.
+    pub fn bridges(&self) -> Result<Vec<Bridge>, TorrcError> {
+        self.get_all("Bridge")
+            .into_iter()
+            .map(|value| {
+                value.parse().map_err(|message| TorrcError::InvalidValue {
+                    key: "Bridge".to_owned(),
+                    message,
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the `ClientTransportPlugin` options.
+    pub fn client_transport_plugins(&self) -> Result<Vec<TransportPlugin>, TorrcError> {
+        self.get_all("ClientTransportPlugin")
+            .into_iter()
+            .map(|value| {
+                value.parse().map_err(|message| TorrcError::InvalidValue {
+                    key: "ClientTransportPlugin".to_owned(),
+                    message,
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the nodes of the `ExitNodes` option,
+    /// such as fingerprints, nicknames or country codes like `{de}`.
+    pub fn exit_nodes(&self) -> Vec<&str> {
+        self.get("ExitNodes")
+            .map(|nodes| {
+                nodes
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|node| !node.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Sets the `ExitNodes` option, or removes it if `nodes` is empty.
+    pub fn set_exit_nodes(&mut self, nodes: &[&str]) {
+        if nodes.is_empty() {
+            self.remove("ExitNodes");
+        } else {
+            self.set("ExitNodes", nodes.join(","));
+        }
+    }
+}
+
+impl Line {
+    fn is_option(&self, name: &str) -> bool {
+        match self {
+            Line::Option { key, .. } => key.eq_ignore_ascii_case(name),
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Torrc {
+    type Err = TorrcError;
+
+    fn from_str(s: &str) -> Result<Torrc, TorrcError> {
+        let mut lines = Vec::new();
+        let mut raw_lines = s.lines().enumerate();
+
+        while let Some((index, raw)) = raw_lines.next() {
+            let number = index + 1;
+            let mut line = raw.trim().to_owned();
+            if line.is_empty() {
+                lines.push(Line::Blank);
+                continue;
+            }
+            if line.starts_with('#') {
+                lines.push(Line::Comment(raw.to_owned()));
+                continue;
+            }
+            // A backslash at the end of a line continues the option on the next line
+            while line.ends_with('\\') {
+                line.pop();
+                match raw_lines.next() {
+                    Some((_, next)) if next.trim_start().starts_with('#') => {}
+                    Some((_, next)) => line.push_str(next.trim()),
+                    None => break,
+                }
+            }
+
+            let syntax = |message| TorrcError::Syntax {
+                line: number,
+                message,
+            };
+            let (key, rest) = match line.split_once(char::is_whitespace) {
+                Some((key, rest)) => (key, rest.trim_start()),
+                None => (line.as_str(), ""),
+            };
+            let (value, comment) = if rest.starts_with('"') {
+                let (value, rest) = unquote(rest).ok_or_else(|| syntax("invalid quoted value"))?;
+                let rest = rest.trim_start();
+                if !rest.is_empty() && !rest.starts_with('#') {
+                    return Err(syntax("unexpected text after quoted value"));
+                }
+                (value, rest)
+            } else {
+                let (value, comment) = rest.split_at(rest.find('#').unwrap_or(rest.len()));
+                (value.trim_end().to_owned(), comment)
+            };
+
+            if key == "%include" {
+                if value.is_empty() {
+                    return Err(syntax("missing %include path"));
+                }
+                lines.push(Line::Include {
+                    path: value,
+                    files: Vec::new(),
+                });
+            } else {
+                lines.push(Line::Option {
+                    key: key.to_owned(),
+                    value,
+                    comment: Some(comment.to_owned()).filter(|comment| !comment.is_empty()),
+                });
+            }
+        }
+
+        Ok(Torrc { lines })
+    }
+}
+
+impl fmt::Display for Torrc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for line in &self.lines {
+            match line {
+                Line::Blank => writeln!(f)?,
+                Line::Comment(comment) => writeln!(f, "{}", comment)?,
+                Line::Option {
+                    key,
+                    value,
+                    comment,
+                } => {
+                    write!(f, "{}", key)?;
+                    if !value.is_empty() {
+                        write!(f, " {}", encode_value(value))?;
+                    }
+                    match comment {
+                        Some(comment) => writeln!(f, " {}", comment)?,
+                        None => writeln!(f)?,
+                    }
+                }
+                Line::Include { path, .. } => writeln!(f, "%include {}", encode_value(path))?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn sorted_entries(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+    Ok(entries)
+}
+
+/// Matches a file name against a pattern, where `*` matches any text and `?` a single character.
+fn glob_match(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|i| glob_match(rest, &name[i..])),
+        Some(('?', rest)) => !name.is_empty() && glob_match(rest, &name[1..]),
+        Some((c, rest)) => name.first() == Some(c) && glob_match(rest, &name[1..]),
+    }
+}
+
+/// Quotes values which would otherwise be read differently.
+fn encode_value(value: &str) -> String {
+    let needs_quotes = value.starts_with(char::is_whitespace)
+        || value.ends_with(char::is_whitespace)
+        || value.contains(['#', '"', '\\', '\n', '\r']);
+    if needs_quotes {
+        quote(value)
+    } else {
+        value.to_owned()
+    }
+}
+
+impl FromStr for PortAddress {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<PortAddress, &'static str> {
+        if let Some(path) = s.strip_prefix("unix:") {
+            let path = match unquote(path) {
+                Some((path, _)) => path,
+                None => path.to_owned(),
+            };
+            return Ok(PortAddress::Unix(PathBuf::from(path)));
+        }
+        if s.eq_ignore_ascii_case("auto") || s.ends_with(":auto") {
+            return Ok(PortAddress::Auto);
+        }
+        if let Ok(port) = s.parse::<u16>() {
+            if port == 0 {
+                return Ok(PortAddress::Disabled);
+            }
+            return Ok(PortAddress::Tcp(SocketAddr::new(
+                IpAddr::V4(Ipv4Addr::LOCALHOST),
+                port,
+            )));
+        }
+        s.parse()
+            .map(PortAddress::Tcp)
+            .map_err(|_| "invalid address")
+    }
+}
+
+impl fmt::Display for PortAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PortAddress::Tcp(address) => address.fmt(f),
+            PortAddress::Unix(path) => {
+                let path = path.to_string_lossy();
+                if path.contains(char::is_whitespace) || path.contains('"') {
+                    write!(f, "unix:{}", quote(&path))
+                } else {
+                    write!(f, "unix:{}", path)
+                }
+            }
+            PortAddress::Auto => f.write_str("auto"),
+            PortAddress::Disabled => f.write_str("0"),
+        }
+    }
+}
+
+impl FromStr for PortConfig {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<PortConfig, &'static str> {
+        let s = s.trim();
+        // Quoted Unix socket paths may contain spaces
+        let (address, flags) = match s.strip_prefix("unix:") {
+            Some(path) if path.starts_with('"') => {
+                let (_, rest) = unquote(path).ok_or("invalid quoted path")?;
+                s.split_at(s.len() - rest.len())
+            }
+            _ => s.split_at(s.find(char::is_whitespace).unwrap_or(s.len())),
+        };
+        Ok(PortConfig {
+            address: address.parse()?,
+            flags: flags.split_whitespace().map(str::to_owned).collect(),
+        })
+    }
+}
+
+impl fmt::Display for PortConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.address.fmt(f)?;
+        for flag in &self.flags {
+            write!(f, " {}", flag)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Bridge {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Bridge, &'static str> {
+        let mut words = s.split_whitespace().peekable();
+        let first = words.next().ok_or("missing bridge address")?;
+        // Addresses contain a colon, transport names don't
+        let (transport, address) = if first.contains(':') {
+            (None, first)
+        } else {
+            let address = words.next().ok_or("missing bridge address")?;
+            (Some(first.to_owned()), address)
+        };
+        let fingerprint = words
+            .next_if(|word| word.len() == 40 && word.chars().all(|c| c.is_ascii_hexdigit()))
+            .map(str::to_owned);
+
+        Ok(Bridge {
+            transport,
+            address: address.to_owned(),
+            fingerprint,
+            args: words.map(str::to_owned).collect(),
+        })
+    }
+}
+
+impl fmt::Display for Bridge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(transport) = &self.transport {
+            write!(f, "{} ", transport)?;
+        }
+        f.write_str(&self.address)?;
+        if let Some(fingerprint) = &self.fingerprint {
+            write!(f, " {}", fingerprint)?;
+        }
+        for arg in &self.args {
+            write!(f, " {}", arg)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for TransportPlugin {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<TransportPlugin, &'static str> {
+        let mut words = s.trim().splitn(3, char::is_whitespace);
+        let transports = words
+            .next()
+            .filter(|t| !t.is_empty())
+            .ok_or("missing transports")?;
+        let method = words.next().ok_or("missing method")?;
+        if !["exec", "socks4", "socks5"].contains(&method) {
+            return Err("method must be exec, socks4 or socks5");
+        }
+        let target = words.next().map(str::trim).unwrap_or("");
+        if target.is_empty() {
+            return Err("missing command or proxy address");
+        }
+
+        Ok(TransportPlugin {
+            transports: transports.split(',').map(str::to_owned).collect(),
+            method: method.to_owned(),
+            target: target.to_owned(),
+        })
+    }
+}
+
+impl fmt::Display for TransportPlugin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}",
+            self.transports.join(","),
+            self.method,
+            self.target
+        )
+    }
+}
@@ -0,0 +1,275 @@
+//! [ureq] agents which connect over Tor.
+//!
+//! A [`TorConnector`] opens a [`TorStream`] for every connection of a ureq agent,
+//! using the settings of a [`TorStreamBuilder`], including isolation and extended errors.
+//! [`agent()`] combines it with ureq's rustls connector for `https`
+//! and with a [`TorResolver`], which keeps ureq from resolving hostnames locally.
+//!
+//! This module requires the `ureq` feature.
+//!
+//! ```no_run
+//! use tor_stream::TorStreamBuilder;
+//!
+//! let agent = tor_stream::ureq::agent(TorStreamBuilder::new());
+//!
+//! let body = agent
+//!     .get("http://www.example.com/")
+//!     .call()
+//!     .expect("Request failed")
+//!     .body_mut()
+//!     .read_to_string()
+//!     .expect("Failed to read the body");
+//! ```
+//!
+//! # Isolation
+//!
+//! ureq keeps connections alive and reuses them for later requests to the same host,
+//! so the isolation of the builder applies per connection, not per request.
+//! With [`Isolation::Random`], disable the pool with `max_idle_connections(0)`
+//! for a circuit per request.
+//!
+//! ureq's transport API is not covered by its semver guarantees,
+//! so this module follows the ureq version used by this crate.
+//!
+//! [ureq]: https://docs.rs/ureq/
+//! [`TorConnector`]: struct.TorConnector.html
+//! [`TorResolver`]: struct.TorResolver.html
+//! [`agent()`]: fn.agent.html
+//! [`TorStream`]: ../struct.TorStream.html
+//! [`TorStreamBuilder`]: ../struct.TorStreamBuilder.html
+//! [`Isolation::Random`]: ../enum.Isolation.html#variant.Random
+
+use crate::{TorError, TorStream, TorStreamBuilder};
+
+use std::io::{self, Read, Write};
+use std::time::Duration;
+use ureq::config::Config;
+use ureq::http::uri::Scheme;
+use ureq::http::Uri;
+use ureq::unversioned::resolver::{ResolvedSocketAddrs, Resolver};
+use ureq::unversioned::transport::{
+    Buffers, ConnectionDetails, Connector, LazyBuffers, NextTimeout, RustlsConnector, Transport,
+};
+use ureq::{Agent, Error, Timeout};
+
+/// A ureq connector which connects over the Tor network.
+///
+/// It opens the connection like [`TorStreamBuilder::connect()`],
+/// passing the hostname to Tor, and can be chained with ureq's TLS connectors.
+/// If the builder has no connect timeout, ureq's connect timeout is used.
+///
+/// ```no_run
+/// use tor_stream::ureq::{TorConnector, TorResolver};
+/// use tor_stream::TorStreamBuilder;
+/// use ureq::unversioned::transport::{Connector, RustlsConnector};
+/// use ureq::Agent;
+///
+/// let connector = TorConnector::new(TorStreamBuilder::new()).chain(RustlsConnector::default());
+/// let agent = Agent::with_parts(Agent::config_builder().build(), connector, TorResolver::new());
+/// ```
+///
+/// [`TorStreamBuilder::connect()`]: ../struct.TorStreamBuilder.html#method.connect
+#[derive(Debug, Clone)]
+pub struct TorConnector {
+    builder: TorStreamBuilder,
+}
+
+/// A ureq transport over a [`TorStream`].
+///
+/// [`TorStream`]: ../struct.TorStream.html
+#[derive(Debug)]
+pub struct TorTransport {
+    stream: TorStream,
+    buffers: LazyBuffers,
+    /// The timeouts of the builder, which apply while ureq sets none.
+    default_read_timeout: Option<Duration>,
+    default_write_timeout: Option<Duration>,
+    /// The timeouts currently set on the socket.
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+}
+
+/// A ureq resolver which never resolves hostnames.
+///
+/// ureq resolves the hostname of every request before connecting, unless a proxy is configured.
+/// This resolver returns no addresses instead, so no DNS request leaves the machine,
+/// and the [`TorConnector`] passes the hostname to Tor.
+/// It must only be used with connectors that ignore the resolved addresses.
+///
+/// [`TorConnector`]: struct.TorConnector.html
+#[derive(Debug, Clone, Default)]
+pub struct TorResolver {
+    _private: (),
+}
+
+/// Builds an agent which connects with `builder`, with the default configuration.
+pub fn agent(builder: TorStreamBuilder) -> Agent {
+    agent_with_config(builder, Agent::config_builder().build())
+}
+
+/// Builds an agent which connects with `builder`, with a custom configuration.
+///
+/// A proxy set in `config` is ignored, since all connections go through Tor.
+///
+/// ```no_run
+/// use tor_stream::{Isolation, TorStreamBuilder};
+/// use ureq::Agent;
+///
+/// // A new circuit for every request
+/// let config = Agent::config_builder().max_idle_connections(0).build();
+/// let agent = tor_stream::ureq::agent_with_config(
+///     TorStreamBuilder::new().isolation(Isolation::Random),
+///     config,
+/// );
+/// ```
+pub fn agent_with_config(builder: TorStreamBuilder, config: Config) -> Agent {
+    let connector = TorConnector::new(builder).chain(RustlsConnector::default());
+    Agent::with_parts(config, connector, TorResolver::new())
+}
+
+impl TorConnector {
+    /// Creates a connector which opens streams with `builder`.
+    pub fn new(builder: TorStreamBuilder) -> TorConnector {
+        TorConnector { builder }
+    }
+}
+
+impl Connector<()> for TorConnector {
+    type Out = TorTransport;
+
+    fn connect(
+        &self,
+        details: &ConnectionDetails,
+        _chained: Option<()>,
+    ) -> Result<Option<TorTransport>, Error> {
+        let uri = details.uri;
+        let host = match uri.host() {
+            // IPv6 addresses are enclosed in brackets
+            Some(host) => host.trim_start_matches('[').trim_end_matches(']'),
+            None => return Err(Error::HostNotFound),
+        };
+        let port = uri.port_u16().unwrap_or_else(|| {
+            if uri.scheme() == Some(&Scheme::HTTPS) {
+                443
+            } else {
+                80
+            }
+        });
+
+        let mut builder = self.builder.clone();
+        if builder.connect_timeout.is_none() {
+            builder.connect_timeout = details.timeout.not_zero().map(|timeout| *timeout);
+        }
+        let stream = builder.connect((host, port)).map_err(|e| match e {
+            TorError::TimedOut => Error::Timeout(Timeout::Connect),
+            e => Error::Io(e.into()),
+        })?;
+        if details.config.no_delay() {
+            if let Some(stream) = stream.get_ref().as_tcp() {
+                stream.set_nodelay(true)?;
+            }
+        }
+
+        Ok(Some(TorTransport {
+            stream,
+            buffers: LazyBuffers::new(
+                details.config.input_buffer_size(),
+                details.config.output_buffer_size(),
+            ),
+            default_read_timeout: self.builder.read_timeout,
+            default_write_timeout: self.builder.write_timeout,
+            read_timeout: self.builder.read_timeout,
+            write_timeout: self.builder.write_timeout,
+        }))
+    }
+}
+
+impl TorTransport {
+    /// Returns the underlying stream.
+    #[inline]
+    pub fn get_ref(&self) -> &TorStream {
+        &self.stream
+    }
+}
+
+impl Transport for TorTransport {
+    fn buffers(&mut self) -> &mut dyn Buffers {
+        &mut self.buffers
+    }
+
+    fn transmit_output(&mut self, amount: usize, timeout: NextTimeout) -> Result<(), Error> {
+        // ureq's timeout takes precedence over the write timeout of the builder
+        let write_timeout = timeout
+            .not_zero()
+            .map(|timeout| *timeout)
+            .or(self.default_write_timeout);
+        if self.write_timeout != write_timeout {
+            self.stream.get_ref().set_write_timeout(write_timeout)?;
+            self.write_timeout = write_timeout;
+        }
+
+        let output = &self.buffers.output()[..amount];
+        self.stream
+            .write_all(output)
+            .map_err(|e| map_io_error(e, timeout))
+    }
+
+    fn await_input(&mut self, timeout: NextTimeout) -> Result<bool, Error> {
+        let read_timeout = timeout
+            .not_zero()
+            .map(|timeout| *timeout)
+            .or(self.default_read_timeout);
+        if self.read_timeout != read_timeout {
+            self.stream.get_ref().set_read_timeout(read_timeout)?;
+            self.read_timeout = read_timeout;
+        }
+
+        let input = self.buffers.input_append_buf();
+        let amount = self
+            .stream
+            .read(input)
+            .map_err(|e| map_io_error(e, timeout))?;
+        self.buffers.input_appended(amount);
+        Ok(amount > 0)
+    }
+
+    fn is_open(&mut self) -> bool {
+        // A closed stream is readable without blocking, while an open one should be silent
+        match self.stream.get_ref().as_tcp() {
+            Some(stream) => {
+                if stream.set_nonblocking(true).is_err() {
+                    return false;
+                }
+                let open = matches!(stream.peek(&mut [0]), Err(e) if e.kind() == io::ErrorKind::WouldBlock);
+                stream.set_nonblocking(false).is_ok() && open
+            }
+            // Unix domain sockets can't be peeked on stable Rust
+            None => true,
+        }
+    }
+}
+
+fn map_io_error(error: io::Error, timeout: NextTimeout) -> Error {
+    match error.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => Error::Timeout(timeout.reason),
+        _ => Error::Io(error),
+    }
+}
+
+impl TorResolver {
+    /// Creates a resolver.
+    pub fn new() -> TorResolver {
+        TorResolver::default()
+    }
+}
+
+impl Resolver for TorResolver {
+    fn resolve(
+        &self,
+        _uri: &Uri,
+        _config: &Config,
+        _timeout: NextTimeout,
+    ) -> Result<ResolvedSocketAddrs, Error> {
+        Ok(self.empty())
+    }
+}
@@ -0,0 +1,54 @@
+extern crate tor_stream;
+
+mod common;
+
+use common::{accept_connect, mock_proxy};
+use tor_stream::{TorError, TorStreamBuilder};
+
+use std::io::{self, Read};
+use std::time::{Duration, Instant};
+
+#[test]
+fn connect_timeout() {
+    let (proxy, handle) = mock_proxy(|mut stream| {
+        // Never reply, until the client gives up
+        let _ = stream.read_to_end(&mut Vec::new());
+    });
+
+    let start = Instant::now();
+    let error = TorStreamBuilder::new()
+        .proxy(proxy)
+        .connect_timeout(Duration::from_millis(200))
+        .connect("example.com:80")
+        .err()
+        .unwrap();
+    assert!(matches!(error, TorError::TimedOut), "{:?}", error);
+    assert!(start.elapsed() < Duration::from_secs(5));
+    handle.join().unwrap();
+}
+
+#[test]
+fn read_timeout() {
+    let (proxy, handle) = mock_proxy(|mut stream| {
+        accept_connect(&mut stream);
+        // Stay silent, until the client gives up
+        let _ = stream.read_to_end(&mut Vec::new());
+    });
+
+    let mut stream = TorStreamBuilder::new()
+        .proxy(proxy)
+        .read_timeout(Duration::from_millis(200))
+        .connect("example.com:80")
+        .unwrap();
+    let error = stream.read(&mut [0; 16]).err().unwrap();
+    assert!(
+        matches!(
+            error.kind(),
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+        ),
+        "{:?}",
+        error
+    );
+    drop(stream);
+    handle.join().unwrap();
+}
@@ -0,0 +1,193 @@
+//! Mock SOCKS5 proxies and control ports shared by the integration tests.
+
+// Every test crate includes this module, but none uses all of it
+#![allow(dead_code)]
+
+use tor_stream::control::TorControl;
+
+use std::convert::TryFrom;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver};
+use std::thread::{self, JoinHandle};
+
+/// The test CA, which signed the certificate of `www.example.com`.
+pub const CA: &[u8] = include_bytes!("../certs/ca.pem");
+pub const CERTIFICATE: &[u8] = include_bytes!("../certs/server.pem");
+pub const KEY: &[u8] = include_bytes!("../certs/server.key");
+
+/// The destination and SOCKS5 credentials of a CONNECT request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Connect {
+    pub target: String,
+    pub credentials: Option<(String, String)>,
+}
+
+impl Connect {
+    /// Returns the password, which serves as the isolation token.
+    pub fn password(&self) -> Option<&str> {
+        self.credentials
+            .as_ref()
+            .map(|(_, password)| password.as_str())
+    }
+}
+
+/// Runs a single-connection mock proxy, which is handled by `f`.
+pub fn mock_proxy<T: Send + 'static>(
+    f: impl FnOnce(TcpStream) -> T + Send + 'static,
+) -> (SocketAddr, JoinHandle<T>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let handle = thread::spawn(move || f(listener.accept().unwrap().0));
+    (address, handle)
+}
+
+/// Runs a mock proxy answering consecutive HTTP connections with `responses`.
+/// Returns the CONNECT request and the request head of each connection.
+pub fn mock_http_proxy(
+    responses: Vec<&'static [u8]>,
+) -> (SocketAddr, JoinHandle<Vec<(Connect, String)>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let handle = thread::spawn(move || {
+        responses
+            .into_iter()
+            .map(|response| {
+                let (mut stream, _) = listener.accept().unwrap();
+                let connect = accept_connect(&mut stream);
+                let head = read_head(&stream);
+                stream.write_all(response).unwrap();
+                (connect, head)
+            })
+            .collect()
+    });
+    (address, handle)
+}
+
+pub fn read_vec(stream: &mut impl Read, len: usize) -> Vec<u8> {
+    let mut buf = vec![0; len];
+    stream.read_exact(&mut buf).unwrap();
+    buf
+}
+
+/// Accepts the greeting, with username/password authentication if offered,
+/// and returns the CONNECT request. The reply is left to the caller.
+pub fn accept_request(stream: &mut TcpStream) -> Connect {
+    let len = read_vec(stream, 2)[1] as usize;
+    let mut credentials = None;
+    if read_vec(stream, len).contains(&2) {
+        stream.write_all(&[5, 2]).unwrap();
+        let len = read_vec(stream, 2)[1] as usize;
+        let username = String::from_utf8(read_vec(stream, len)).unwrap();
+        let len = read_vec(stream, 1)[0] as usize;
+        let password = String::from_utf8(read_vec(stream, len)).unwrap();
+        stream.write_all(&[1, 0]).unwrap();
+        credentials = Some((username, password));
+    } else {
+        stream.write_all(&[5, 0]).unwrap();
+    }
+
+    let request = read_vec(stream, 4);
+    assert_eq!(request[..3], [5, 1, 0]);
+    let ip: Option<IpAddr> = match request[3] {
+        1 => Some(<[u8; 4]>::try_from(read_vec(stream, 4)).unwrap().into()),
+        4 => Some(<[u8; 16]>::try_from(read_vec(stream, 16)).unwrap().into()),
+        _ => None,
+    };
+    let target = match ip {
+        Some(ip) => SocketAddr::new(ip, read_port(stream)).to_string(),
+        None => {
+            assert_eq!(request[3], 3, "unexpected address type");
+            let len = read_vec(stream, 1)[0] as usize;
+            let host = String::from_utf8(read_vec(stream, len)).unwrap();
+            format!("{}:{}", host, read_port(stream))
+        }
+    };
+    Connect {
+        target,
+        credentials,
+    }
+}
+
+fn read_port(stream: &mut TcpStream) -> u16 {
+    let port = read_vec(stream, 2);
+    u16::from_be_bytes([port[0], port[1]])
+}
+
+/// Sends a reply with `code` and an unspecified bound address.
+pub fn reply(stream: &mut TcpStream, code: u8) {
+    stream
+        .write_all(&[5, code, 0, 1, 0, 0, 0, 0, 0, 0])
+        .unwrap();
+}
+
+/// Accepts a CONNECT request and replies with success.
+pub fn accept_connect(stream: &mut TcpStream) -> Connect {
+    let connect = accept_request(stream);
+    reply(stream, 0);
+    connect
+}
+
+/// Reads an HTTP request head.
+pub fn read_head(stream: impl Read) -> String {
+    let mut reader = BufReader::new(stream);
+    let mut head = String::new();
+    while !head.ends_with("\r\n\r\n") {
+        assert!(reader.read_line(&mut head).unwrap() > 0);
+    }
+    head
+}
+
+/// Returns a rustls server configuration with the certificate of `www.example.com`.
+#[cfg(feature = "rustls")]
+pub fn tls_server_config() -> std::sync::Arc<rustls::ServerConfig> {
+    use rustls::crypto::ring;
+    use rustls::pki_types::pem::PemObject;
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+    use std::sync::Arc;
+
+    let config = rustls::ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![CertificateDer::from_pem_slice(CERTIFICATE).unwrap()],
+            PrivateKeyDer::from_pem_slice(KEY).unwrap(),
+        )
+        .unwrap();
+    Arc::new(config)
+}
+
+/// Runs a fake control port, which expects the commands of `script` in order
+/// and answers each with the associated reply.
+/// A command ending with `*` matches every command starting with the rest of it.
+/// The received commands are sent through the returned channel.
+/// No further commands are allowed until the connection is closed.
+pub fn fake_control(
+    script: Vec<(&'static str, &'static str)>,
+) -> (TorControl, Receiver<String>, JoinHandle<()>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let (sender, receiver) = mpsc::channel();
+    let handle = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+        for (command, reply) in script {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let line = line.trim_end_matches("\r\n");
+            match command.strip_suffix('*') {
+                Some(prefix) => assert!(line.starts_with(prefix), "unexpected command {:?}", line),
+                None => assert_eq!(line, command, "unexpected command"),
+            }
+            let _ = sender.send(line.to_owned());
+            writer.write_all(reply.as_bytes()).unwrap();
+        }
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "", "unexpected command");
+    });
+
+    (TorControl::connect(address).unwrap(), receiver, handle)
+}
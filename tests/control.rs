@@ -0,0 +1,335 @@
+extern crate tor_stream;
+
+mod common;
+
+use common::fake_control;
+use tor_stream::control::{ControlError, NewIdentity, Signal, TorControl};
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn get_info() {
+    let (mut control, _, handle) = fake_control(vec![
+        ("GETINFO version", "250-version=0.4.8.9\r\n250 OK\r\n"),
+        (
+            "GETINFO net/listeners/socks config-text",
+            "650 CIRC 1 BUILT\r\n\
+             250-net/listeners/socks=\"127.0.0.1:9050\"\r\n\
+             250+config-text=\r\n\
+             SocksPort 9050\r\n\
+             ..hidden\r\n\
+             .\r\n\
+             250 OK\r\n",
+        ),
+    ]);
+
+    assert_eq!(control.get_info("version").unwrap(), "0.4.8.9");
+
+    let info = control
+        .get_info_many(&["net/listeners/socks", "config-text"])
+        .unwrap();
+    assert_eq!(info["net/listeners/socks"], "\"127.0.0.1:9050\"");
+    assert_eq!(info["config-text"], "SocksPort 9050\n.hidden");
+
+    let events = control.take_events();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].keyword(), "CIRC");
+    drop(control);
+    handle.join().unwrap();
+}
+
+#[test]
+fn conf() {
+    let (mut control, _, handle) = fake_control(vec![
+        (
+            "GETCONF SocksPort DataDirectory ExitNodes",
+            "250-SocksPort=9050\r\n\
+             250-SocksPort=\"unix:/run/tor/socks WorldWritable\"\r\n\
+             250-DataDirectory=/var/lib/tor\r\n\
+             250 ExitNodes\r\n",
+        ),
+        (
+            "SETCONF ExitNodes=\"{de},{nl}\" Nickname=\"a \\\"b\\\"\" SocksPort",
+            "250 OK\r\n",
+        ),
+        (
+            "SETCONF Foo=\"1\"",
+            "552 Unrecognized option: Unknown option 'Foo'.  Failing.\r\n",
+        ),
+    ]);
+
+    assert_eq!(
+        control
+            .get_conf(&["SocksPort", "DataDirectory", "ExitNodes"])
+            .unwrap(),
+        vec![
+            ("SocksPort".to_owned(), Some("9050".to_owned())),
+            (
+                "SocksPort".to_owned(),
+                Some("unix:/run/tor/socks WorldWritable".to_owned())
+            ),
+            ("DataDirectory".to_owned(), Some("/var/lib/tor".to_owned())),
+            ("ExitNodes".to_owned(), None),
+        ]
+    );
+
+    control
+        .set_conf(&[
+            ("ExitNodes", Some("{de},{nl}")),
+            ("Nickname", Some("a \"b\"")),
+            ("SocksPort", None),
+        ])
+        .unwrap();
+
+    match control.set_conf(&[("Foo", Some("1"))]) {
+        Err(ControlError::Reply(reply)) => {
+            assert_eq!(reply.code(), 552);
+            assert!(reply.message().starts_with("Unrecognized option"));
+        }
+        result => panic!("unexpected result {:?}", result),
+    }
+    drop(control);
+    handle.join().unwrap();
+}
+
+#[test]
+fn protocol_info() {
+    let (mut control, _, handle) = fake_control(vec![(
+        "PROTOCOLINFO 1",
+        "250-PROTOCOLINFO 1\r\n\
+         250-AUTH METHODS=COOKIE,SAFECOOKIE,HASHEDPASSWORD COOKIEFILE=\"/var/run/tor/control \\\"auth\\\".cookie\"\r\n\
+         250-VERSION Tor=\"0.4.8.9\"\r\n\
+         250 OK\r\n",
+    )]);
+
+    let info = control.protocol_info().unwrap();
+    assert_eq!(info.protocol_version, 1);
+    assert_eq!(info.tor_version, "0.4.8.9");
+    assert_eq!(
+        info.auth_methods,
+        ["COOKIE", "SAFECOOKIE", "HASHEDPASSWORD"]
+    );
+    assert_eq!(
+        info.cookie_file.as_deref(),
+        Some(Path::new("/var/run/tor/control \"auth\".cookie"))
+    );
+    drop(control);
+    handle.join().unwrap();
+}
+
+#[test]
+fn events() {
+    let (mut control, _, handle) = fake_control(vec![
+        ("SETEVENTS NOTICE STATUS_CLIENT", "250 OK\r\n"),
+        (
+            "SIGNAL NEWNYM",
+            "250 OK\r\n\
+             650 NOTICE Rate limiting NEWNYM request: delaying by 8 second(s)\r\n\
+             650-STATUS_CLIENT NOTICE CIRCUIT_ESTABLISHED\r\n\
+             650 OK\r\n",
+        ),
+    ]);
+
+    control.set_events(&["NOTICE", "STATUS_CLIENT"]).unwrap();
+    control.signal(Signal::NewNym).unwrap();
+
+    let notice = control.next_event().unwrap();
+    assert_eq!(notice.code(), 650);
+    assert_eq!(notice.keyword(), "NOTICE");
+    let status = control.next_event().unwrap();
+    assert_eq!(status.keyword(), "STATUS_CLIENT");
+    assert_eq!(status.lines().len(), 2);
+    drop(control);
+    handle.join().unwrap();
+}
+
+#[test]
+fn malformed_reply() {
+    let (mut control, _, handle) = fake_control(vec![("GETINFO version", "250\u{e9}\r\n")]);
+
+    match control.get_info("version") {
+        Err(ControlError::Protocol(_)) => {}
+        result => panic!("unexpected result {:?}", result),
+    }
+    drop(control);
+    handle.join().unwrap();
+}
+
+#[test]
+fn authenticate() {
+    let (mut control, _, handle) = fake_control(vec![
+        (
+            "PROTOCOLINFO 1",
+            "250-PROTOCOLINFO 1\r\n\
+             250-AUTH METHODS=HASHEDPASSWORD\r\n\
+             250-VERSION Tor=\"0.4.8.9\"\r\n\
+             250 OK\r\n",
+        ),
+        ("AUTHENTICATE \"pass \\\"word\\\"\"", "250 OK\r\n"),
+        (
+            "AUTHENTICATE",
+            "515 Authentication failed: Password did not match\r\n",
+        ),
+    ]);
+
+    control.set_password("pass \"word\"");
+    control.authenticate_auto().unwrap();
+    match control.authenticate_null() {
+        Err(ControlError::Reply(reply)) => assert_eq!(reply.code(), 515),
+        result => panic!("unexpected result {:?}", result),
+    }
+    drop(control);
+    handle.join().unwrap();
+}
+
+#[test]
+fn authenticate_safecookie() {
+    use hmac::{Hmac, Mac};
+    use sha2::Sha256;
+
+    fn hmac(key: &[u8], message: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+        mac.update(message);
+        mac.finalize()
+            .into_bytes()
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect()
+    }
+
+    let cookie = [7; 32];
+    let cookie_file =
+        std::env::temp_dir().join(format!("tor-stream-control-{}.cookie", std::process::id()));
+    std::fs::write(&cookie_file, cookie).unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let path = cookie_file.display().to_string();
+    let handle = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+        let mut line = String::new();
+
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "PROTOCOLINFO 1\r\n");
+        write!(
+            writer,
+            "250-PROTOCOLINFO 1\r\n\
+             250-AUTH METHODS=COOKIE,SAFECOOKIE COOKIEFILE=\"{}\"\r\n\
+             250-VERSION Tor=\"0.4.8.9\"\r\n\
+             250 OK\r\n",
+            path
+        )
+        .unwrap();
+
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        let client_nonce = line
+            .strip_prefix("AUTHCHALLENGE SAFECOOKIE ")
+            .unwrap()
+            .trim_end();
+        let server_nonce = [9; 32];
+        let mut message = cookie.to_vec();
+        message.extend(
+            (0..client_nonce.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&client_nonce[i..i + 2], 16).unwrap()),
+        );
+        message.extend_from_slice(&server_nonce);
+        write!(
+            writer,
+            "250 AUTHCHALLENGE SERVERHASH={} SERVERNONCE={}\r\n",
+            hmac(
+                b"Tor safe cookie authentication server-to-controller hash",
+                &message
+            ),
+            "09".repeat(32)
+        )
+        .unwrap();
+
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(
+            line,
+            format!(
+                "AUTHENTICATE {}\r\n",
+                hmac(
+                    b"Tor safe cookie authentication controller-to-server hash",
+                    &message
+                )
+            )
+        );
+        writer.write_all(b"250 OK\r\n").unwrap();
+    });
+
+    let mut control = TorControl::connect(address).unwrap();
+    control.authenticate_auto().unwrap();
+    handle.join().unwrap();
+    std::fs::remove_file(cookie_file).unwrap();
+}
+
+#[test]
+fn new_identity() {
+    let (mut control, _, handle) = fake_control(vec![
+        ("SETEVENTS NOTICE", "250 OK\r\n"),
+        (
+            "SIGNAL NEWNYM",
+            "250 OK\r\n\
+             650 NOTICE Rate limiting NEWNYM request: delaying by 8 second(s)\r\n",
+        ),
+        ("GETINFO version", "250-version=0.4.8.9\r\n250 OK\r\n"),
+        ("SETEVENTS", "250 OK\r\n"),
+        ("SETEVENTS CIRC", "250 OK\r\n"),
+        ("SETEVENTS CIRC NOTICE", "250 OK\r\n"),
+        ("SIGNAL NEWNYM", "250 OK\r\n"),
+        (
+            "GETINFO version",
+            "650 NOTICE New control connection opened.\r\n\
+             650 CIRC 1 BUILT\r\n\
+             250-version=0.4.8.9\r\n\
+             250 OK\r\n",
+        ),
+        ("SETEVENTS CIRC", "250 OK\r\n"),
+    ]);
+
+    assert_eq!(
+        control.new_identity().unwrap(),
+        NewIdentity::RateLimited(Duration::from_secs(8))
+    );
+    assert!(control.take_events().is_empty());
+
+    control.set_events(&["CIRC"]).unwrap();
+    assert_eq!(control.new_identity().unwrap(), NewIdentity::Applied);
+    let events = control.take_events();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].keyword(), "CIRC");
+    drop(control);
+    handle.join().unwrap();
+}
+
+#[test]
+fn new_identity_failed() {
+    let (mut control, _, handle) = fake_control(vec![
+        ("SETEVENTS CIRC", "250 OK\r\n"),
+        ("SETEVENTS CIRC NOTICE", "250 OK\r\n"),
+        (
+            "SIGNAL NEWNYM",
+            "552 Unrecognized signal code \"NEWNYM\"\r\n",
+        ),
+        // NOTICE is unsubscribed although the signal failed
+        ("SETEVENTS CIRC", "250 OK\r\n"),
+    ]);
+
+    control.set_events(&["CIRC"]).unwrap();
+    match control.new_identity() {
+        Err(ControlError::Reply(reply)) => assert_eq!(reply.code(), 552),
+        result => panic!("unexpected result {:?}", result),
+    }
+    drop(control);
+    handle.join().unwrap();
+}
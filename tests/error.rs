@@ -0,0 +1,33 @@
+extern crate tor_stream;
+
+use tor_stream::TorError;
+
+use std::io;
+
+#[test]
+fn reply_code_round_trip() {
+    assert!(TorError::from_reply_code(0x00).is_none());
+    for code in (0x01..=0x08).chain(0xF0..=0xF7) {
+        let error = TorError::from_reply_code(code).unwrap();
+        assert!(
+            !matches!(error, TorError::UnknownReply(_)),
+            "{:#04x} is not decoded",
+            code
+        );
+        assert_eq!(error.reply_code(), Some(code));
+        assert_eq!(error.is_onion_service_error(), code >= 0xF0);
+    }
+
+    let error = TorError::from_reply_code(0x42).unwrap();
+    assert!(matches!(error, TorError::UnknownReply(0x42)), "{:?}", error);
+    assert_eq!(error.reply_code(), Some(0x42));
+    assert_eq!(TorError::TimedOut.reply_code(), None);
+}
+
+#[test]
+fn io_error() {
+    let error: io::Error = TorError::from_reply_code(0x05).unwrap().into();
+    assert_eq!(error.kind(), io::ErrorKind::ConnectionRefused);
+    let error = TorError::from_io_error(&error).unwrap();
+    assert!(matches!(error, TorError::ConnectionRefused), "{:?}", error);
+}
@@ -0,0 +1,92 @@
+#![cfg(feature = "futures-io")]
+
+extern crate tor_stream;
+
+use tor_stream::futures::TorStream;
+use tor_stream::{Isolation, TargetAddr, TorStreamBuilder};
+
+use futures::executor::block_on;
+use futures::io::{AllowStdIo, AsyncReadExt, AsyncWriteExt};
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+#[test]
+fn connect_with_transport() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let proxy = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut buf = [0; 3];
+        stream.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [5, 1, 0]);
+        stream.write_all(&[5, 0]).unwrap();
+
+        let mut request = vec![0; 4 + 4 + 2];
+        stream.read_exact(&mut request).unwrap();
+        stream.write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0]).unwrap();
+
+        stream.write_all(b"pong").unwrap();
+        request
+    });
+
+    block_on(async {
+        let transport = AllowStdIo::new(TcpStream::connect(proxy).unwrap());
+        let mut stream = TorStream::connect_with_transport(transport, "10.1.2.3:8080")
+            .await
+            .unwrap();
+        assert_eq!(
+            stream.target_addr(),
+            &TargetAddr::Ip("10.1.2.3:8080".parse().unwrap())
+        );
+
+        let mut buf = [0; 4];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"pong");
+    });
+
+    assert_eq!(
+        server.join().unwrap(),
+        [5, 1, 0, 1, 10, 1, 2, 3, 0x1F, 0x90]
+    );
+}
+
+#[test]
+fn isolation() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let proxy = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut buf = [0; 3];
+        stream.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [5, 1, 2]);
+        stream.write_all(&[5, 2]).unwrap();
+
+        let mut auth = vec![0; 1 + 1 + 1 + 1 + 1];
+        stream.read_exact(&mut auth).unwrap();
+        stream.write_all(&[1, 0]).unwrap();
+
+        let mut request = vec![0; 5 + 11 + 2];
+        stream.read_exact(&mut request).unwrap();
+        stream.write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0]).unwrap();
+
+        let mut buf = [0; 4];
+        stream.read_exact(&mut buf).unwrap();
+        (auth, buf)
+    });
+
+    block_on(async {
+        let transport = AllowStdIo::new(TcpStream::connect(proxy).unwrap());
+        let mut stream = TorStreamBuilder::new()
+            .isolation(Isolation::credentials("a", "b"))
+            .connect_with_transport(transport, "example.com:80")
+            .await
+            .unwrap();
+        stream.write_all(b"ping").await.unwrap();
+        stream.flush().await.unwrap();
+    });
+
+    let (auth, data) = server.join().unwrap();
+    assert_eq!(auth, b"\x01\x01a\x01b");
+    assert_eq!(&data, b"ping");
+}
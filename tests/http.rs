@@ -0,0 +1,266 @@
+extern crate tor_stream;
+
+mod common;
+
+use common::mock_http_proxy;
+use tor_stream::http::{Client, HttpError, Request};
+use tor_stream::TorStreamBuilder;
+
+use std::net::SocketAddr;
+
+fn client(proxy: SocketAddr) -> Client {
+    Client::new().stream_builder(TorStreamBuilder::new().proxy(proxy))
+}
+
+#[test]
+fn content_length() {
+    let (proxy, handle) = mock_http_proxy(vec![
+        b"HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 5\r\n\r\nhello, trailing",
+    ]);
+
+    let response = client(proxy)
+        .header("User-Agent", "test")
+        .get("http://www.example.com:8080/path?query#fragment")
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.reason(), "OK");
+    assert_eq!(response.header("content-type"), Some("text/plain"));
+    assert_eq!(response.text().unwrap(), "hello");
+    assert_eq!(response.url(), "http://www.example.com:8080/path?query");
+
+    let requests = handle.join().unwrap();
+    assert_eq!(requests[0].0.target, "www.example.com:8080");
+    assert_eq!(
+        requests[0].1,
+        "GET /path?query HTTP/1.1\r\nHost: www.example.com:8080\r\nUser-Agent: test\r\nConnection: close\r\n\r\n"
+    );
+}
+
+#[test]
+fn reserved_headers() {
+    let (proxy, handle) = mock_http_proxy(vec![b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n"]);
+
+    let request = Request::new("POST", "http://example.com/form")
+        .header("host", "evil.example")
+        .header("Connection", "keep-alive")
+        .header("Content-Length", "100")
+        .header("Content-Type", "text/plain")
+        .body("data");
+    client(proxy)
+        .header("HOST", "evil.example")
+        .send(&request)
+        .unwrap();
+
+    // The headers set by the client are not duplicated
+    let requests = handle.join().unwrap();
+    assert_eq!(
+        requests[0].1,
+        "POST /form HTTP/1.1\r\nHost: example.com\r\nContent-Type: text/plain\r\n\
+         Content-Length: 4\r\nConnection: close\r\n\r\n"
+    );
+}
+
+#[test]
+fn chunked() {
+    let (proxy, handle) = mock_http_proxy(vec![
+        b"HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+          5;ext=1\r\nhello\r\n7\r\n, world\r\n0\r\nExpires: never\r\n\r\n",
+    ]);
+
+    let response = client(proxy).get("http://example.com").unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body(), b"hello, world");
+    handle.join().unwrap();
+}
+
+#[test]
+fn until_eof() {
+    let (proxy, handle) = mock_http_proxy(vec![
+        b"HTTP/1.0 404 Not Found\r\nServer: test\r\n\r\nnot found",
+        b"HTTP/1.1 200 OK\r\nContent-Length: 9\r\n\r\n",
+        b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhel",
+    ]);
+    let client = client(proxy);
+
+    let response = client.get("http://example.com/missing").unwrap();
+    assert!(!response.is_success());
+    assert_eq!(response.status(), 404);
+    assert_eq!(response.body(), b"not found");
+
+    // No body is expected for HEAD requests, regardless of Content-Length
+    let response = client.head("http://example.com/").unwrap();
+    assert_eq!(response.body(), b"");
+
+    match client.get("http://example.com/") {
+        Err(HttpError::Protocol(_)) => {}
+        result => panic!("unexpected result {:?}", result),
+    }
+    handle.join().unwrap();
+}
+
+#[test]
+fn body_length() {
+    let (proxy, handle) = mock_http_proxy(vec![
+        b"HTTP/1.1 200 OK\r\nContent-Length: 5, 5\r\nContent-Length: 5\r\n\r\nhello",
+        b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nContent-Length: 100\r\n\r\nhello",
+        b"HTTP/1.1 200 OK\r\nContent-Length: 11\r\n\r\nhello again",
+        b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n6\r\n again\r\n0\r\n\r\n",
+        b"HTTP/1.0 200 OK\r\n\r\nhello again",
+        b"HTTP/1.0 200 OK\r\n\r\nhello",
+    ]);
+    let client = client(proxy).max_body_len(10);
+
+    assert_eq!(client.get("http://example.com/").unwrap().body(), b"hello");
+    match client.get("http://example.com/") {
+        Err(HttpError::Protocol(_)) => {}
+        result => panic!("unexpected result {:?}", result),
+    }
+    for _ in 0..3 {
+        match client.get("http://example.com/") {
+            Err(HttpError::BodyTooLong) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+    assert_eq!(client.get("http://example.com/").unwrap().body(), b"hello");
+    handle.join().unwrap();
+}
+
+#[test]
+fn redirects() {
+    let (proxy, handle) = mock_http_proxy(vec![
+        b"HTTP/1.1 303 See Other\r\nLocation: /next\r\nContent-Length: 0\r\n\r\n",
+        b"HTTP/1.1 307 Temporary Redirect\r\nLocation: http://other.example.com/final\r\n\r\n",
+        b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\ndone",
+    ]);
+
+    let request = Request::new("PUT", "http://example.com/start")
+        .header("Cookie", "secret")
+        .body("data");
+    let response = client(proxy).send(&request).unwrap();
+    assert_eq!(response.body(), b"done");
+    assert_eq!(response.url(), "http://other.example.com/final");
+
+    let requests = handle.join().unwrap();
+    let targets: Vec<&str> = requests
+        .iter()
+        .map(|(connect, _)| connect.target.as_str())
+        .collect();
+    assert_eq!(
+        targets,
+        ["example.com:80", "example.com:80", "other.example.com:80"]
+    );
+    assert!(requests[0].1.starts_with("PUT /start HTTP/1.1\r\n"));
+    assert!(requests[0]
+        .1
+        .contains("Cookie: secret\r\nContent-Length: 4\r\n"));
+    assert_eq!(
+        requests[1].1,
+        "GET /next HTTP/1.1\r\nHost: example.com\r\nCookie: secret\r\nConnection: close\r\n\r\n"
+    );
+    assert_eq!(
+        requests[2].1,
+        "GET /final HTTP/1.1\r\nHost: other.example.com\r\nConnection: close\r\n\r\n"
+    );
+}
+
+#[test]
+fn redirect_policy() {
+    const ONION: &str = "http://2gzyxa5ihm7nsggfxnu52rck2vv4rvmdlkiu3zzui5du4xyclen53wid.onion/";
+    const REDIRECT: &[u8] = b"HTTP/1.1 302 Found\r\nLocation: http://2gzyxa5ihm7nsggfxnu52rck2vv4rvmdlkiu3zzui5du4xyclen53wid.onion/\r\n\r\n";
+
+    let (proxy, handle) = mock_http_proxy(vec![REDIRECT, REDIRECT, REDIRECT, REDIRECT]);
+    let client = client(proxy);
+
+    match client.get("http://example.com/") {
+        Err(HttpError::OnionRedirect(url)) => assert_eq!(url, ONION),
+        result => panic!("unexpected result {:?}", result),
+    }
+
+    let response = client
+        .clone()
+        .max_redirects(0)
+        .get("http://example.com/")
+        .unwrap();
+    assert_eq!(response.status(), 302);
+    assert_eq!(response.header("Location"), Some(ONION));
+
+    // The onion service redirects to itself
+    match client
+        .onion_redirects(true)
+        .max_redirects(1)
+        .get("http://example.com/")
+    {
+        Err(HttpError::TooManyRedirects) => {}
+        result => panic!("unexpected result {:?}", result),
+    }
+    assert_eq!(
+        handle.join().unwrap()[3].0.target,
+        "2gzyxa5ihm7nsggfxnu52rck2vv4rvmdlkiu3zzui5du4xyclen53wid.onion:80"
+    );
+}
+
+#[test]
+fn invalid_input() {
+    let client = Client::new();
+    assert!(matches!(
+        client.get("example.com"),
+        Err(HttpError::InvalidUrl(_))
+    ));
+    assert!(matches!(
+        client.get("http://user@example.com/"),
+        Err(HttpError::InvalidUrl(_))
+    ));
+    assert!(matches!(
+        client.get("ftp://example.com/"),
+        Err(HttpError::UnsupportedScheme(_))
+    ));
+    assert!(matches!(
+        client.send(&Request::new("GET", "http://example.com/").header("X", "a\r\nB: c")),
+        Err(HttpError::InvalidHeader(_))
+    ));
+    // The method is checked before connecting, since it ends up in the request line
+    assert!(matches!(
+        client.send(&Request::new(
+            "GET / HTTP/1.1\r\nX-Evil:",
+            "http://example.com/"
+        )),
+        Err(HttpError::InvalidMethod(_))
+    ));
+    for url in &[
+        "http://host\r\nX-Evil: 1/",
+        "http://exa mple.com/",
+        "http://host\x7f/",
+    ] {
+        assert!(
+            matches!(client.get(url), Err(HttpError::InvalidUrl(_))),
+            "{:?}",
+            url
+        );
+    }
+}
+
+#[test]
+fn unsafe_path() {
+    let (proxy, handle) = mock_http_proxy(vec![
+        b"HTTP/1.1 302 Found\r\nLocation: next page\x7f\r\nContent-Length: 0\r\n\r\n",
+        b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n",
+    ]);
+
+    // Whitespace and control characters are percent-encoded instead of ending the request line
+    let response = client(proxy)
+        .get("http://example.com/a b\r\nX-Evil: 1?q=\u{e4}")
+        .unwrap();
+    assert_eq!(response.url(), "http://example.com/next%20page%7F");
+
+    let requests = handle.join().unwrap();
+    assert!(
+        requests[0]
+            .1
+            .starts_with("GET /a%20b%0D%0AX-Evil:%201?q=%C3%A4 HTTP/1.1\r\nHost: example.com\r\n"),
+        "{}",
+        requests[0].1
+    );
+    assert!(requests[1]
+        .1
+        .starts_with("GET /next%20page%7F HTTP/1.1\r\n"));
+}
@@ -0,0 +1,133 @@
+#![cfg(feature = "hyper")]
+
+extern crate tor_stream;
+
+mod common;
+
+use common::mock_http_proxy;
+use http_body_util::{BodyExt, Empty};
+use hyper::body::Bytes;
+use hyper::Uri;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+use tor_stream::hyper::{ConnectError, TorConnector};
+use tor_stream::{Isolation, TorStreamBuilder};
+
+const RESPONSE: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello";
+
+async fn get(client: &Client<TorConnector, Empty<Bytes>>, uri: &str) -> Bytes {
+    let response = client.get(uri.parse().unwrap()).await.unwrap();
+    assert_eq!(response.status(), 200);
+    response.into_body().collect().await.unwrap().to_bytes()
+}
+
+#[tokio::test]
+async fn http() {
+    let (proxy, handle) = mock_http_proxy(vec![RESPONSE]);
+    let connector = TorConnector::new().stream_builder(TorStreamBuilder::new().proxy(proxy));
+    let client = Client::builder(TokioExecutor::new()).build(connector);
+
+    assert_eq!(get(&client, "http://www.example.com:8080/").await, "hello");
+    let (connect, head) = &handle.join().unwrap()[0];
+    assert_eq!(connect.target, "www.example.com:8080");
+    assert_eq!(connect.credentials, None);
+    assert!(head.starts_with("GET / HTTP/1.1\r\n"), "{}", head);
+}
+
+#[tokio::test]
+async fn isolation() {
+    let (proxy, handle) = mock_http_proxy(vec![RESPONSE; 2]);
+    let connector = TorConnector::new().stream_builder(
+        TorStreamBuilder::new()
+            .proxy(proxy)
+            .isolation(Isolation::Random),
+    );
+    let client = Client::builder(TokioExecutor::new())
+        .pool_max_idle_per_host(0)
+        .build(connector);
+
+    get(&client, "http://example.com/").await;
+    get(&client, "http://example.com/").await;
+    let connections = handle.join().unwrap();
+    assert!(connections[0].0.password().is_some());
+    assert_ne!(connections[0].0.password(), connections[1].0.password());
+}
+
+#[tokio::test]
+async fn isolation_fn() {
+    let (proxy, handle) = mock_http_proxy(vec![RESPONSE; 3]);
+    let connector = TorConnector::new()
+        .stream_builder(TorStreamBuilder::new().proxy(proxy))
+        .isolation_fn(|uri| Isolation::credentials("host", uri.host().unwrap()));
+    let client = Client::builder(TokioExecutor::new()).build(connector);
+
+    get(&client, "http://a.example/").await;
+    get(&client, "http://b.example/").await;
+    get(&client, "http://a.example/other").await;
+
+    // Each connection uses the isolation chosen for its host
+    let connections = handle.join().unwrap();
+    let tokens: Vec<_> = connections
+        .iter()
+        .map(|(connect, _)| connect.password())
+        .collect();
+    assert_eq!(
+        tokens,
+        [Some("a.example"), Some("b.example"), Some("a.example")]
+    );
+}
+
+#[tokio::test]
+async fn unsupported_scheme() {
+    let connector = TorConnector::new();
+    for uri in &["ftp://example.com/", "/path"] {
+        match connector.connect(uri.parse::<Uri>().unwrap()).await {
+            Err(ConnectError::UnsupportedScheme(_)) => {}
+            result => panic!("unexpected result {:?}", result.map(|_| ())),
+        }
+    }
+    #[cfg(not(feature = "hyper-rustls"))]
+    match connector
+        .connect(Uri::from_static("https://example.com/"))
+        .await
+    {
+        Err(ConnectError::UnsupportedScheme(_)) => {}
+        result => panic!("unexpected result {:?}", result.map(|_| ())),
+    }
+}
+
+#[cfg(feature = "hyper-rustls")]
+#[tokio::test]
+async fn https() {
+    use common::{accept_connect, mock_proxy, read_head, tls_server_config, CA};
+    use rustls::pki_types::pem::PemObject;
+    use rustls::pki_types::CertificateDer;
+    use rustls::{RootCertStore, ServerConnection, StreamOwned};
+    use std::io::Write;
+    use tor_stream::rustls::TlsConnector;
+
+    let (proxy, handle) = mock_proxy(|mut stream| {
+        let connect = accept_connect(&mut stream);
+        let connection = ServerConnection::new(tls_server_config()).unwrap();
+        let mut tls = StreamOwned::new(connection, stream);
+        let head = read_head(&mut tls);
+        tls.write_all(RESPONSE).unwrap();
+        tls.conn.send_close_notify();
+        tls.flush().unwrap();
+        (connect, head)
+    });
+
+    let mut roots = RootCertStore::empty();
+    roots
+        .add(CertificateDer::from_pem_slice(CA).unwrap())
+        .unwrap();
+    let connector = TorConnector::new()
+        .stream_builder(TorStreamBuilder::new().proxy(proxy))
+        .tls_connector(TlsConnector::with_root_store(roots));
+    let client = Client::builder(TokioExecutor::new()).build(connector);
+
+    assert_eq!(get(&client, "https://www.example.com/").await, "hello");
+    let (connect, head) = handle.join().unwrap();
+    assert_eq!(connect.target, "www.example.com:443");
+    assert!(head.starts_with("GET / HTTP/1.1\r\n"), "{}", head);
+}
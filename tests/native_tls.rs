@@ -0,0 +1,109 @@
+#![cfg(feature = "native-tls")]
+
+extern crate tor_stream;
+
+mod common;
+
+use common::{accept_connect, mock_proxy, CA, CERTIFICATE, KEY};
+use native_tls::{Certificate, Identity, TlsAcceptor};
+use sha2::{Digest, Sha256};
+use tor_stream::native_tls::{TlsConnector, TlsError, TorTlsStream};
+use tor_stream::TorStreamBuilder;
+
+use std::io::{self, Read, Write};
+use std::net::SocketAddr;
+use std::thread::JoinHandle;
+
+/// Runs a proxy which accepts a single CONNECT request and terminates TLS itself,
+/// answering `ping` with `pong`. Returns whether the client sent `ping`.
+fn tls_proxy() -> (SocketAddr, JoinHandle<bool>) {
+    mock_proxy(|mut stream| {
+        accept_connect(&mut stream);
+
+        let identity = Identity::from_pkcs8(CERTIFICATE, KEY).unwrap();
+        // The handshake fails if the client rejects the certificate
+        let mut tls = match TlsAcceptor::new(identity).unwrap().accept(stream) {
+            Ok(tls) => tls,
+            Err(_) => return false,
+        };
+        let mut buf = [0; 4];
+        if tls.read_exact(&mut buf).is_err() {
+            return false;
+        }
+        assert_eq!(&buf, b"ping");
+        tls.write_all(b"pong").unwrap();
+        true
+    })
+}
+
+fn connect(connector: &TlsConnector) -> (Result<TorTlsStream, TlsError>, JoinHandle<bool>) {
+    let (proxy, handle) = tls_proxy();
+    let stream = TorStreamBuilder::new()
+        .proxy(proxy)
+        .connect("www.example.com:443")
+        .unwrap();
+    (connector.connect(stream), handle)
+}
+
+#[test]
+fn handshake() {
+    let pin: [u8; 32] = Sha256::digest(
+        Certificate::from_pem(CERTIFICATE)
+            .unwrap()
+            .to_der()
+            .unwrap(),
+    )
+    .into();
+    let connector = TlsConnector::with_root_certificates(vec![Certificate::from_pem(CA).unwrap()])
+        .pin_sha256(pin);
+
+    let (stream, handle) = connect(&connector);
+    let mut stream = stream.unwrap();
+    assert_eq!(stream.peer_certificate_sha256().unwrap(), Some(pin));
+    assert_eq!(stream.target_addr().to_string(), "www.example.com:443");
+
+    stream.write_all(b"ping").unwrap();
+    let mut buf = [0; 4];
+    stream.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"pong");
+    assert!(handle.join().unwrap());
+}
+
+#[test]
+fn rejected_certificate() {
+    // The test CA is not trusted by the platform
+    let (result, handle) = connect(&TlsConnector::new());
+    match result {
+        Err(TlsError::Tls(_)) => {}
+        result => panic!("unexpected result {:?}", result),
+    }
+    assert!(!handle.join().unwrap());
+
+    let connector = TlsConnector::with_root_certificates(vec![Certificate::from_pem(CA).unwrap()])
+        .pin_sha256([0; 32]);
+    let (result, handle) = connect(&connector);
+    match result {
+        Err(TlsError::PinMismatch) => {}
+        result => panic!("unexpected result {:?}", result),
+    }
+    assert!(!handle.join().unwrap());
+}
+
+#[test]
+fn connection_reset() {
+    let (proxy, handle) = mock_proxy(|mut stream| {
+        accept_connect(&mut stream);
+        // Closing the socket with the unread ClientHello resets the connection
+        stream.peek(&mut [0]).unwrap();
+    });
+    let stream = TorStreamBuilder::new()
+        .proxy(proxy)
+        .connect("www.example.com:443")
+        .unwrap();
+
+    match TlsConnector::new().connect(stream) {
+        Err(TlsError::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::ConnectionReset),
+        result => panic!("unexpected result {:?}", result),
+    }
+    handle.join().unwrap();
+}
@@ -0,0 +1,494 @@
+extern crate tor_stream;
+
+mod common;
+
+use common::fake_control;
+use tor_stream::control::ControlError;
+use tor_stream::onion::{
+    ClientAuth, ClientAuthKey, ClientAuthPublicKey, OnionAddress, OnionError, OnionSecretKey,
+    TorListener, TorListenerBuilder,
+};
+use tor_stream::{TargetAddr, ToTargetAddr};
+
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+#[test]
+fn listener() {
+    let service_id = "pg6mmjiyjmcrsslvykfwnntlaru7p5svn6y2ymmju6nubxndf4pscryd";
+    let (control, commands, handle) = fake_control(vec![
+        (
+            "ADD_ONION NEW:ED25519-V3 Port=80,127.0.0.1:*",
+            "250-ServiceID=pg6mmjiyjmcrsslvykfwnntlaru7p5svn6y2ymmju6nubxndf4pscryd\r\n\
+             250-PrivateKey=ED25519-V3:AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQ==\r\n\
+             250 OK\r\n",
+        ),
+        (
+            "DEL_ONION pg6mmjiyjmcrsslvykfwnntlaru7p5svn6y2ymmju6nubxndf4pscryd",
+            "250 OK\r\n",
+        ),
+    ]);
+
+    let listener = TorListener::bind(control, 80).unwrap();
+    let local_addr = listener.local_addr().unwrap();
+    assert_eq!(
+        commands.recv().unwrap(),
+        format!("ADD_ONION NEW:ED25519-V3 Port=80,{}", local_addr)
+    );
+    assert_eq!(listener.service_id(), service_id);
+    assert_eq!(
+        listener.onion_address().to_string(),
+        format!("{}.onion", service_id)
+    );
+    assert_eq!(listener.virtual_port(), 80);
+    assert_eq!(listener.private_key(), &OnionSecretKey::from_bytes([1; 64]));
+
+    // Tor forwards connections to the local address
+    let client = thread::spawn(move || {
+        let mut stream = TcpStream::connect(local_addr).unwrap();
+        stream.write_all(b"ping").unwrap();
+    });
+    let mut stream = listener.incoming().next().unwrap().unwrap();
+    let mut buf = [0; 4];
+    stream.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"ping");
+    client.join().unwrap();
+
+    drop(listener);
+    handle.join().unwrap();
+    assert!(commands.recv().unwrap().starts_with("DEL_ONION"));
+}
+
+#[test]
+fn detached_listener_with_key() {
+    let key = OnionSecretKey::from_bytes([1; 64]);
+    let (control, commands, handle) = fake_control(vec![(
+        "ADD_ONION ED25519-V3:*",
+        "250-ServiceID=pg6mmjiyjmcrsslvykfwnntlaru7p5svn6y2ymmju6nubxndf4pscryd\r\n\
+         250 OK\r\n",
+    )]);
+
+    let listener = TorListenerBuilder::new()
+        .key(key.clone())
+        .detach(true)
+        .bind(control, 443)
+        .unwrap();
+    assert_eq!(
+        commands.recv().unwrap(),
+        format!(
+            "ADD_ONION {} Flags=Detach Port=443,{}",
+            key.to_blob(),
+            listener.local_addr().unwrap()
+        )
+    );
+    assert_eq!(listener.private_key(), &key);
+    assert!(listener.is_detached());
+
+    // A detached service is not removed
+    drop(listener);
+    handle.join().unwrap();
+}
+
+#[test]
+fn listener_with_local_addr() {
+    let local_addr = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let (control, commands, handle) = fake_control(vec![(
+        "ADD_ONION NEW:ED25519-V3 Flags=Detach *",
+        "250-ServiceID=pg6mmjiyjmcrsslvykfwnntlaru7p5svn6y2ymmju6nubxndf4pscryd\r\n\
+         250-PrivateKey=ED25519-V3:AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQ==\r\n\
+         250 OK\r\n",
+    )]);
+
+    let listener = TorListenerBuilder::new()
+        .detach(true)
+        .local_addr(local_addr)
+        .bind(control, 80)
+        .unwrap();
+    assert_eq!(listener.local_addr().unwrap(), local_addr);
+    assert_eq!(
+        commands.recv().unwrap(),
+        format!(
+            "ADD_ONION NEW:ED25519-V3 Flags=Detach Port=80,{}",
+            local_addr
+        )
+    );
+    drop(listener);
+    handle.join().unwrap();
+}
+
+#[test]
+fn rebind_detached_listener() {
+    let key = OnionSecretKey::from_bytes([1; 64]);
+    let builder = TorListenerBuilder::new()
+        .key(key.clone())
+        .detach(true)
+        .local_addr("127.0.0.1:0".parse().unwrap());
+
+    let (control, commands, handle) = fake_control(vec![(
+        "ADD_ONION ED25519-V3:*",
+        "250-ServiceID=pg6mmjiyjmcrsslvykfwnntlaru7p5svn6y2ymmju6nubxndf4pscryd\r\n\
+         250 OK\r\n",
+    )]);
+    let listener = builder.bind(control, 80).unwrap();
+    let local_addr = listener.local_addr().unwrap();
+    assert!(commands
+        .recv()
+        .unwrap()
+        .ends_with(&format!("Port=80,{}", local_addr)));
+    assert!(!listener.is_resumed());
+    drop(listener);
+    handle.join().unwrap();
+
+    // After a restart, Tor still runs the detached service
+    let (control, commands, handle) = fake_control(vec![
+        ("ADD_ONION ED25519-V3:*", "550 Onion address collision\r\n"),
+        (
+            "GETINFO onions/detached",
+            "250+onions/detached=\r\n\
+             darkfailenbsdla5mal2mxn2uz66od5vtzd5qozslagrfzachha3f3id\r\n\
+             luquq56icps5wzb5fmm6wcvbz3vp7hrxyotqsfd5nnxorzuqkztk36yd\r\n\
+             .\r\n\
+             250 OK\r\n",
+        ),
+    ]);
+    let builder = builder.local_addr(local_addr);
+    let listener = builder.bind(control, 80).unwrap();
+    assert!(commands
+        .recv()
+        .unwrap()
+        .ends_with(&format!("Port=80,{}", local_addr)));
+    assert_eq!(listener.local_addr().unwrap(), local_addr);
+    assert_eq!(listener.onion_address(), &key.onion_address());
+    assert_eq!(listener.private_key(), &key);
+    assert!(listener.is_detached());
+    assert!(listener.is_resumed());
+    drop(listener);
+    handle.join().unwrap();
+
+    // The key is in use, but not by a detached service
+    let (control, _commands, handle) = fake_control(vec![
+        ("ADD_ONION ED25519-V3:*", "550 Onion address collision\r\n"),
+        (
+            "GETINFO onions/detached",
+            "250-onions/detached=\r\n250 OK\r\n",
+        ),
+    ]);
+    let error = builder.bind(control, 80).err().unwrap();
+    assert!(matches!(error, ControlError::Reply(_)), "{:?}", error);
+    handle.join().unwrap();
+
+    // Without a fixed local address, the collision is an error
+    let (control, _commands, handle) = fake_control(vec![(
+        "ADD_ONION ED25519-V3:*",
+        "550 Onion address collision\r\n",
+    )]);
+    let error = TorListenerBuilder::new()
+        .key(key)
+        .detach(true)
+        .bind(control, 80)
+        .err()
+        .unwrap();
+    assert!(matches!(error, ControlError::Reply(_)), "{:?}", error);
+    handle.join().unwrap();
+}
+
+#[test]
+fn secret_key_formats() {
+    let bytes: Vec<u8> = (0..64).collect();
+    let mut array = [0; 64];
+    array.copy_from_slice(&bytes);
+    let key = OnionSecretKey::from_bytes(array);
+
+    let blob = key.to_blob();
+    assert_eq!(OnionSecretKey::from_blob(&blob).unwrap(), key);
+    assert_eq!(
+        OnionSecretKey::from_blob(blob.trim_end_matches('=')).unwrap(),
+        key
+    );
+    assert_eq!(
+        OnionSecretKey::from_blob("RSA1024:AAAA"),
+        Err(OnionError::InvalidKey("expected ED25519-V3 key"))
+    );
+    assert!(OnionSecretKey::from_blob("ED25519-V3:AAAA").is_err());
+
+    let file = key.to_file_bytes();
+    assert_eq!(file.len(), 96);
+    assert_eq!(&file[..32], b"== ed25519v1-secret: type0 ==\0\0\0");
+    assert_eq!(OnionSecretKey::from_file_bytes(&file).unwrap(), key);
+    assert!(OnionSecretKey::from_file_bytes(&file[32..]).is_err());
+
+    let path = std::env::temp_dir().join(format!(
+        "tor-stream-onion-{}-hs_ed25519_secret_key",
+        std::process::id()
+    ));
+    key.write_to_file(&path).unwrap();
+    assert_eq!(OnionSecretKey::read_from_file(&path).unwrap(), key);
+    std::fs::remove_file(path).unwrap();
+
+    assert_eq!(format!("{:?}", key), "OnionSecretKey(..)");
+}
+
+#[test]
+fn onion_address() {
+    let service_id = "darkfailenbsdla5mal2mxn2uz66od5vtzd5qozslagrfzachha3f3id";
+    let address: OnionAddress = format!("{}.onion", service_id).parse().unwrap();
+    assert_eq!(address.service_id(), service_id);
+    assert_eq!(address.subdomain(), None);
+    assert_eq!(address.to_string(), format!("{}.onion", service_id));
+    assert_eq!(
+        (&address, 443).to_target_addr().unwrap(),
+        TargetAddr::Domain(format!("{}.onion", service_id), 443)
+    );
+
+    let upper: OnionAddress = format!("WWW.Docs.{}.ONION", service_id.to_uppercase())
+        .parse()
+        .unwrap();
+    assert_eq!(upper.subdomain(), Some("www.docs"));
+    assert_eq!(upper.public_key(), address.public_key());
+    assert_eq!(
+        (upper, 80).to_target_addr().unwrap(),
+        TargetAddr::Domain(format!("www.docs.{}.onion", service_id), 80)
+    );
+
+    let parse = |s: &str| s.parse::<OnionAddress>();
+    assert_eq!(parse("expyuzz4wqqyqhjn.onion"), Err(OnionError::V2Address));
+    assert_eq!(
+        parse(service_id),
+        Err(OnionError::InvalidAddress("missing .onion suffix"))
+    );
+    assert_eq!(
+        parse("darkfailenbsdla5mal2mxn2uz66od5vtzd5qozslagrfzachha3f4id.onion"),
+        Err(OnionError::InvalidAddress("invalid checksum"))
+    );
+    assert_eq!(
+        parse("darkfailenbsdla5mal2mxn2uz66od5vtzd5qozslagrfzachha3f3i1.onion"),
+        Err(OnionError::InvalidAddress("invalid base32"))
+    );
+    assert_eq!(
+        parse(&format!(".{}.onion", service_id)),
+        Err(OnionError::InvalidAddress("empty subdomain label"))
+    );
+    assert_eq!(
+        parse("example.onion"),
+        Err(OnionError::InvalidAddress(
+            "service ID must be 56 characters long"
+        ))
+    );
+}
+
+#[test]
+fn generate_key() {
+    fn unhex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    // Test vector 1 of RFC 8032
+    let mut seed = [0; 32];
+    seed.copy_from_slice(&unhex(
+        "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60",
+    ));
+    let key = OnionSecretKey::from_seed(seed);
+    let public_key = unhex("d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a");
+    assert_eq!(&key.public_key()[..], &public_key[..]);
+
+    let address = key.onion_address();
+    assert_eq!(address.public_key(), &key.public_key());
+    assert_eq!(
+        address.to_string().parse::<OnionAddress>().unwrap(),
+        address
+    );
+
+    let generated = OnionSecretKey::generate().unwrap();
+    assert_ne!(generated, OnionSecretKey::generate().unwrap());
+    assert_eq!(
+        generated.onion_address().public_key(),
+        &generated.public_key()
+    );
+
+    let dir = std::env::temp_dir().join(format!("tor-stream-onion-{}-dir", std::process::id()));
+    key.write_service_dir(&dir).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(dir.join("hostname")).unwrap(),
+        format!("{}\n", address)
+    );
+    let public_file = std::fs::read(dir.join("hs_ed25519_public_key")).unwrap();
+    assert_eq!(&public_file[..32], b"== ed25519v1-public: type0 ==\0\0\0");
+    assert_eq!(&public_file[32..], &public_key[..]);
+    assert_eq!(
+        OnionSecretKey::read_from_file(dir.join("hs_ed25519_secret_key")).unwrap(),
+        key
+    );
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = |path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(dir.clone()), 0o700);
+        assert_eq!(mode(dir.join("hs_ed25519_secret_key")), 0o600);
+
+        // An existing key file is restricted as well
+        let secret_file = dir.join("hs_ed25519_secret_key");
+        std::fs::set_permissions(&secret_file, std::fs::Permissions::from_mode(0o644)).unwrap();
+        key.write_to_file(&secret_file).unwrap();
+        assert_eq!(mode(secret_file), 0o600);
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn client_auth() {
+    let service_id = "darkfailenbsdla5mal2mxn2uz66od5vtzd5qozslagrfzachha3f3id";
+
+    // Test vector of RFC 7748
+    let key = ClientAuthKey::from_bytes([
+        0x77, 0x07, 0x6d, 0x0a, 0x73, 0x18, 0xa5, 0x7d, 0x3c, 0x16, 0xc1, 0x72, 0x51, 0xb2, 0x66,
+        0x45, 0xdf, 0x4c, 0x2f, 0x87, 0xeb, 0xc0, 0x99, 0x2a, 0xb1, 0x77, 0xfb, 0xa5, 0x1d, 0xb9,
+        0x2c, 0x2a,
+    ]);
+    let public_key = key.public_key();
+    assert_eq!(
+        public_key.as_bytes(),
+        &[
+            0x85, 0x20, 0xf0, 0x09, 0x89, 0x30, 0xa7, 0x54, 0x74, 0x8b, 0x7d, 0xdc, 0xb4, 0x3e,
+            0xf7, 0x5a, 0x0d, 0xbf, 0x3a, 0x0d, 0x26, 0x38, 0x1a, 0xf4, 0xeb, 0xa4, 0xa9, 0x8e,
+            0xaa, 0x9b, 0x4e, 0x6a,
+        ]
+    );
+
+    let auth_file = public_key.to_auth();
+    assert_eq!(
+        auth_file,
+        format!("descriptor:x25519:{}\n", public_key.to_base32())
+    );
+    assert_eq!(
+        ClientAuthPublicKey::from_auth(&auth_file).unwrap(),
+        public_key
+    );
+    assert_eq!(
+        ClientAuthPublicKey::from_auth(&auth_file.to_lowercase()).unwrap(),
+        public_key
+    );
+
+    let auth = ClientAuth::new(
+        format!("{}.onion", service_id).parse().unwrap(),
+        key.clone(),
+    );
+    let auth_private = auth.to_auth_private();
+    assert_eq!(
+        auth_private,
+        format!("{}:descriptor:x25519:{}\n", service_id, key.to_base32())
+    );
+    assert_eq!(auth_private.parse::<ClientAuth>().unwrap(), auth);
+    assert_eq!(
+        ClientAuth::from_auth_private("x:descriptor:x25519:AAAA"),
+        Err(OnionError::InvalidAddress(
+            "service ID must be 56 characters long"
+        ))
+    );
+    assert!(
+        ClientAuth::from_auth_private(&format!("{}:descriptor:x25519:AAAA", service_id)).is_err()
+    );
+
+    let generated = ClientAuthKey::generate().unwrap();
+    assert_ne!(generated, ClientAuthKey::generate().unwrap());
+    assert_eq!(format!("{:?}", generated), "ClientAuthKey(..)");
+}
+
+#[test]
+fn client_auth_control() {
+    let service_id = "darkfailenbsdla5mal2mxn2uz66od5vtzd5qozslagrfzachha3f3id";
+    let key = ClientAuthKey::from_bytes([0x41; 32]);
+    let (mut control, commands, handle) = fake_control(vec![
+        ("ONION_CLIENT_AUTH_ADD *", "250 OK\r\n"),
+        (
+            "ONION_CLIENT_AUTH_VIEW",
+            "250-ONION_CLIENT_AUTH_VIEW\r\n\
+             250-CLIENT darkfailenbsdla5mal2mxn2uz66od5vtzd5qozslagrfzachha3f3id x25519:QUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUE= ClientName=alice Flags=Permanent\r\n\
+             250 OK\r\n",
+        ),
+        ("ONION_CLIENT_AUTH_REMOVE *", "251 No credentials for the service\r\n"),
+    ]);
+
+    let mut auth = ClientAuth::new(format!("{}.onion", service_id).parse().unwrap(), key);
+    auth.client_name = Some("alice".to_owned());
+    auth.permanent = true;
+
+    control.onion_client_auth_add(&auth).unwrap();
+    assert_eq!(
+        commands.recv().unwrap(),
+        format!(
+            "ONION_CLIENT_AUTH_ADD {} x25519:QUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUE= ClientName=alice Flags=Permanent",
+            service_id
+        )
+    );
+
+    assert_eq!(
+        control.onion_client_auth_view(None).unwrap(),
+        [auth.clone()]
+    );
+    assert_eq!(commands.recv().unwrap(), "ONION_CLIENT_AUTH_VIEW");
+
+    control.onion_client_auth_remove(&auth.address).unwrap();
+    assert_eq!(
+        commands.recv().unwrap(),
+        format!("ONION_CLIENT_AUTH_REMOVE {}", service_id)
+    );
+
+    // Invalid names are rejected without sending a command
+    for name in &[
+        "x Flags=Permanent",
+        "x\r\nSIGNAL HALT",
+        "",
+        "seventeen_letters",
+    ] {
+        auth.client_name = Some(name.to_string());
+        match control.onion_client_auth_add(&auth) {
+            Err(ControlError::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::InvalidInput),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    drop(control);
+    handle.join().unwrap();
+}
+
+#[test]
+fn listener_with_client_auth() {
+    let clients = [
+        ClientAuthKey::from_bytes([1; 32]).public_key(),
+        ClientAuthKey::from_bytes([2; 32]).public_key(),
+    ];
+    let (control, commands, handle) = fake_control(vec![
+        (
+            "ADD_ONION NEW:ED25519-V3 Flags=V3Auth *",
+            "250-ServiceID=pg6mmjiyjmcrsslvykfwnntlaru7p5svn6y2ymmju6nubxndf4pscryd\r\n\
+             250-PrivateKey=ED25519-V3:AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQ==\r\n\
+             250 OK\r\n",
+        ),
+        ("DEL_ONION *", "250 OK\r\n"),
+    ]);
+
+    let listener = TorListenerBuilder::new()
+        .authorized_client(clients[0].clone())
+        .authorized_client(clients[1].clone())
+        .bind(control, 80)
+        .unwrap();
+    assert_eq!(
+        commands.recv().unwrap(),
+        format!(
+            "ADD_ONION NEW:ED25519-V3 Flags=V3Auth Port=80,{} ClientAuthV3={} ClientAuthV3={}",
+            listener.local_addr().unwrap(),
+            clients[0].to_base32(),
+            clients[1].to_base32()
+        )
+    );
+
+    drop(listener);
+    handle.join().unwrap();
+}
@@ -0,0 +1,139 @@
+#![cfg(unix)]
+
+extern crate tor_stream;
+
+use tor_stream::process::TorProcessBuilder;
+
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::sync::Once;
+use std::time::Duration;
+
+/// Writes fake `tor` scripts and returns the directory containing them.
+///
+/// All scripts are written before any of them runs, because executing a file
+/// while another thread may still hold it open for writing fails with `ETXTBSY`.
+fn scripts() -> PathBuf {
+    static WRITE: Once = Once::new();
+
+    let dir = std::env::temp_dir().join(format!("tor-stream-scripts-{}", std::process::id()));
+    WRITE.call_once(|| {
+        fs::create_dir_all(&dir).unwrap();
+        let scripts = [
+            (
+                "bootstrap",
+                "[ \"$1\" = -f ] && grep -q '^SocksPort 127.0.0.1:auto$' \"$2\" || exit 1\n\
+                 echo 'Oct 17 10:00:00.000 [notice] Opening Socks listener on 127.0.0.1:0'\n\
+                 echo 'Oct 17 10:00:00.000 [notice] Opened Socks listener connection (ready) on 127.0.0.1:39050'\n\
+                 echo 'Oct 17 10:00:00.000 [notice] Opening Control listener on 127.0.0.1:0'\n\
+                 echo 'Oct 17 10:00:00.000 [notice] Opened Control listener connection (ready) on 127.0.0.1:39051'\n\
+                 echo 'Oct 17 10:00:00.000 [notice] Bootstrapped 0% (starting): Starting'\n\
+                 echo 'Oct 17 10:00:01.000 [notice] Bootstrapped 100% (done): Done'\n\
+                 exec sleep 60\n",
+            ),
+            (
+                "exit",
+                "echo 'Oct 17 10:00:00.000 [warn] Could not bind to 127.0.0.1:9050'\n\
+                 exit 1\n",
+            ),
+            (
+                "crash",
+                "echo 'Oct 17 10:00:00.000 [notice] Opened Socks listener on 127.0.0.1:39054'\n\
+                 echo 'Oct 17 10:00:00.000 [notice] Opened Control listener on 127.0.0.1:39055'\n\
+                 echo 'Oct 17 10:00:00.000 [err] Out of memory'\n\
+                 exit 1\n",
+            ),
+            (
+                "stuck",
+                "echo 'Oct 17 10:00:00.000 [notice] Opened Socks listener on 127.0.0.1:39052'\n\
+                 echo 'Oct 17 10:00:00.000 [notice] Opened Control listener on 127.0.0.1:39053'\n\
+                 echo 'Oct 17 10:00:00.000 [notice] Bootstrapped 5% (conn): Connecting to a relay'\n\
+                 exec sleep 60\n",
+            ),
+        ];
+        for (name, script) in &scripts {
+            let path = dir.join(name);
+            fs::write(&path, format!("#!/bin/sh\n{}", script)).unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+    });
+    dir
+}
+
+#[test]
+fn bootstrap() {
+    let mut tor = TorProcessBuilder::new()
+        .tor_path(scripts().join("bootstrap"))
+        .option("ExitNodes", "{de}")
+        .spawn()
+        .unwrap();
+    tor.wait_for_bootstrap(Duration::from_secs(10)).unwrap();
+    assert_eq!(tor.bootstrap_progress(), 100);
+    // The ports are chosen by Tor and read from its log
+    assert_eq!(tor.socks_addr(), "127.0.0.1:39050".parse().unwrap());
+    assert_eq!(tor.control_addr(), "127.0.0.1:39051".parse().unwrap());
+
+    let data_dir = tor.data_dir().to_owned();
+    let torrc = fs::read_to_string(data_dir.join("torrc")).unwrap();
+    assert!(torrc.contains("SocksPort 127.0.0.1:auto\n"));
+    assert!(torrc.contains("ControlPort 127.0.0.1:auto\n"));
+    assert!(torrc.contains("ExitNodes {de}\n"));
+    assert_eq!(
+        fs::metadata(&data_dir).unwrap().permissions().mode() & 0o777,
+        0o700
+    );
+
+    // The temporary data directory is removed with the process
+    drop(tor);
+    assert!(!data_dir.exists());
+}
+
+#[test]
+fn exit_before_listening() {
+    let error = TorProcessBuilder::new()
+        .tor_path(scripts().join("exit"))
+        .spawn()
+        .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    assert!(error.to_string().contains("Could not bind"));
+}
+
+#[test]
+fn exit_before_bootstrap() {
+    let mut tor = TorProcessBuilder::new()
+        .tor_path(scripts().join("crash"))
+        .spawn()
+        .unwrap();
+    let error = tor.wait_for_bootstrap(Duration::from_secs(10)).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    assert!(error.to_string().contains("Out of memory"));
+}
+
+#[test]
+fn bootstrap_timeout() {
+    let mut tor = TorProcessBuilder::new()
+        .tor_path(scripts().join("stuck"))
+        .spawn()
+        .unwrap();
+    let error = tor
+        .wait_for_bootstrap(Duration::from_millis(500))
+        .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+    assert_eq!(tor.bootstrap_progress(), 5);
+}
+
+#[test]
+fn missing_binary() {
+    let dir = std::env::temp_dir().join(format!("tor-stream-missing-{}", std::process::id()));
+    let error = TorProcessBuilder::new()
+        .tor_path(dir.join("tor"))
+        .data_dir(&dir)
+        .spawn()
+        .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    // An explicit data directory is kept
+    assert!(dir.join("torrc").exists());
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,53 @@
+#![cfg(feature = "reqwest")]
+
+extern crate tor_stream;
+
+mod common;
+
+use common::mock_http_proxy;
+use tor_stream::reqwest::ClientError;
+use tor_stream::{Isolation, TorStreamBuilder};
+
+#[tokio::test]
+async fn client() {
+    let (proxy, handle) = mock_http_proxy(vec![
+        b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello",
+    ]);
+
+    let builder = TorStreamBuilder::new()
+        .proxy(proxy)
+        .isolation(Isolation::credentials("user", "p@ss:word"));
+    let client = tor_stream::reqwest::client_builder(&builder)
+        .unwrap()
+        .build()
+        .unwrap();
+    let body = client
+        .get("http://www.example.com/")
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert_eq!(body, "hello");
+
+    // The hostname is resolved by the proxy
+    let (connect, _) = &handle.join().unwrap()[0];
+    assert_eq!(connect.target, "www.example.com:80");
+    assert_eq!(
+        connect.credentials,
+        Some(("user".to_owned(), "p@ss:word".to_owned()))
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn unsupported_proxy() {
+    let builder = TorStreamBuilder::new().proxy(std::path::Path::new("/run/tor/socks"));
+    match tor_stream::reqwest::client_builder(&builder) {
+        Err(ClientError::UnsupportedProxy(proxy)) => {
+            assert_eq!(proxy.to_string(), "unix:/run/tor/socks")
+        }
+        result => panic!("unexpected result {:?}", result.map(|_| ())),
+    }
+}
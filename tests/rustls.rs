@@ -0,0 +1,93 @@
+#![cfg(feature = "rustls")]
+
+extern crate tor_stream;
+
+mod common;
+
+use common::{accept_connect, mock_proxy, tls_server_config, CA, CERTIFICATE};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::CertificateDer;
+use rustls::{CertificateError, RootCertStore, ServerConnection, StreamOwned};
+use sha2::{Digest, Sha256};
+use tor_stream::rustls::{TlsConnector, TlsError};
+use tor_stream::TorStreamBuilder;
+
+use std::io::{Read, Write};
+use std::net::SocketAddr;
+use std::thread::JoinHandle;
+
+/// Runs a proxy which accepts a single CONNECT request and terminates TLS itself,
+/// answering `ping` with `pong`. Returns the SNI sent by the client.
+fn tls_proxy() -> (SocketAddr, JoinHandle<Option<String>>) {
+    mock_proxy(|mut stream| {
+        accept_connect(&mut stream);
+        let connection = ServerConnection::new(tls_server_config()).unwrap();
+        let mut tls = StreamOwned::new(connection, stream);
+
+        // The handshake fails if the client rejects the certificate
+        let mut buf = [0; 4];
+        tls.read_exact(&mut buf).ok()?;
+        assert_eq!(&buf, b"ping");
+        tls.write_all(b"pong").unwrap();
+        tls.conn.server_name().map(str::to_owned)
+    })
+}
+
+fn test_roots() -> RootCertStore {
+    let mut roots = RootCertStore::empty();
+    roots
+        .add(CertificateDer::from_pem_slice(CA).unwrap())
+        .unwrap();
+    roots
+}
+
+#[test]
+fn handshake() {
+    let (proxy, handle) = tls_proxy();
+    let pin: [u8; 32] = Sha256::digest(CertificateDer::from_pem_slice(CERTIFICATE).unwrap()).into();
+
+    let stream = TorStreamBuilder::new()
+        .proxy(proxy)
+        .connect("www.example.com:443")
+        .unwrap();
+    let mut stream = TlsConnector::with_root_store(test_roots())
+        .pin_sha256([0; 32])
+        .pin_sha256(pin)
+        .connect(stream)
+        .unwrap();
+    assert_eq!(stream.peer_certificates().unwrap().len(), 1);
+    assert_eq!(stream.target_addr().to_string(), "www.example.com:443");
+
+    stream.write_all(b"ping").unwrap();
+    let mut buf = [0; 4];
+    stream.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"pong");
+    assert_eq!(handle.join().unwrap().as_deref(), Some("www.example.com"));
+}
+
+#[test]
+fn rejected_certificate() {
+    let connect = |connector: TlsConnector| {
+        let (proxy, handle) = tls_proxy();
+        let stream = TorStreamBuilder::new()
+            .proxy(proxy)
+            .connect("www.example.com:443")
+            .unwrap();
+        let error = connector.connect(stream).err().unwrap();
+        assert_eq!(handle.join().unwrap(), None);
+        error
+    };
+
+    // The test CA is not a public root
+    match connect(TlsConnector::new()) {
+        TlsError::Tls(rustls::Error::InvalidCertificate(CertificateError::UnknownIssuer)) => {}
+        error => panic!("unexpected error {:?}", error),
+    }
+
+    match connect(TlsConnector::with_root_store(test_roots()).pin_sha256([0; 32])) {
+        TlsError::Tls(rustls::Error::InvalidCertificate(
+            CertificateError::ApplicationVerificationFailure,
+        )) => {}
+        error => panic!("unexpected error {:?}", error),
+    }
+}
@@ -0,0 +1,404 @@
+extern crate tor_stream;
+
+mod common;
+
+use common::{mock_proxy, read_vec};
+use tor_stream::{
+    DnsPolicy, Isolation, IsolationGroup, TargetAddr, TorError, TorStream, TorStreamBuilder,
+};
+
+use std::io::{Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+use std::thread;
+
+/// Accepts the greeting without authentication and returns the raw CONNECT request.
+fn accept_raw_request(stream: &mut TcpStream, len: usize) -> Vec<u8> {
+    assert_eq!(read_vec(stream, 3), [5, 1, 0]);
+    stream.write_all(&[5, 0]).unwrap();
+    read_vec(stream, len)
+}
+
+#[test]
+fn connect_domain() {
+    let (proxy, handle) = mock_proxy(|mut stream| {
+        let request = accept_raw_request(&mut stream, 5 + 15 + 2);
+        stream
+            .write_all(&[5, 0, 0, 1, 10, 0, 0, 1, 0x1F, 0x90])
+            .unwrap();
+        stream.write_all(b"pong").unwrap();
+        assert_eq!(read_vec(&mut stream, 4), b"ping");
+        request
+    });
+
+    let mut stream = TorStreamBuilder::new()
+        .proxy(proxy)
+        .connect("www.example.com:443")
+        .unwrap();
+    assert_eq!(
+        stream.target_addr(),
+        &TargetAddr::Domain("www.example.com".to_owned(), 443)
+    );
+    assert_eq!(
+        stream.bind_addr(),
+        &TargetAddr::Ip("10.0.0.1:8080".parse().unwrap())
+    );
+
+    let mut buf = [0; 4];
+    stream.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"pong");
+    stream.write_all(b"ping").unwrap();
+
+    let mut expected = vec![5, 1, 0, 3, 15];
+    expected.extend_from_slice(b"www.example.com");
+    expected.extend_from_slice(&[1, 0xBB]);
+    assert_eq!(handle.join().unwrap(), expected);
+}
+
+#[test]
+fn connect_ip() {
+    let (proxy, handle) = mock_proxy(|mut stream| {
+        let request = accept_raw_request(&mut stream, 4 + 16 + 2);
+        stream
+            .write_all(&[5, 0, 0, 3, 4, b't', b'e', b's', b't', 0, 80])
+            .unwrap();
+        request
+    });
+
+    let stream = TorStreamBuilder::new()
+        .proxy(proxy)
+        .connect("[::1]:80")
+        .unwrap();
+    assert_eq!(
+        stream.bind_addr(),
+        &TargetAddr::Domain("test".to_owned(), 80)
+    );
+
+    let mut expected = vec![5, 1, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+    expected.extend_from_slice(&[0, 80]);
+    assert_eq!(handle.join().unwrap(), expected);
+}
+
+#[test]
+fn reply_codes() {
+    for &(code, ref check) in &[
+        (
+            0x04,
+            (|e| matches!(e, TorError::HostUnreachable)) as fn(&TorError) -> bool,
+        ),
+        (0x06, |e| matches!(e, TorError::TtlExpired)),
+        (0xF2, |e| matches!(e, TorError::OnionServiceIntroFailed)),
+        (0xF6, |e| matches!(e, TorError::OnionServiceBadAddress)),
+        (0x42, |e| matches!(e, TorError::UnknownReply(0x42))),
+    ] {
+        let (proxy, handle) = mock_proxy(move |mut stream| {
+            accept_raw_request(&mut stream, 5 + 11 + 2);
+            stream
+                .write_all(&[5, code, 0, 1, 0, 0, 0, 0, 0, 0])
+                .unwrap();
+        });
+
+        let error = TorStreamBuilder::new()
+            .proxy(proxy)
+            .connect("example.com:80")
+            .err()
+            .unwrap();
+        assert!(check(&error), "unexpected error {:?}", error);
+        assert_eq!(error.reply_code(), Some(code));
+        handle.join().unwrap();
+    }
+}
+
+/// Accepts username/password authentication and returns the credentials.
+fn accept_credentials(stream: &mut TcpStream, status: u8) -> (String, String) {
+    assert_eq!(read_vec(stream, 3), [5, 1, 2]);
+    stream.write_all(&[5, 2]).unwrap();
+    assert_eq!(read_vec(stream, 1), [1]);
+    let len = read_vec(stream, 1)[0] as usize;
+    let username = String::from_utf8(read_vec(stream, len)).unwrap();
+    let len = read_vec(stream, 1)[0] as usize;
+    let password = String::from_utf8(read_vec(stream, len)).unwrap();
+    stream.write_all(&[1, status]).unwrap();
+    (username, password)
+}
+
+fn connect_isolated(isolation: &Isolation) -> (String, String) {
+    let (proxy, handle) = mock_proxy(|mut stream| {
+        let credentials = accept_credentials(&mut stream, 0);
+        read_vec(&mut stream, 5 + 11 + 2);
+        stream.write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0]).unwrap();
+        credentials
+    });
+
+    TorStreamBuilder::new()
+        .proxy(proxy)
+        .isolation(isolation.clone())
+        .connect("example.com:80")
+        .unwrap();
+    handle.join().unwrap()
+}
+
+#[test]
+fn isolation() {
+    let explicit = Isolation::credentials("user", "secret");
+    assert_eq!(
+        connect_isolated(&explicit),
+        ("user".to_owned(), "secret".to_owned())
+    );
+
+    let group = Isolation::Group(IsolationGroup::new());
+    assert_eq!(connect_isolated(&group), connect_isolated(&group));
+    assert_ne!(
+        connect_isolated(&group),
+        connect_isolated(&IsolationGroup::new().into())
+    );
+
+    assert_ne!(
+        connect_isolated(&Isolation::Random),
+        connect_isolated(&Isolation::Random)
+    );
+
+    let named = Isolation::Group(IsolationGroup::named("session").unwrap());
+    assert_eq!(
+        connect_isolated(&named),
+        ("tor-stream-group".to_owned(), "session".to_owned())
+    );
+}
+
+#[test]
+fn isolation_group_names() {
+    let token = IsolationGroup::new().token().to_owned();
+    assert_eq!(token.len(), 32);
+    assert!(token.chars().all(|c| c.is_ascii_hexdigit()), "{}", token);
+
+    assert!(IsolationGroup::named("x".repeat(255)).is_ok());
+    for name in &[String::new(), "x".repeat(256)] {
+        let error = IsolationGroup::named(name.as_str()).err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+}
+
+#[test]
+fn authentication_failed() {
+    let (proxy, handle) = mock_proxy(|mut stream| {
+        accept_credentials(&mut stream, 1);
+    });
+
+    let error = TorStreamBuilder::new()
+        .proxy(proxy)
+        .isolation(Isolation::Random)
+        .connect("example.com:80")
+        .err()
+        .unwrap();
+    assert!(
+        matches!(error, TorError::AuthenticationFailed),
+        "{:?}",
+        error
+    );
+    handle.join().unwrap();
+}
+
+#[test]
+fn no_acceptable_methods() {
+    let (proxy, handle) = mock_proxy(|mut stream| {
+        read_vec(&mut stream, 3);
+        stream.write_all(&[5, 0xFF]).unwrap();
+    });
+
+    let error = TorStreamBuilder::new()
+        .proxy(proxy)
+        .connect("example.com:80")
+        .err()
+        .unwrap();
+    assert!(matches!(error, TorError::Protocol(_)), "{:?}", error);
+    handle.join().unwrap();
+}
+
+#[test]
+fn proxy_unreachable() {
+    let proxy = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+
+    let error = TorStreamBuilder::new()
+        .proxy(proxy)
+        .connect("example.com:80")
+        .err()
+        .unwrap();
+    assert!(
+        matches!(error, TorError::ProxyUnreachable(_)),
+        "{:?}",
+        error
+    );
+}
+
+#[test]
+#[allow(deprecated)]
+fn socks_compatibility() {
+    use tor_stream::socks::{TargetAddr, ToTargetAddr};
+
+    assert_eq!(
+        "example.com:80".to_target_addr().unwrap(),
+        TargetAddr::Domain("example.com".to_owned(), 80)
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn connect_unix_socket() {
+    use std::os::unix::net::UnixListener;
+
+    let dir = std::env::temp_dir().join(format!("tor-stream-socks5-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let socket = dir.join("socks");
+    let listener = UnixListener::bind(&socket).unwrap();
+    let handle = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut buf = [0; 3];
+        stream.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [5, 1, 0]);
+        stream.write_all(&[5, 0]).unwrap();
+
+        let mut request = vec![0; 5 + 11 + 2];
+        stream.read_exact(&mut request).unwrap();
+        stream.write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0]).unwrap();
+        stream.write_all(b"pong").unwrap();
+        request
+    });
+
+    let mut stream = TorStream::connect_with_address(socket.as_path(), "example.com:80").unwrap();
+    assert!(stream.get_ref().as_unix().is_some());
+    assert!(stream.get_ref().as_tcp().is_none());
+
+    let mut buf = [0; 4];
+    stream.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"pong");
+
+    let mut expected = vec![5, 1, 0, 3, 11];
+    expected.extend_from_slice(b"example.com");
+    expected.extend_from_slice(&[0, 80]);
+    assert_eq!(handle.join().unwrap(), expected);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn resolve() {
+    let (proxy, handle) = mock_proxy(|mut stream| {
+        let request = accept_raw_request(&mut stream, 5 + 15 + 2);
+        let mut reply = vec![5, 0, 0, 4];
+        reply.extend_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        reply.extend_from_slice(&[0, 0]);
+        stream.write_all(&reply).unwrap();
+        request
+    });
+
+    let address = TorStreamBuilder::new()
+        .proxy(proxy)
+        .resolve("www.example.com")
+        .unwrap();
+    assert_eq!(address, "2001:db8::1".parse::<IpAddr>().unwrap());
+
+    let mut expected = vec![5, 0xF0, 0, 3, 15];
+    expected.extend_from_slice(b"www.example.com");
+    expected.extend_from_slice(&[0, 0]);
+    assert_eq!(handle.join().unwrap(), expected);
+
+    // Tor reports unknown names as unreachable hosts
+    let (proxy, handle) = mock_proxy(|mut stream| {
+        accept_raw_request(&mut stream, 5 + 7 + 2);
+        stream.write_all(&[5, 4, 0, 1, 0, 0, 0, 0, 0, 0]).unwrap();
+    });
+    let error = TorStreamBuilder::new()
+        .proxy(proxy)
+        .resolve("invalid")
+        .err()
+        .unwrap();
+    assert!(matches!(error, TorError::HostUnreachable), "{:?}", error);
+    handle.join().unwrap();
+}
+
+#[test]
+fn resolve_ptr() {
+    let (proxy, handle) = mock_proxy(|mut stream| {
+        let request = accept_raw_request(&mut stream, 4 + 4 + 2);
+        stream
+            .write_all(&[
+                5, 0, 0, 3, 7, b'a', b'.', b'b', b'.', b'o', b'r', b'g', 0, 0,
+            ])
+            .unwrap();
+        request
+    });
+
+    let host = TorStreamBuilder::new()
+        .proxy(proxy)
+        .resolve_ptr(Ipv4Addr::new(192, 0, 2, 1).into())
+        .unwrap();
+    assert_eq!(host, "a.b.org");
+    assert_eq!(handle.join().unwrap(), [5, 0xF1, 0, 1, 192, 0, 2, 1, 0, 0]);
+}
+
+#[test]
+fn dns_policy() {
+    // Nothing listens here, so refused destinations must fail before connecting
+    let unreachable = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let strict = TorStreamBuilder::new()
+        .proxy(unreachable)
+        .dns_policy(DnsPolicy::Strict);
+
+    let resolved: SocketAddr = "192.0.2.1:80".parse().unwrap();
+    let error = strict.connect(resolved).err().unwrap();
+    assert!(
+        matches!(error, TorError::LocallyResolved(address) if address == resolved),
+        "{:?}",
+        error
+    );
+    let error = strict.connect((Ipv4Addr::LOCALHOST, 80)).err().unwrap();
+    assert!(matches!(error, TorError::LocallyResolved(_)), "{:?}", error);
+
+    // Hostnames and explicit IP addresses are passed on to the proxy
+    for destination in &[
+        TargetAddr::Domain("example.com".to_owned(), 80),
+        TargetAddr::Ip(resolved),
+    ] {
+        let error = strict.connect(destination.clone()).err().unwrap();
+        assert!(
+            matches!(error, TorError::ProxyUnreachable(_)),
+            "{:?}",
+            error
+        );
+    }
+    let error = strict.connect("192.0.2.1:80").err().unwrap();
+    assert!(
+        matches!(error, TorError::ProxyUnreachable(_)),
+        "{:?}",
+        error
+    );
+
+    // IP literals are only logged with a hostname-only policy
+    let (proxy, handle) = mock_proxy(|mut stream| {
+        let request = accept_raw_request(&mut stream, 4 + 4 + 2);
+        stream.write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0]).unwrap();
+        request
+    });
+    TorStreamBuilder::new()
+        .proxy(proxy)
+        .dns_policy(DnsPolicy::HostnameOnly)
+        .connect(("192.0.2.1", 80))
+        .unwrap();
+    assert_eq!(handle.join().unwrap(), [5, 1, 0, 1, 192, 0, 2, 1, 0, 80]);
+
+    // The default policy accepts everything
+    let error = TorStreamBuilder::new()
+        .proxy(unreachable)
+        .connect(resolved)
+        .err()
+        .unwrap();
+    assert!(
+        matches!(error, TorError::ProxyUnreachable(_)),
+        "{:?}",
+        error
+    );
+}
@@ -0,0 +1,169 @@
+#![cfg(feature = "tokio")]
+
+extern crate tor_stream;
+
+use tor_stream::tokio::TorStream;
+use tor_stream::{Isolation, TargetAddr, TorError, TorStreamBuilder};
+
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+#[tokio::test]
+async fn connect() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut buf = [0; 3];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf, [5, 1, 0]);
+        stream.write_all(&[5, 0]).await.unwrap();
+
+        let mut request = vec![0; 5 + 11 + 2];
+        stream.read_exact(&mut request).await.unwrap();
+        stream
+            .write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 80])
+            .await
+            .unwrap();
+
+        stream.write_all(b"pong").await.unwrap();
+        let mut buf = [0; 4];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+        request
+    });
+
+    let mut stream = TorStream::connect_with_address(proxy, ("example.com", 80))
+        .await
+        .unwrap();
+    assert_eq!(
+        stream.bind_addr(),
+        &TargetAddr::Ip("127.0.0.1:80".parse().unwrap())
+    );
+
+    let mut buf = [0; 4];
+    stream.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"pong");
+    stream.write_all(b"ping").await.unwrap();
+
+    let mut expected = vec![5, 1, 0, 3, 11];
+    expected.extend_from_slice(b"example.com");
+    expected.extend_from_slice(&[0, 80]);
+    assert_eq!(server.await.unwrap(), expected);
+}
+
+#[tokio::test]
+async fn isolation() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut buf = [0; 3];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf, [5, 1, 2]);
+        stream.write_all(&[5, 2]).await.unwrap();
+
+        let mut auth = vec![0; 1 + 1 + 4 + 1 + 6];
+        stream.read_exact(&mut auth).await.unwrap();
+        stream.write_all(&[1, 1]).await.unwrap();
+        auth
+    });
+
+    let error = TorStreamBuilder::new()
+        .proxy(proxy)
+        .isolation(Isolation::credentials("user", "secret"))
+        .connect_tokio("example.com:80")
+        .await
+        .err()
+        .unwrap();
+    assert!(
+        matches!(error, TorError::AuthenticationFailed),
+        "{:?}",
+        error
+    );
+    assert_eq!(server.await.unwrap(), b"\x01\x04user\x06secret");
+}
+
+#[tokio::test]
+async fn connect_timeout() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let _ = stream.read_to_end(&mut Vec::new()).await;
+    });
+
+    let error = TorStreamBuilder::new()
+        .proxy(proxy)
+        .connect_timeout(Duration::from_millis(200))
+        .connect_tokio("example.com:80")
+        .await
+        .err()
+        .unwrap();
+    assert!(matches!(error, TorError::TimedOut), "{:?}", error);
+    server.await.unwrap();
+}
+
+#[tokio::test]
+async fn discovery() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut buf = [0; 3];
+        stream.read_exact(&mut buf).await.unwrap();
+        stream.write_all(&[5, 0]).await.unwrap();
+        let mut request = vec![0; 5 + 11 + 2];
+        stream.read_exact(&mut request).await.unwrap();
+        stream
+            .write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0])
+            .await
+            .unwrap();
+    });
+
+    // The single-threaded runtime keeps serving the mock proxy during the discovery
+    std::env::set_var("TOR_PROXY", proxy.to_string());
+    TorStreamBuilder::new()
+        .connect_timeout(Duration::from_secs(10))
+        .connect_tokio("example.com:80")
+        .await
+        .unwrap();
+    server.await.unwrap();
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn connect_unix_socket() {
+    use tokio::net::UnixListener;
+
+    let dir = std::env::temp_dir().join(format!("tor-stream-tokio-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let socket = dir.join("socks");
+    let listener = UnixListener::bind(&socket).unwrap();
+    let server = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut buf = [0; 3];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf, [5, 1, 0]);
+        stream.write_all(&[5, 0]).await.unwrap();
+
+        let mut request = vec![0; 5 + 11 + 2];
+        stream.read_exact(&mut request).await.unwrap();
+        stream
+            .write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0])
+            .await
+            .unwrap();
+        let mut buf = [0; 4];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+    });
+
+    let mut stream = TorStream::connect_with_address(socket.clone(), ("example.com", 80))
+        .await
+        .unwrap();
+    assert!(stream.get_ref().as_unix().is_some());
+    stream.write_all(b"ping").await.unwrap();
+    server.await.unwrap();
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,194 @@
+extern crate tor_stream;
+
+use tor_stream::torrc::{Bridge, HiddenService, PortAddress, PortConfig, Torrc, TorrcError};
+
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+const TORRC: &str = "\
+## Configuration file for a typical Tor user
+
+SocksPort 192.168.0.1:9100 IsolateDestAddr # LAN proxy
+SocksPort unix:\"/run/tor/socks dir/socket\" WorldWritable
+ControlPort 9051
+Nickname \"with #hash\"
+HiddenServiceDir /var/lib/tor/hidden_service/
+HiddenServicePort 80 127.0.0.1:8080
+HiddenServicePort 22
+ClientTransportPlugin obfs4,meek_lite exec /usr/bin/obfs4proxy --enableLogging
+Bridge obfs4 192.0.2.3:443 0123456789ABCDEF0123456789ABCDEF01234567 cert=abc iat-mode=0
+Bridge 192.0.2.4:9001
+ExitNodes {de}, \\
+  {nl}
+";
+
+#[test]
+fn parse() {
+    let torrc: Torrc = TORRC.parse().unwrap();
+
+    assert_eq!(
+        torrc.socks_ports().unwrap(),
+        [
+            PortConfig {
+                address: PortAddress::Tcp("192.168.0.1:9100".parse().unwrap()),
+                flags: vec!["IsolateDestAddr".to_owned()],
+            },
+            PortConfig {
+                address: PortAddress::Unix(PathBuf::from("/run/tor/socks dir/socket")),
+                flags: vec!["WorldWritable".to_owned()],
+            },
+        ]
+    );
+    assert_eq!(
+        torrc.socks_addr().unwrap(),
+        Some("192.168.0.1:9100".parse().unwrap())
+    );
+    assert_eq!(
+        torrc.control_ports().unwrap()[0].address,
+        PortAddress::Tcp("127.0.0.1:9051".parse().unwrap())
+    );
+    assert_eq!(torrc.get("nickname"), Some("with #hash"));
+    assert_eq!(
+        torrc.hidden_services().unwrap(),
+        [HiddenService {
+            dir: PathBuf::from("/var/lib/tor/hidden_service/"),
+            ports: vec![(80, Some("127.0.0.1:8080".to_owned())), (22, None)],
+        }]
+    );
+
+    let plugins = torrc.client_transport_plugins().unwrap();
+    assert_eq!(plugins[0].transports, ["obfs4", "meek_lite"]);
+    assert_eq!(plugins[0].method, "exec");
+    assert_eq!(plugins[0].target, "/usr/bin/obfs4proxy --enableLogging");
+
+    let bridges = torrc.bridges().unwrap();
+    assert_eq!(
+        bridges[0],
+        Bridge {
+            transport: Some("obfs4".to_owned()),
+            address: "192.0.2.3:443".to_owned(),
+            fingerprint: Some("0123456789ABCDEF0123456789ABCDEF01234567".to_owned()),
+            args: vec!["cert=abc".to_owned(), "iat-mode=0".to_owned()],
+        }
+    );
+    assert_eq!(bridges[1].transport, None);
+    assert_eq!(bridges[1].address, "192.0.2.4:9001");
+    assert_eq!(
+        bridges[0].to_string(),
+        "obfs4 192.0.2.3:443 0123456789ABCDEF0123456789ABCDEF01234567 cert=abc iat-mode=0"
+    );
+
+    assert_eq!(torrc.exit_nodes(), ["{de}", "{nl}"]);
+}
+
+#[test]
+fn modify_and_serialize() {
+    let mut torrc: Torrc = TORRC.parse().unwrap();
+    torrc.set("SocksPort", "9150");
+    torrc.set_exit_nodes(&["{ch}"]);
+    torrc.remove("bridge");
+    torrc.add("DataDirectory", " leading space");
+
+    let serialized = torrc.to_string();
+    assert!(serialized.starts_with(
+        "## Configuration file for a typical Tor user\n\nSocksPort 9150\nControlPort 9051\n"
+    ));
+    assert!(serialized.contains("Nickname \"with #hash\"\n"));
+    assert!(!serialized.contains("Bridge"));
+    assert!(serialized.ends_with("ExitNodes {ch}\nDataDirectory \" leading space\"\n"));
+
+    let reparsed: Torrc = serialized.parse().unwrap();
+    assert_eq!(reparsed.options(), torrc.options());
+    assert_eq!(
+        reparsed.socks_addr().unwrap(),
+        Some("127.0.0.1:9150".parse().unwrap())
+    );
+
+    // Comments after values are kept, and replaced along with the value
+    let mut torrc: Torrc =
+        "ControlPort 9051 # local only\nNickname \"a b\"  # quoted\nLog\t# none\n"
+            .parse()
+            .unwrap();
+    assert_eq!(torrc.get("Nickname"), Some("a b"));
+    assert_eq!(torrc.get("Log"), Some(""));
+    assert_eq!(
+        torrc.to_string(),
+        "ControlPort 9051 # local only\nNickname a b # quoted\nLog # none\n"
+    );
+    torrc.set("ControlPort", "9151");
+    assert!(torrc.to_string().starts_with("ControlPort 9151\n"));
+}
+
+#[test]
+fn defaults_and_errors() {
+    let empty = Torrc::new();
+    assert_eq!(
+        empty.socks_addr().unwrap(),
+        Some("127.0.0.1:9050".parse::<SocketAddr>().unwrap())
+    );
+    assert!(empty.exit_nodes().is_empty());
+
+    let disabled: Torrc = "SocksPort 0\n".parse().unwrap();
+    assert_eq!(disabled.socks_addr().unwrap(), None);
+
+    match "Nickname \"unterminated\n".parse::<Torrc>() {
+        Err(TorrcError::Syntax { line: 1, .. }) => {}
+        result => panic!("unexpected result {:?}", result),
+    }
+    let invalid: Torrc = "SocksPort example.com:9050\n".parse().unwrap();
+    match invalid.socks_ports() {
+        Err(TorrcError::InvalidValue { key, .. }) => assert_eq!(key, "SocksPort"),
+        result => panic!("unexpected result {:?}", result),
+    }
+}
+
+#[test]
+fn include() {
+    let dir = std::env::temp_dir().join(format!("tor-stream-torrc-{}", std::process::id()));
+    let conf_dir = dir.join("torrc.d");
+    fs::create_dir_all(&conf_dir).unwrap();
+    fs::write(
+        dir.join("torrc"),
+        "SocksPort 9050\n%include torrc.d\nExitNodes {de}\n",
+    )
+    .unwrap();
+    fs::write(conf_dir.join("50-socks"), "SocksPort 9150\n").unwrap();
+    fs::write(conf_dir.join("10-exit"), "ExitNodes {nl}\n").unwrap();
+    fs::write(conf_dir.join(".hidden"), "SocksPort 1\n").unwrap();
+
+    let torrc = Torrc::load(dir.join("torrc")).unwrap();
+    assert_eq!(
+        torrc.options(),
+        [
+            ("SocksPort", "9050"),
+            ("ExitNodes", "{nl}"),
+            ("SocksPort", "9150"),
+            ("ExitNodes", "{de}"),
+        ]
+    );
+    assert_eq!(torrc.get("ExitNodes"), Some("{de}"));
+    // Includes are written back as directives
+    assert_eq!(
+        torrc.to_string(),
+        "SocksPort 9050\n%include torrc.d\nExitNodes {de}\n"
+    );
+
+    // Wildcards in the last component include the matching files in alphabetical order
+    fs::write(conf_dir.join("20-nick.conf"), "Nickname second\n").unwrap();
+    fs::write(conf_dir.join("15-nick.conf"), "Nickname first\n").unwrap();
+    fs::write(conf_dir.join(".nick.conf"), "Nickname hidden\n").unwrap();
+    fs::write(dir.join("glob"), "%include torrc.d/?0-*.conf\n").unwrap();
+    let torrc = Torrc::load(dir.join("glob")).unwrap();
+    assert_eq!(torrc.get_all("Nickname"), ["second"]);
+    fs::write(dir.join("glob"), "%include torrc.d/*.conf\n").unwrap();
+    let torrc = Torrc::load(dir.join("glob")).unwrap();
+    assert_eq!(torrc.get_all("Nickname"), ["first", "second"]);
+
+    fs::write(dir.join("loop"), "%include loop\n").unwrap();
+    match Torrc::load(dir.join("loop")) {
+        Err(TorrcError::IncludeDepth) => {}
+        result => panic!("unexpected result {:?}", result),
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
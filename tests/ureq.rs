@@ -0,0 +1,94 @@
+#![cfg(feature = "ureq")]
+
+extern crate tor_stream;
+
+mod common;
+
+use common::{accept_connect, accept_request, mock_http_proxy, mock_proxy, read_head, reply};
+use tor_stream::{Isolation, TorError, TorStreamBuilder};
+
+use std::io::Write;
+use std::thread;
+use std::time::Duration;
+
+const RESPONSE: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello";
+
+#[test]
+fn agent() {
+    let (proxy, handle) = mock_http_proxy(vec![RESPONSE; 2]);
+    let config = ureq::Agent::config_builder()
+        .max_idle_connections(0)
+        .build();
+    let agent = tor_stream::ureq::agent_with_config(
+        TorStreamBuilder::new()
+            .proxy(proxy)
+            .isolation(Isolation::Random),
+        config,
+    );
+
+    for _ in 0..2 {
+        let body = agent
+            .get("http://www.example.com:8080/")
+            .call()
+            .unwrap()
+            .body_mut()
+            .read_to_string()
+            .unwrap();
+        assert_eq!(body, "hello");
+    }
+
+    // The hostname is passed to Tor, and every connection has its own circuit
+    let connections = handle.join().unwrap();
+    assert_eq!(connections[0].0.target, "www.example.com:8080");
+    assert!(connections[0].0.credentials.is_some());
+    assert_ne!(connections[0].0.credentials, connections[1].0.credentials);
+}
+
+#[test]
+fn timeouts() {
+    let (proxy, handle) = mock_proxy(|mut stream| {
+        accept_connect(&mut stream);
+        read_head(&stream);
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n")
+            .unwrap();
+        thread::sleep(Duration::from_millis(600));
+        stream.write_all(b"hello").unwrap();
+    });
+
+    // The response timeout must not apply to the body, which has no timeout
+    let config = ureq::Agent::config_builder()
+        .timeout_recv_response(Some(Duration::from_millis(300)))
+        .build();
+    let agent = tor_stream::ureq::agent_with_config(TorStreamBuilder::new().proxy(proxy), config);
+    let body = agent
+        .get("http://www.example.com/")
+        .call()
+        .unwrap()
+        .body_mut()
+        .read_to_string()
+        .unwrap();
+    assert_eq!(body, "hello");
+    handle.join().unwrap();
+}
+
+#[test]
+fn extended_error() {
+    let (proxy, handle) = mock_proxy(|mut stream| {
+        accept_request(&mut stream);
+        // Onion service descriptor can not be found
+        reply(&mut stream, 0xF0);
+    });
+
+    let agent = tor_stream::ureq::agent(TorStreamBuilder::new().proxy(proxy));
+    let error = match agent.get("http://example.onion/").call() {
+        Err(ureq::Error::Io(e)) => e,
+        result => panic!("unexpected result {:?}", result),
+    };
+    let error = error
+        .get_ref()
+        .and_then(|e| e.downcast_ref::<TorError>())
+        .unwrap();
+    assert!(error.is_onion_service_error(), "{:?}", error);
+    handle.join().unwrap();
+}